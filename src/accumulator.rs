@@ -0,0 +1,138 @@
+//! Stepping an attractor straight into a density grid, without keeping its history.
+//!
+//! Every position in an attractor's history is a `Vec<f64>` of its own, so tens of millions of
+//! positions take gigabytes of memory, and getting the densities means binning the whole history
+//! again. An `Accumulator` instead keeps a histogram which is only ever added to: each call to
+//! `step` bins the new positions as they're calculated (with `Attractor::step_with`) and then
+//! forgets them. Memory use doesn't depend on the number of iterations, and each step only costs
+//! the positions added by that step, so the live window can refine the image progressively.
+//!
+//! The bounds of the histogram (and the fit of the camera) are fixed when the accumulator is
+//! created, from whatever history the attractor has at the time. Positions which later land
+//! outside of those bounds are dropped.
+use crate::attractors::{Attractor, Histogram};
+use crate::camera::Camera;
+use crate::sampling::Sampling;
+
+/// A histogram which positions are added to as they're calculated. See the module docs.
+#[derive(Debug, Clone)]
+pub struct Accumulator {
+    pub histogram: Histogram,
+    /// The number of positions which have been added, including any which were out of bounds.
+    pub num_positions: usize,
+    /// The fitted camera which positions are projected through, or `None` to use the positions
+    /// as they are (when the camera hasn't been moved).
+    projection: Option<Camera>,
+    sampling: Sampling,
+    /// The parameters of the attractor and the camera this was created with, so that it can tell
+    /// when it's out of date.
+    params: Vec<f64>,
+    camera: Camera,
+}
+
+impl Accumulator {
+    /// Create a `width` by `height` accumulator for `attractor` as seen through `camera`, with
+    /// every position already in the attractor's history added to it.
+    pub fn new<A: Attractor>(
+        attractor: &A,
+        camera: &Camera,
+        width: usize,
+        height: usize,
+        perc_border: f64,
+        sampling: &Sampling,
+    ) -> Self {
+        let (bounds, projection) = if camera.is_default() {
+            (camera.bounds_of(attractor), None)
+        } else {
+            let mut fitted = camera.clone();
+            fitted.fit(attractor.history());
+            (fitted.screen_bounds(width, height), Some(fitted))
+        };
+        let mut accumulator = Accumulator {
+            histogram: Histogram::supersampled(width, height, bounds, perc_border, sampling),
+            num_positions: 0,
+            projection,
+            sampling: *sampling,
+            params: attractor.params(),
+            camera: camera.clone(),
+        };
+        for pos in attractor.history() {
+            accumulator.add(pos);
+        }
+        accumulator
+    }
+
+    /// True if this accumulator was created for an attractor with the same parameters as
+    /// `attractor`, seen through `camera`. Otherwise it should be replaced with a new one.
+    pub fn is_for<A: Attractor>(&self, attractor: &A, camera: &Camera) -> bool {
+        self.params == attractor.params() && &self.camera == camera
+    }
+
+    /// Add one position to the histogram.
+    fn add(&mut self, pos: &[f64]) {
+        match &self.projection {
+            Some(camera) => self.histogram.add(&camera.project(pos)),
+            None => self.histogram.add(pos),
+        }
+        self.num_positions += 1;
+    }
+
+    /// Step `attractor` `num_steps` times (like `Attractor::step`), adding each new position to
+    /// the histogram. The attractor's history is left with only its latest position.
+    pub fn step<A: Attractor>(&mut self, attractor: &mut A, num_steps: usize) {
+        attractor.step_with(num_steps, |pos| self.add(pos));
+    }
+
+    /// The density of every pixel, normalised to [0.0, 1.0] like
+    /// `Attractor::get_densities_with_border`.
+    pub fn densities(&self) -> Vec<f64> {
+        self.histogram
+            .downsampled(self.sampling.supersample.max(1), self.sampling.filter)
+            .normalised()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attractors::{CliffordAttractor, DeJongAttractor, SprottAttractor};
+    use crate::flows::LorenzAttractor;
+
+    /// Accumulating some steps should give the same densities as keeping the whole history.
+    fn check_matches_history<A: Attractor>(params: Vec<f64>) {
+        let mut kept = A::new(params.clone()).unwrap();
+        kept.step(1_000);
+        let mut streamed = A::new(params).unwrap();
+        streamed.step(1_000);
+        let mut accumulator = Accumulator::new(
+            &streamed,
+            &Camera::default(),
+            40,
+            30,
+            0.05,
+            &Sampling::default(),
+        );
+        for _ in 0..3 {
+            kept.step(5_000);
+            accumulator.step(&mut streamed, 5_000);
+        }
+        assert_eq!(accumulator.num_positions, kept.history().len());
+        assert_eq!(streamed.history().len(), 1);
+        assert_eq!(streamed.history().last(), kept.history().last());
+        // The bounds of some attractors grow as they're stepped, but the accumulator's don't
+        let bounds = accumulator.histogram.bounds;
+        assert_eq!(
+            accumulator.densities(),
+            kept.get_densities_within(bounds, 40, 30, 0.05, &Sampling::default())
+        );
+    }
+
+    #[test]
+    fn accumulating_matches_keeping_the_history() {
+        check_matches_history::<CliffordAttractor>(vec![1.7, 1.7, 0.6, 1.2]);
+        check_matches_history::<DeJongAttractor>(vec![-2.0, -2.0, -1.2, 2.0]);
+        let sprott = SprottAttractor::from_code("FIRCDERRPVLD").unwrap();
+        check_matches_history::<SprottAttractor>(sprott.params());
+        check_matches_history::<LorenzAttractor>(vec![10.0, 28.0, 8.0 / 3.0]);
+    }
+}
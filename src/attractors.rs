@@ -15,8 +15,84 @@
 /// - [Yu-Wang Attractors](http://paulbourke.net/fractals/yuwang/)
 use std::{
     fmt::{self, Display},
-    io::Write,
+    fs::File,
+    io::{self, BufRead, BufReader, Write},
 };
+
+use crate::diagnostics::describe_lyapunov;
+use crate::framing::{BoundsFit, Framed};
+use crate::sampling::{downsample, Filter, Sampling, Splat};
+
+/// Everything that can go wrong when creating, changing, or saving an attractor.
+#[derive(Debug)]
+pub enum AttractorError {
+    /// The attractor named `name` needs `expected` parameters, but was given `given` parameters.
+    WrongNumParameters {
+        name: &'static str,
+        expected: u8,
+        given: usize,
+    },
+    /// Reading from or writing to a file failed.
+    Io(io::Error),
+    /// A binary trajectory file couldn't be understood.
+    InvalidBinaryFile(String),
+    /// A Sprott letter code couldn't be understood.
+    InvalidCode(String),
+    /// A file couldn't be understood. `line` is the 1-indexed line number where things went wrong.
+    InvalidFile { line: usize, reason: String },
+}
+
+impl Display for AttractorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AttractorError::WrongNumParameters {
+                name,
+                expected,
+                given,
+            } => write!(
+                f,
+                "{} attractors require {} parameters but {} were given",
+                name, expected, given
+            ),
+            AttractorError::Io(e) => write!(f, "I/O error: {}", e),
+            AttractorError::InvalidFile { line, reason } => {
+                write!(f, "Invalid file at line {}: {}", line, reason)
+            }
+            AttractorError::InvalidBinaryFile(reason) => {
+                write!(f, "Invalid binary trajectory file: {}", reason)
+            }
+            AttractorError::InvalidCode(reason) => write!(f, "Invalid Sprott code: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for AttractorError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AttractorError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for AttractorError {
+    fn from(e: io::Error) -> Self {
+        AttractorError::Io(e)
+    }
+}
+
+/// Return an error if `given` isn't the number of parameters the attractor `A` requires.
+pub(crate) fn check_num_params<A: Attractor>(given: usize) -> Result<(), AttractorError> {
+    if given != A::NUM_PARAMETERS as usize {
+        return Err(AttractorError::WrongNumParameters {
+            name: A::NAME,
+            expected: A::NUM_PARAMETERS,
+            given,
+        });
+    }
+    Ok(())
+}
+
 pub trait Attractor {
     /// A name for the attractor matching [a-zA-Z]+, used when saving a sequence of points to file
     const NAME: &'static str;
@@ -26,30 +102,169 @@ pub trait Attractor {
     const NUM_PARAMETERS: u8;
 
     /// Create a new Attractor from the vector of parameters `params`.
-    fn new(params: Vec<f64>) -> Self;
+    ///
+    /// Returns an error if `params` doesn't have exactly `NUM_PARAMETERS` elements.
+    fn new(params: Vec<f64>) -> Result<Self, AttractorError>
+    where
+        Self: Sized;
 
     /// Given an xy position, mutate x and y to be the next position based on the Attractor's
-    /// formula. Do this for `num_steps` steps, saving each step to `history`.
+    /// formula. Do this for `num_steps` steps, saving each step to `history`, so that exactly
+    /// `num_steps` new positions are added.
     fn step(&mut self, num_steps: usize);
 
+    /// Step exactly like `step`, but give each new position to `visit` instead of saving it, so
+    /// that memory use doesn't grow with the number of steps. Afterwards the history only has the
+    /// latest position, which is where the next step continues from.
+    ///
+    /// The default steps as usual and then forgets the new history, which still allocates every
+    /// position. Attractors which can cheaply calculate one position at a time should override it.
+    fn step_with(&mut self, num_steps: usize, mut visit: impl FnMut(&[f64]))
+    where
+        Self: Sized,
+    {
+        let before = self.history().len();
+        self.step(num_steps);
+        let history = self.history();
+        for pos in &history[before.min(history.len())..] {
+            visit(pos);
+        }
+        if let Some(last) = history.last().cloned() {
+            self.set_history(vec![last]);
+        }
+    }
+
     /// Change the parameters of the Attractor.
     ///
     /// If an element in params is `None`, then that parameter will remain how it was. If an
     /// element in `params` is `Some<f64>`, then that value will be unpacked into the corresponding
     /// parameter.
-    fn set_params(&mut self, params: Vec<Option<f64>>);
+    ///
+    /// Returns an error (and leaves the attractor unchanged) if `params` doesn't have exactly
+    /// `NUM_PARAMETERS` elements.
+    fn set_params(&mut self, params: Vec<Option<f64>>) -> Result<(), AttractorError>;
 
-    fn get_densities(&mut self, width: usize, height: usize) -> Vec<f64>;
+    /// The current parameters of the Attractor, in the same order as they're given to `new`.
+    fn params(&self) -> Vec<f64>;
+
+    /// The formula for each new position with the parameters filled in, one line per
+    /// coordinate, used to caption prints. The default lists the parameters six to a line, for
+    /// attractors without a short formula.
+    fn equations(&self) -> Vec<String> {
+        self.params()
+            .chunks(6)
+            .map(|chunk| {
+                let params: Vec<String> = chunk.iter().map(|p| format!("{:+.4}", p)).collect();
+                params.join(", ")
+            })
+            .collect()
+    }
+
+    /// The name of each parameter, in the same order as `params`. The default names them `a`, `b`,
+    /// `c`, ... (or `p1`, `p2`, ... if there are more than 26 of them).
+    fn param_names(&self) -> Vec<String> {
+        let num_params = Self::NUM_PARAMETERS as usize;
+        (0..num_params)
+            .map(|i| match num_params {
+                0..=26 => ((b'a' + i as u8) as char).to_string(),
+                _ => format!("p{}", i + 1),
+            })
+            .collect()
+    }
+
+    /// A short code for the parameters which can be shared instead of the parameters themselves,
+    /// like the letter codes of Sprott attractors. `None` if the attractor doesn't have codes, or
+    /// if its parameters can't be written as one.
+    fn code(&self) -> Option<String> {
+        None
+    }
 
+    /// Every position visited by the attractor so far, oldest first.
+    fn history(&self) -> &Vec<Vec<f64>>;
+
+    /// Replace every previously visited position with `history`, and continue stepping from the
+    /// last position in `history`.
+    fn set_history(&mut self, history: Vec<Vec<f64>>);
+
+    /// The region of space which will be mapped onto the pixels of a density grid.
+    fn bounds(&self) -> Bounds;
+
+    /// Given a certain `width` and `height` in pixels, return the density of every pixel with no
+    /// border. See `get_densities_with_border` for details.
+    fn get_densities(&mut self, width: usize, height: usize) -> Vec<f64> {
+        self.get_densities_with_border(width, height, 0.0)
+    }
+
+    /// Given a certain `width` and `height` in pixels, return the density of the pixel at `index`,
+    /// where `index` is equal to:
+    /// ```
+    ///     index = x_position + width * y_position
+    /// ```
+    ///
+    /// `perc_border` is the fraction of the width (and height) left empty on each side of the
+    /// image. The returned density has been normalised to be in the range [0.0, 1.0] where 1.0
+    /// indicates that pixel was the most frequently landed on, and 0.0 indicates that pixel was
+    /// never landed on.
+    ///
+    /// Attractors shouldn't need to override this, all the binning is done by `Histogram`.
     fn get_densities_with_border(
         &mut self,
         width: usize,
         height: usize,
         perc_border: f64,
-    ) -> Vec<f64>;
+    ) -> Vec<f64> {
+        self.get_densities_sampled(width, height, perc_border, &Sampling::default())
+    }
+
+    /// The same as `get_densities_with_border`, but anti-aliased with `sampling`.
+    fn get_densities_sampled(
+        &mut self,
+        width: usize,
+        height: usize,
+        perc_border: f64,
+        sampling: &Sampling,
+    ) -> Vec<f64> {
+        self.get_densities_within(self.bounds(), width, height, perc_border, sampling)
+    }
+
+    /// The same as `get_densities_sampled`, but with `bounds` mapped onto the image instead of
+    /// the attractor's own bounds. Positions outside of `bounds` aren't drawn.
+    fn get_densities_within(
+        &mut self,
+        bounds: Bounds,
+        width: usize,
+        height: usize,
+        perc_border: f64,
+        sampling: &Sampling,
+    ) -> Vec<f64> {
+        let mut histogram = Histogram::supersampled(width, height, bounds, perc_border, sampling);
+        histogram.add_all(self.history());
+        histogram
+            .downsampled(sampling.supersample.max(1), sampling.filter)
+            .normalised()
+    }
 
     fn reset(&mut self);
 
+    /// This attractor, drawn within bounds fitted to a sample of its positions (as described by
+    /// `fit`) instead of its own `bounds`. See `Framed` for details.
+    fn framed(self, fit: BoundsFit) -> Framed<Self>
+    where
+        Self: Sized,
+    {
+        Framed::new(self, fit)
+    }
+
+    /// A new attractor with the same parameters (and any other settings) as this one, but with
+    /// none of its history. Attractors with settings other than their parameters should override
+    /// this so that those settings are copied too.
+    fn empty_copy(&self) -> Self
+    where
+        Self: Sized,
+    {
+        Self::new(self.params()).expect("An attractor's own parameters are always valid")
+    }
+
     /// Save the attractor to a file.
     ///
     /// The attractor's name, it's parameters, and every coordinate taken so far is saved to the
@@ -59,6 +274,7 @@ pub trait Attractor {
     /// The format of the file is in plain text like:
     /// ```
     /// #<NAME>,<NUM_PARAMETERS>,<NUM_DIMENSIONS>
+    /// #code=<CODE>
     /// #<NAME_OF_PARAMETER_1>=<VALUE_OF_PARAMETER_1>
     /// ...
     /// #<NAME_OF_PARAMETER_n>=<VALUE_OF_PARAMETER_n>
@@ -68,9 +284,384 @@ pub trait Attractor {
     /// 3:<X_POSITION>,<Y_POSITION>,<Z_POSITION>
     /// ...
     /// ```
-    /// Where the z-position is only included for 3-dimensional attractors. These files are
-    /// designed to be human-readable for debug purposes.
-    fn to_file(&mut self, directory: String);
+    /// Where the z-position is only included for 3-dimensional attractors, and the code is only
+    /// included for attractors which have one (see `code`). These files are designed to be
+    /// human-readable for debug purposes.
+    ///
+    /// Returns an error if the file couldn't be created or written to.
+    fn to_file(&mut self, directory: String) -> Result<(), AttractorError>;
+
+    /// Load an attractor (both it's parameters and it's history) from a file written by `to_file`.
+    ///
+    /// Returns an error if the file can't be read, or if the preamble doesn't match this
+    /// attractor's `NAME`, `NUM_PARAMETERS`, and `DIMENSIONALITY`.
+    fn from_file(filename: String) -> Result<Self, AttractorError>
+    where
+        Self: Sized,
+    {
+        let reader = BufReader::new(File::open(filename)?);
+        let (params, history) = read_trajectory(
+            reader,
+            Self::NAME,
+            Self::NUM_PARAMETERS,
+            Self::DIMENSIONALITY,
+        )?;
+        let mut attractor = Self::new(params)?;
+        attractor.set_history(history);
+        Ok(attractor)
+    }
+}
+
+/// Parse the text format written by `Attractor::to_file`, returning the parameters and the
+/// history. The preamble must describe an attractor named `name` with `num_params` parameters
+/// living in `dimensionality` dimensions, otherwise an error is returned.
+fn read_trajectory<R: BufRead>(
+    reader: R,
+    name: &'static str,
+    num_params: u8,
+    dimensionality: u8,
+) -> Result<(Vec<f64>, Vec<Vec<f64>>), AttractorError> {
+    let invalid = |line: usize, reason: String| AttractorError::InvalidFile { line, reason };
+    let mut params = vec![];
+    let mut history = vec![];
+    let mut seen_header = false;
+
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let lineno = i + 1;
+        if let Some(preamble) = line.strip_prefix('#') {
+            if !history.is_empty() {
+                return Err(invalid(lineno, "preamble after positions".to_string()));
+            }
+            if !seen_header {
+                // The first line looks like `#<NAME>,<NUM_PARAMETERS>,<NUM_DIMENSIONS>`
+                let expected = format!("{},{},{}", name, num_params, dimensionality);
+                if preamble != expected {
+                    return Err(invalid(
+                        lineno,
+                        format!("expected header `#{}` but found `{}`", expected, line),
+                    ));
+                }
+                seen_header = true;
+            } else if preamble.starts_with("code=") {
+                // The code is only there to be read by people, since it comes from the parameters
+                continue;
+            } else {
+                // Every other preamble line looks like `#<NAME_OF_PARAMETER>=<VALUE>`
+                let value = preamble
+                    .split_once('=')
+                    .and_then(|(_, v)| v.parse::<f64>().ok())
+                    .ok_or_else(|| invalid(lineno, format!("bad parameter `{}`", line)))?;
+                params.push(value);
+            }
+            continue;
+        }
+        if !seen_header {
+            return Err(invalid(lineno, "missing header".to_string()));
+        }
+        // Positions look like `<INDEX>:<X_POSITION>,<Y_POSITION>,...`
+        let (index, coords) = line
+            .split_once(':')
+            .ok_or_else(|| invalid(lineno, format!("bad position `{}`", line)))?;
+        if index.parse::<usize>().ok() != Some(history.len()) {
+            return Err(invalid(
+                lineno,
+                format!("expected position #{} but found `{}`", history.len(), index),
+            ));
+        }
+        let pos = coords
+            .split(',')
+            .map(|c| c.parse::<f64>())
+            .collect::<Result<Vec<f64>, _>>()
+            .map_err(|e| invalid(lineno, format!("bad coordinate: {}", e)))?;
+        if pos.len() != dimensionality as usize {
+            return Err(invalid(
+                lineno,
+                format!(
+                    "expected {} coordinates but found {}",
+                    dimensionality,
+                    pos.len()
+                ),
+            ));
+        }
+        history.push(pos);
+    }
+
+    if !seen_header {
+        return Err(invalid(1, "the file is empty".to_string()));
+    }
+    if params.len() != num_params as usize {
+        return Err(AttractorError::WrongNumParameters {
+            name,
+            expected: num_params,
+            given: params.len(),
+        });
+    }
+    Ok((params, history))
+}
+
+/// The `NAME` of the attractor whose trajectory was saved to `filename` by `Attractor::to_file`,
+/// read from the first line of the preamble.
+pub fn saved_attractor_name(filename: &str) -> Result<String, AttractorError> {
+    let mut first_line = String::new();
+    BufReader::new(File::open(filename)?).read_line(&mut first_line)?;
+    first_line
+        .strip_prefix('#')
+        .and_then(|preamble| preamble.split(',').next())
+        .filter(|name| !name.trim().is_empty())
+        .map(|name| name.to_string())
+        .ok_or_else(|| AttractorError::InvalidFile {
+            line: 1,
+            reason: "missing header".to_string(),
+        })
+}
+
+/// Write a trajectory in the text format described by `Attractor::to_file`, for attractors with
+/// too many parameters to write out by hand. Each parameter is named `param_names[i]`, and the
+/// `Attractor::code` is written before them if there is one.
+pub(crate) fn write_trajectory(
+    filename: String,
+    header: (&str, u8, u8),
+    code: Option<String>,
+    param_names: &[String],
+    params: &[f64],
+    history: &[Vec<f64>],
+) -> Result<(), AttractorError> {
+    let mut file = io::BufWriter::new(File::create(filename)?);
+    let (name, num_params, dimensionality) = header;
+    writeln!(file, "#{},{},{}", name, num_params, dimensionality)?;
+    if let Some(code) = code {
+        writeln!(file, "#code={}", code)?;
+    }
+    for (param_name, param) in param_names.iter().zip(params.iter()) {
+        writeln!(file, "#{}={}", param_name, param)?;
+    }
+    for (i, item) in history.iter().enumerate() {
+        let coords: Vec<String> = item.iter().map(|c| c.to_string()).collect();
+        writeln!(file, "{}:{}", i, coords.join(","))?;
+    }
+    file.flush()?;
+    Ok(())
+}
+
+/// The rectangular region of 2D space in which an attractor lives. Any position outside of these
+/// bounds won't be drawn.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounds {
+    pub xmin: f64,
+    pub xmax: f64,
+    pub ymin: f64,
+    pub ymax: f64,
+}
+
+impl Bounds {
+    /// These bounds shrunk by `factor` around `centre`, so that `centre` stays in the same place
+    /// on the screen. Factors larger than 1 zoom in, factors between 0 and 1 zoom out.
+    pub fn zoomed(&self, centre: [f64; 2], factor: f64) -> Bounds {
+        Bounds {
+            xmin: centre[0] - (centre[0] - self.xmin) / factor,
+            xmax: centre[0] + (self.xmax - centre[0]) / factor,
+            ymin: centre[1] - (centre[1] - self.ymin) / factor,
+            ymax: centre[1] + (self.ymax - centre[1]) / factor,
+        }
+    }
+
+    /// These bounds moved by `dx` and `dy`.
+    pub fn moved(&self, dx: f64, dy: f64) -> Bounds {
+        Bounds {
+            xmin: self.xmin + dx,
+            xmax: self.xmax + dx,
+            ymin: self.ymin + dy,
+            ymax: self.ymax + dy,
+        }
+    }
+}
+
+/// A 2D histogram counting how many times an attractor landed in each pixel of a `width` by
+/// `height` grid.
+///
+/// This is the one place where positions get converted into pixels, so that every `Attractor`
+/// treats bounds, borders, and resolution identically. Positions are mapped onto pixels as:
+/// ```
+/// x_pixel = floor(width  * ((x - xmin) / (xmax - xmin) * (1 - 2 * perc_border) + perc_border))
+/// y_pixel = floor(height * ((y - ymin) / (ymax - ymin) * (1 - 2 * perc_border) + perc_border))
+/// ```
+/// and positions which fall outside of `bounds` are ignored. With `Splat::Bilinear`, each
+/// position is instead shared between the four pixels whose centres are nearest to it.
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    pub width: usize,
+    pub height: usize,
+    /// The region of space which gets mapped onto the histogram.
+    pub bounds: Bounds,
+    /// The fraction of the width (and height) left empty on each side of the histogram.
+    pub perc_border: f64,
+    /// The number of times each pixel was landed on, indexed as `x + y * width`.
+    pub counts: Vec<f64>,
+    /// The largest value in `counts`.
+    pub max: f64,
+    pub splat: Splat,
+}
+
+impl Histogram {
+    /// Create a new, empty, histogram.
+    pub fn new(width: usize, height: usize, bounds: Bounds, perc_border: f64) -> Self {
+        Histogram {
+            width,
+            height,
+            bounds,
+            perc_border,
+            counts: vec![0.0; width * height],
+            max: 0.0,
+            splat: Splat::Nearest,
+        }
+    }
+
+    /// Create a histogram which only maps positions onto pixels, without any memory for the
+    /// counts. This is for when the counts are kept somewhere else, like in the tiles of
+    /// `TiledRender`, since a full size histogram of a large image doesn't fit in memory.
+    pub fn without_counts(
+        width: usize,
+        height: usize,
+        bounds: Bounds,
+        perc_border: f64,
+        splat: Splat,
+    ) -> Self {
+        Histogram {
+            width,
+            height,
+            bounds,
+            perc_border,
+            counts: vec![],
+            max: 0.0,
+            splat,
+        }
+    }
+
+    /// Create a new, empty, histogram which is `sampling.supersample` times larger than `width`
+    /// by `height` in each direction, and splats positions with `sampling.splat`. Use
+    /// `downsampled` to shrink it back to `width` by `height` once every position has been added.
+    pub fn supersampled(
+        width: usize,
+        height: usize,
+        bounds: Bounds,
+        perc_border: f64,
+        sampling: &Sampling,
+    ) -> Self {
+        let factor = sampling.supersample.max(1);
+        Histogram {
+            splat: sampling.splat,
+            ..Histogram::new(width * factor, height * factor, bounds, perc_border)
+        }
+    }
+
+    /// Shrink the histogram by `factor` in each direction, combining pixels with `filter`.
+    pub fn downsampled(&self, factor: usize, filter: Filter) -> Histogram {
+        let counts = downsample(&self.counts, self.width, self.height, factor, filter);
+        Histogram {
+            width: self.width / factor,
+            height: self.height / factor,
+            bounds: self.bounds,
+            perc_border: self.perc_border,
+            max: counts.iter().copied().fold(0.0, f64::max),
+            counts,
+            splat: self.splat,
+        }
+    }
+
+    /// Convert a position into pixel coordinates, where the pixel (x, y) covers [x, x + 1) by
+    /// [y, y + 1), or `None` if the position is outside of `bounds` (or isn't a number at all).
+    /// Nothing lands on a histogram with no pixels, since there's no pixel to land on.
+    fn pixel_coords(&self, pos: &[f64]) -> Option<(f64, f64)> {
+        if self.width == 0 || self.height == 0 {
+            return None;
+        }
+        let x_frac = (pos[0] - self.bounds.xmin) / (self.bounds.xmax - self.bounds.xmin);
+        let y_frac = (pos[1] - self.bounds.ymin) / (self.bounds.ymax - self.bounds.ymin);
+        // Written so that NaNs also get rejected
+        if !((0.0..=1.0).contains(&x_frac) && (0.0..=1.0).contains(&y_frac)) {
+            return None;
+        }
+        let scale = 1.0 - 2.0 * self.perc_border;
+        Some((
+            self.width as f64 * (x_frac * scale + self.perc_border),
+            self.height as f64 * (y_frac * scale + self.perc_border),
+        ))
+    }
+
+    /// Convert pixel coordinates (like those of the mouse) back into a position, the inverse of
+    /// the mapping described above. Pixel (x, y) covers [x, x + 1) by [y, y + 1).
+    pub fn position_of(&self, x: f64, y: f64) -> [f64; 2] {
+        let scale = 1.0 - 2.0 * self.perc_border;
+        let x_frac = (x / self.width as f64 - self.perc_border) / scale;
+        let y_frac = (y / self.height as f64 - self.perc_border) / scale;
+        [
+            self.bounds.xmin + x_frac * (self.bounds.xmax - self.bounds.xmin),
+            self.bounds.ymin + y_frac * (self.bounds.ymax - self.bounds.ymin),
+        ]
+    }
+
+    /// Convert a position into the index of the pixel it lands on, or `None` if the position is
+    /// outside of `bounds` (or isn't a number at all).
+    pub fn index_of(&self, pos: &[f64]) -> Option<usize> {
+        let (x, y) = self.pixel_coords(pos)?;
+        let (x, y) = (x.floor() as usize, y.floor() as usize);
+        // A position exactly on xmax or ymax would otherwise land one pixel past the edge
+        Some(x.min(self.width - 1) + y.min(self.height - 1) * self.width)
+    }
+
+    /// The pixels which a position gets added to, and how much of it goes to each one. Unused
+    /// entries have a weight of 0.0.
+    pub fn splat_weights(&self, pos: &[f64]) -> Option<[(usize, f64); 4]> {
+        match self.splat {
+            Splat::Nearest => {
+                let i = self.index_of(pos)?;
+                Some([(i, 1.0), (i, 0.0), (i, 0.0), (i, 0.0)])
+            }
+            Splat::Bilinear => {
+                let (x, y) = self.pixel_coords(pos)?;
+                // Measure from pixel centres, keeping the pixels on the edges of the histogram
+                let x = (x - 0.5).clamp(0.0, (self.width - 1) as f64);
+                let y = (y - 0.5).clamp(0.0, (self.height - 1) as f64);
+                let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+                let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+                let (fx, fy) = (x - x0 as f64, y - y0 as f64);
+                Some([
+                    (x0 + y0 * self.width, (1.0 - fx) * (1.0 - fy)),
+                    (x1 + y0 * self.width, fx * (1.0 - fy)),
+                    (x0 + y1 * self.width, (1.0 - fx) * fy),
+                    (x1 + y1 * self.width, fx * fy),
+                ])
+            }
+        }
+    }
+
+    /// Add one position to the histogram.
+    pub fn add(&mut self, pos: &[f64]) {
+        if let Some(weights) = self.splat_weights(pos) {
+            for (i, weight) in weights {
+                self.counts[i] += weight;
+                if self.counts[i] > self.max {
+                    self.max = self.counts[i];
+                }
+            }
+        }
+    }
+
+    /// Add every position in `positions` to the histogram.
+    pub fn add_all(&mut self, positions: &[Vec<f64>]) {
+        for pos in positions {
+            self.add(pos);
+        }
+    }
+
+    /// Return the counts divided by the maximum count, so that every value is in [0.0, 1.0]
+    pub fn normalised(&self) -> Vec<f64> {
+        if self.max == 0.0 {
+            return vec![0.0; self.counts.len()];
+        }
+        self.counts.iter().map(|c| c / self.max).collect()
+    }
 }
 
 /// A Clifford Attractor, as discovered by [Clifford A
@@ -119,14 +710,10 @@ impl Attractor for CliffordAttractor {
     /// Clifford attractors require 4 parameters.
     const NUM_PARAMETERS: u8 = 4;
 
-    fn new(params: Vec<f64>) -> Self {
-        assert!(
-            params.len() == 4,
-            "Clifford Attractors require 4 parameters (a, b, c, d) but you only gave {}",
-            params.len()
-        );
+    fn new(params: Vec<f64>) -> Result<Self, AttractorError> {
+        check_num_params::<CliffordAttractor>(params.len())?;
 
-        CliffordAttractor {
+        Ok(CliffordAttractor {
             // Create a new Clifford attractor from the vector of parameters `params`
             a: params[0],
             b: params[1],
@@ -140,7 +727,7 @@ impl Attractor for CliffordAttractor {
             ymax: 1.0 + params[3].abs(),  // max(sin()) + |d| * max(cos())
             history: vec![vec![0.0, 0.0]],
             param_history: vec![params],
-        }
+        })
     }
 
     /// Given an xy position, mutate x and y to be the next position based on the Clifford attractor
@@ -153,9 +740,9 @@ impl Attractor for CliffordAttractor {
     /// and y values for each individual iteration can be retrieved from the `.history` vector
     /// variable.
     fn step(&mut self, num_steps: usize) {
-        let mut xx = self.x.clone();
-        let mut yy = self.y.clone();
-        for _ in 1..num_steps {
+        let mut xx = self.x;
+        let mut yy = self.y;
+        for _ in 0..num_steps {
             xx = (self.a * yy).sin() + self.c * (self.a * xx).cos();
             yy = (self.b * xx).sin() + self.d * (self.b * yy).cos();
             self.history.push(vec![xx, yy]);
@@ -164,6 +751,19 @@ impl Attractor for CliffordAttractor {
         self.y = yy;
     }
 
+    fn step_with(&mut self, num_steps: usize, mut visit: impl FnMut(&[f64])) {
+        let mut xx = self.x;
+        let mut yy = self.y;
+        for _ in 0..num_steps {
+            xx = (self.a * yy).sin() + self.c * (self.a * xx).cos();
+            yy = (self.b * xx).sin() + self.d * (self.b * yy).cos();
+            visit(&[xx, yy]);
+        }
+        self.x = xx;
+        self.y = yy;
+        self.history = vec![vec![xx, yy]];
+    }
+
     fn reset(&mut self) {
         self.history = vec![vec![0.0, 0.0]];
         // self.param_history = vec![vec![self.a, self.b, self.c, self.d]];
@@ -175,12 +775,8 @@ impl Attractor for CliffordAttractor {
     /// A `Vec<Option<f64>>` is required, where the length of the vector must be
     /// 4 If an item in the vector is `Some`, then that item will be set to the value of a, b, c,
     /// or d based on the index of the item
-    fn set_params(&mut self, params: Vec<Option<f64>>) {
-        assert!(
-            params.len() == 4,
-            "Clifford Attractors require 4 parameters (a, b, c, d) but you only gave {}",
-            params.len()
-        );
+    fn set_params(&mut self, params: Vec<Option<f64>>) -> Result<(), AttractorError> {
+        check_num_params::<CliffordAttractor>(params.len())?;
         // Go through each parameter and check if it needs to be updated
         if let Some(a) = params[0] {
             self.a = a;
@@ -203,59 +799,45 @@ impl Attractor for CliffordAttractor {
         self.param_history
             .push(vec![self.a, self.b, self.c, self.d]);
         println!("{:#}", self);
+        Ok(())
     }
 
-    /// Given a certain `width` and `height` in pixels, return the density of the
-    /// pixel at `index`, where `index` is equal to:
-    /// ```
-    ///     index = x_position + width * y_position
-    /// ```
-    ///
-    /// The returned density has been normalised to be in the range [0.0, 1.0]
-    /// where 1.0 indicates that pixel was the most frequently landed on, and
-    /// 0.0 indicates that pixel was never landed on.
-    // TODO: add padding argument to display the attractor on only part of the
-    // buffer
-    fn get_densities(&mut self, width: usize, height: usize) -> Vec<f64> {
-        self.get_densities_with_border(width, height, 0.0)
+    fn params(&self) -> Vec<f64> {
+        vec![self.a, self.b, self.c, self.d]
     }
-    fn get_densities_with_border(
-        &mut self,
-        width: usize,
-        height: usize,
-        perc_border: f64,
-    ) -> Vec<f64> {
-        let xrange = self.xmax - self.xmin;
-        let yrange = self.ymax - self.ymin;
-        let mut densities = vec![0.0; width * height];
-        let mut densities_max = 0.0;
-
-        // First loop over the history, flooring all the values to find
-        // a histogram of how many times the attractor hit each xy point
-        for pos in self.history.iter() {
-            let x_full = width as f64 * (pos[0] - self.xmin) / xrange;
-            let y_full = height as f64 * (pos[1] - self.ymin) / yrange;
-            let x =
-                (x_full * (1.0 - 2.0 * perc_border) + width as f64 * perc_border).floor() as usize;
-            let y =
-                (y_full * (1.0 - 2.0 * perc_border) + height as f64 * perc_border).floor() as usize;
-            let i = x + y * width;
-            densities[i] += 1.0;
-            if densities[i] > densities_max {
-                densities_max = densities[i];
-            }
+
+    fn equations(&self) -> Vec<String> {
+        let (a, b, c, d) = (self.a, self.b, self.c, self.d);
+        vec![
+            format!("x_new = sin({a:.4} * y) + {c:.4} * cos({a:.4} * x)"),
+            format!("y_new = sin({b:.4} * x) + {d:.4} * cos({b:.4} * y)"),
+        ]
+    }
+
+    fn history(&self) -> &Vec<Vec<f64>> {
+        &self.history
+    }
+
+    fn set_history(&mut self, history: Vec<Vec<f64>>) {
+        if let Some(last) = history.last() {
+            self.x = last[0];
+            self.y = last[1];
         }
-        // Then loop over it again, to divide each of the items in the densities
-        // by the maximum
-        for idx in &mut densities {
-            *idx /= densities_max;
+        self.history = history;
+    }
+
+    fn bounds(&self) -> Bounds {
+        Bounds {
+            xmin: self.xmin,
+            xmax: self.xmax,
+            ymin: self.ymin,
+            ymax: self.ymax,
         }
-        return densities;
     }
 
     /// Write the Clifford Attractor to the file named `filename`
-    fn to_file(&mut self, filename: String) {
-        let mut file = std::fs::File::create(filename).expect("Failed to create file.");
+    fn to_file(&mut self, filename: String) -> Result<(), AttractorError> {
+        let mut file = std::fs::File::create(filename)?;
         // The preamble contains various things defining the attractor in question, and every line
         // in the preamble starts with a `#`
         let preamble: String = format!(
@@ -268,21 +850,21 @@ impl Attractor for CliffordAttractor {
             self.c,
             self.d
         );
-        file.write_all(preamble.as_bytes())
-            .expect("Failed to preamble write to file.");
+        file.write_all(preamble.as_bytes())?;
         for (i, item) in self.history.iter().enumerate() {
             let position: String = format!("{}:{},{}\n", i, item[0], item[1]);
-            file.write_all(position.as_bytes())
-                .expect("Failed to write position to file.");
+            file.write_all(position.as_bytes())?;
         }
+        Ok(())
     }
 }
 
 impl Display for CliffordAttractor {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Clifford Attractor (history={}, param_history={}):\n  x_new = sin({a:+.4} * y) + {c:+.4} * cos({a:+.4} * x);\n  y_new = sin({b:+.4} * x) + {d:+.4} * cos({b:+.4} * y)",
+        write!(f, "Clifford Attractor (history={}, param_history={}):\n  x_new = sin({a:+.4} * y) + {c:+.4} * cos({a:+.4} * x);\n  y_new = sin({b:+.4} * x) + {d:+.4} * cos({b:+.4} * y)\n  {}",
         self.history.len(),
         self.param_history.len(),
+        describe_lyapunov(self),
         a=self.a,
         b=self.b,
         c=self.c,
@@ -333,14 +915,10 @@ impl Attractor for DeJongAttractor {
     /// DeJong attractors require 4 parameters.
     const NUM_PARAMETERS: u8 = 4;
 
-    fn new(params: Vec<f64>) -> Self {
-        assert!(
-            params.len() == 4,
-            "DeJong Attractors require 4 parameters (a, b, c, d) but you only gave {}",
-            params.len()
-        );
+    fn new(params: Vec<f64>) -> Result<Self, AttractorError> {
+        check_num_params::<DeJongAttractor>(params.len())?;
 
-        DeJongAttractor {
+        Ok(DeJongAttractor {
             // Create a new DeJong attractor from the vector of parameters `params`
             a: params[0],
             b: params[1],
@@ -353,7 +931,7 @@ impl Attractor for DeJongAttractor {
             ymin: -2.0, // min(sin()) - min(cos())
             ymax: 2.0,  // max(sin()) + max(cos())
             history: vec![vec![0.0, 0.0]],
-        }
+        })
     }
 
     /// Given an xy position, mutate x and y to be the next position based on the DeJong attractor
@@ -366,13 +944,22 @@ impl Attractor for DeJongAttractor {
     /// and y values for each individual iteration can be retrieved from the `.history` vector
     /// variable.
     fn step(&mut self, num_steps: usize) {
-        for _ in 1..num_steps {
+        for _ in 0..num_steps {
             self.x = (self.a * self.y).sin() - (self.b * self.x).cos();
             self.y = (self.c * self.x).sin() - (self.d * self.y).cos();
             self.history.push(vec![self.x, self.y]);
         }
     }
 
+    fn step_with(&mut self, num_steps: usize, mut visit: impl FnMut(&[f64])) {
+        for _ in 0..num_steps {
+            self.x = (self.a * self.y).sin() - (self.b * self.x).cos();
+            self.y = (self.c * self.x).sin() - (self.d * self.y).cos();
+            visit(&[self.x, self.y]);
+        }
+        self.history = vec![vec![self.x, self.y]];
+    }
+
     fn reset(&mut self) {
         self.history = vec![vec![0.0, 0.0]];
     }
@@ -383,12 +970,8 @@ impl Attractor for DeJongAttractor {
     /// A `Vec<Option<f64>>` is required, where the length of the vector must be
     /// 4 If an item in the vector is `Some`, then that item will be set to the value of a, b, c,
     /// or d based on the index of the item
-    fn set_params(&mut self, params: Vec<Option<f64>>) {
-        assert!(
-            params.len() == 4,
-            "DeJong Attractors require 4 parameters (a, b, c, d) but you only gave {}",
-            params.len()
-        );
+    fn set_params(&mut self, params: Vec<Option<f64>>) -> Result<(), AttractorError> {
+        check_num_params::<DeJongAttractor>(params.len())?;
         // Go through each parameter and check if it needs to be updated
         if let Some(a) = params[0] {
             self.a = a;
@@ -402,44 +985,45 @@ impl Attractor for DeJongAttractor {
         if let Some(d) = params[3] {
             self.d = d;
         }
+        Ok(())
     }
 
-    fn get_densities(&mut self, width: usize, height: usize) -> Vec<f64> {
-        self.get_densities_with_border(width, height, 0.0)
+    fn params(&self) -> Vec<f64> {
+        vec![self.a, self.b, self.c, self.d]
     }
-    fn get_densities_with_border(
-        &mut self,
-        width: usize,
-        height: usize,
-        _perc_border: f64,
-    ) -> Vec<f64> {
-        let xrange = self.xmax - self.xmin;
-        let yrange = self.ymax - self.ymin;
-        let mut densities = vec![0.0; width * height];
-        let mut densities_max = 0.0;
-
-        // First loop over the history, flooring all the values to find
-        // a histogram of how many times the attractor hit each xy point
-        for pos in &mut self.history {
-            let x = ((pos[0] - self.xmin) / xrange).floor() as usize;
-            let y = ((pos[1] - self.ymin) / yrange).floor() as usize;
-            let i = x + y * width;
-            densities[i] += 1.0;
-            if densities[i] > densities_max {
-                densities_max = densities[i];
-            }
+
+    fn equations(&self) -> Vec<String> {
+        let (a, b, c, d) = (self.a, self.b, self.c, self.d);
+        vec![
+            format!("x_new = sin({a:.4} * y) - cos({b:.4} * x)"),
+            format!("y_new = sin({c:.4} * x) - cos({d:.4} * y)"),
+        ]
+    }
+
+    fn history(&self) -> &Vec<Vec<f64>> {
+        &self.history
+    }
+
+    fn set_history(&mut self, history: Vec<Vec<f64>>) {
+        if let Some(last) = history.last() {
+            self.x = last[0];
+            self.y = last[1];
         }
-        // Then loop over it again, to divide each of the items in the densities
-        // by the maximum
-        for idx in &mut densities {
-            *idx /= densities_max;
+        self.history = history;
+    }
+
+    fn bounds(&self) -> Bounds {
+        Bounds {
+            xmin: self.xmin,
+            xmax: self.xmax,
+            ymin: self.ymin,
+            ymax: self.ymax,
         }
-        return densities;
     }
 
     /// Write the DeJong Attractor to the file named `filename`
-    fn to_file(&mut self, filename: String) {
-        let mut file = std::fs::File::create(filename).expect("Failed to create file.");
+    fn to_file(&mut self, filename: String) -> Result<(), AttractorError> {
+        let mut file = std::fs::File::create(filename)?;
         // The preamble contains various things defining the attractor in question, and every line
         // in the preamble starts with a `#`
         let preamble: String = format!(
@@ -452,13 +1036,433 @@ impl Attractor for DeJongAttractor {
             self.c,
             self.d
         );
-        file.write_all(preamble.as_bytes())
-            .expect("Failed to preamble write to file.");
+        file.write_all(preamble.as_bytes())?;
         for (i, item) in self.history.iter().enumerate() {
             let position: String = format!("{}:{},{}\n", i, item[0], item[1]);
-            file.write_all(position.as_bytes())
-                .expect("Failed to write position to file.");
+            file.write_all(position.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+/// The index of the letter in a Sprott code with value 0.0 (`M`). Each letter is 0.1 larger than
+/// the one before it, so `A` is -1.2 and `Y` is 1.2.
+const SPROTT_CODE_ZERO: u8 = 12;
+/// The number of letters used in Sprott codes (`A` through `Y`).
+const SPROTT_CODE_LETTERS: u8 = 25;
+
+/// Convert a Sprott letter code like `FIRCDERRPVLD` into it's coefficients, checking that there
+/// are exactly `num_coeffs` letters.
+fn coeffs_from_code(code: &str, num_coeffs: usize) -> Result<Vec<f64>, AttractorError> {
+    if code.chars().count() != num_coeffs {
+        return Err(AttractorError::InvalidCode(format!(
+            "`{}` should have {} letters but has {}",
+            code,
+            num_coeffs,
+            code.chars().count()
+        )));
+    }
+    code.chars()
+        .map(|c| {
+            let idx = (c.to_ascii_uppercase() as u32).wrapping_sub('A' as u32);
+            if idx >= SPROTT_CODE_LETTERS as u32 {
+                return Err(AttractorError::InvalidCode(format!(
+                    "`{}` isn't one of the letters A to Y",
+                    c
+                )));
+            }
+            // Dividing by 10 (instead of multiplying by 0.1) gives the closest f64 to the decimal
+            Ok((idx as f64 - SPROTT_CODE_ZERO as f64) / 10.0)
+        })
+        .collect()
+}
+
+/// Convert coefficients back into a Sprott letter code, or `None` if any of the coefficients
+/// isn't one of -1.2, -1.1, ..., 1.1, 1.2.
+fn code_from_coeffs(coeffs: &[f64]) -> Option<String> {
+    coeffs
+        .iter()
+        .map(|c| {
+            let idx = (c * 10.0).round() + SPROTT_CODE_ZERO as f64;
+            let on_grid = ((idx - SPROTT_CODE_ZERO as f64) / 10.0 - c).abs() < 1e-9;
+            if on_grid && (0.0..SPROTT_CODE_LETTERS as f64).contains(&idx) {
+                Some((b'A' + idx as u8) as char)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Expand a partial set of parameters (see `Attractor::set_params`) into `coeffs`.
+pub(crate) fn update_coeffs(coeffs: &mut [f64], params: Vec<Option<f64>>) {
+    for (coeff, param) in coeffs.iter_mut().zip(params) {
+        if let Some(param) = param {
+            *coeff = param;
+        }
+    }
+}
+
+/// Grow `bounds` so that it includes `pos`. Positions which have escaped to infinity are ignored.
+pub(crate) fn include_in_bounds(bounds: &mut Bounds, pos: &[f64]) {
+    if pos[0].is_finite() && pos[1].is_finite() {
+        bounds.xmin = bounds.xmin.min(pos[0]);
+        bounds.xmax = bounds.xmax.max(pos[0]);
+        bounds.ymin = bounds.ymin.min(pos[1]);
+        bounds.ymax = bounds.ymax.max(pos[1]);
+    }
+}
+
+/// A 2D [Sprott Polynomial Attractor](http://paulbourke.net/fractals/sprott/), from Julien C.
+/// Sprott's book "Strange Attractors: Creating Patterns in Chaos".
+///
+/// The 12 coefficients a1 through a12 are used in a general quadratic map:
+/// ```
+/// x_new = a1 + a2 * x + a3 * x * x + a4 * x * y +  a5 * y +  a6 * y * y
+/// y_new = a7 + a8 * x + a9 * x * x + a10 * x * y + a11 * y + a12 * y * y
+/// ```
+/// Most sets of coefficients escape to infinity or collapse to a point, but some of them are
+/// strange attractors. Sprott shares the interesting ones as a code of 12 letters like
+/// `FIRCDERRPVLD`, where `A` is -1.2, `B` is -1.1, and so on up to `Y` which is 1.2. See
+/// `SprottAttractor::from_code` and `SprottAttractor::code`.
+///
+/// There's no simple formula for the bounds of these attractors, so the bounds are grown to fit
+/// every position visited so far.
+#[derive(Debug)]
+pub struct SprottAttractor {
+    /// The coefficients a1 through a12.
+    pub coeffs: Vec<f64>,
+    /// The current x value.
+    pub x: f64,
+    /// The current y value.
+    pub y: f64,
+    /// The smallest box containing every finite position visited so far.
+    bounds: Bounds,
+    /// Store all the previously visited points in the history vector
+    pub history: Vec<Vec<f64>>,
+}
+
+impl SprottAttractor {
+    /// Sprott starts every orbit from this position.
+    const START: [f64; 2] = [0.05, 0.05];
+
+    /// Create a new Sprott attractor from a 12 letter code like `FIRCDERRPVLD`.
+    pub fn from_code(code: &str) -> Result<Self, AttractorError> {
+        Self::new(coeffs_from_code(code, Self::NUM_PARAMETERS as usize)?)
+    }
+
+    /// Move to the next position of the quadratic map, growing the bounds to fit it.
+    fn advance(&mut self) -> [f64; 2] {
+        let a = &self.coeffs;
+        let (x, y) = (self.x, self.y);
+        self.x = a[0] + a[1] * x + a[2] * x * x + a[3] * x * y + a[4] * y + a[5] * y * y;
+        self.y = a[6] + a[7] * x + a[8] * x * x + a[9] * x * y + a[10] * y + a[11] * y * y;
+        include_in_bounds(&mut self.bounds, &[self.x, self.y]);
+        [self.x, self.y]
+    }
+}
+
+impl Attractor for SprottAttractor {
+    /// The name used to specify the attractor in text files.
+    const NAME: &'static str = "sprott";
+    /// 2D Sprott attractors live in 2 dimensions.
+    const DIMENSIONALITY: u8 = 2;
+    /// 2D Sprott attractors require 12 parameters.
+    const NUM_PARAMETERS: u8 = 12;
+
+    fn new(params: Vec<f64>) -> Result<Self, AttractorError> {
+        check_num_params::<SprottAttractor>(params.len())?;
+        let [x, y] = Self::START;
+        Ok(SprottAttractor {
+            coeffs: params,
+            x,
+            y,
+            bounds: Bounds {
+                xmin: x,
+                xmax: x,
+                ymin: y,
+                ymax: y,
+            },
+            history: vec![vec![x, y]],
+        })
+    }
+
+    /// Step the quadratic map `num_steps` times, saving each position to the `.history` vector.
+    fn step(&mut self, num_steps: usize) {
+        for _ in 0..num_steps {
+            let pos = self.advance();
+            self.history.push(pos.to_vec());
+        }
+    }
+
+    fn step_with(&mut self, num_steps: usize, mut visit: impl FnMut(&[f64])) {
+        for _ in 0..num_steps {
+            visit(&self.advance());
+        }
+        self.history = vec![vec![self.x, self.y]];
+    }
+
+    fn reset(&mut self) {
+        let [x, y] = Self::START;
+        self.x = x;
+        self.y = y;
+        self.set_history(vec![vec![x, y]]);
+    }
+
+    fn set_params(&mut self, params: Vec<Option<f64>>) -> Result<(), AttractorError> {
+        check_num_params::<SprottAttractor>(params.len())?;
+        update_coeffs(&mut self.coeffs, params);
+        Ok(())
+    }
+
+    fn params(&self) -> Vec<f64> {
+        self.coeffs.clone()
+    }
+
+    /// The coefficients are named `a1` to `a12`, like in Sprott's book.
+    fn param_names(&self) -> Vec<String> {
+        (1..=self.coeffs.len()).map(|i| format!("a{}", i)).collect()
+    }
+
+    fn code(&self) -> Option<String> {
+        code_from_coeffs(&self.coeffs)
+    }
+
+    fn equations(&self) -> Vec<String> {
+        let a = &self.coeffs;
+        vec![
+            format!(
+                "x_new = {:+.1} {:+.1}x {:+.1}x^2 {:+.1}xy {:+.1}y {:+.1}y^2",
+                a[0], a[1], a[2], a[3], a[4], a[5]
+            ),
+            format!(
+                "y_new = {:+.1} {:+.1}x {:+.1}x^2 {:+.1}xy {:+.1}y {:+.1}y^2",
+                a[6], a[7], a[8], a[9], a[10], a[11]
+            ),
+        ]
+    }
+
+    fn history(&self) -> &Vec<Vec<f64>> {
+        &self.history
+    }
+
+    fn set_history(&mut self, history: Vec<Vec<f64>>) {
+        if let Some(last) = history.last() {
+            self.x = last[0];
+            self.y = last[1];
+            self.bounds = Bounds {
+                xmin: last[0],
+                xmax: last[0],
+                ymin: last[1],
+                ymax: last[1],
+            };
+        }
+        for pos in history.iter() {
+            include_in_bounds(&mut self.bounds, pos);
         }
+        self.history = history;
+    }
+
+    fn bounds(&self) -> Bounds {
+        self.bounds
+    }
+
+    /// Write the Sprott Attractor to the file named `filename`, with its code (if it has one) and
+    /// parameters named `a1` to `a12`.
+    fn to_file(&mut self, filename: String) -> Result<(), AttractorError> {
+        write_trajectory(
+            filename,
+            (Self::NAME, Self::NUM_PARAMETERS, Self::DIMENSIONALITY),
+            self.code(),
+            &self.param_names(),
+            &self.coeffs,
+            &self.history,
+        )
+    }
+}
+
+impl Display for SprottAttractor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let a = &self.coeffs;
+        write!(
+            f,
+            "Sprott Attractor {} (history={}):\n  x_new = {:+.1} {:+.1}x {:+.1}x^2 {:+.1}xy {:+.1}y {:+.1}y^2\n  y_new = {:+.1} {:+.1}x {:+.1}x^2 {:+.1}xy {:+.1}y {:+.1}y^2\n  {}",
+            self.code().unwrap_or_else(|| "(no code)".to_string()),
+            self.history.len(),
+            a[0], a[1], a[2], a[3], a[4], a[5], a[6], a[7], a[8], a[9], a[10], a[11],
+            describe_lyapunov(self),
+        )
+    }
+}
+
+/// A 3D [Sprott Polynomial Attractor](http://paulbourke.net/fractals/sprott/).
+///
+/// This is the 3D version of `SprottAttractor`, with 10 coefficients for each of x, y, and z:
+/// ```
+/// x_new = a1  + a2 * x  + a3 * x * x  + a4 * x * y  + a5 * x * z
+///             + a6 * y  + a7 * y * y  + a8 * y * z  + a9 * z  + a10 * z * z
+/// y_new = a11 + a12 * x + a13 * x * x + a14 * x * y + a15 * x * z
+///             + a16 * y + a17 * y * y + a18 * y * z + a19 * z + a20 * z * z
+/// z_new = a21 + a22 * x + a23 * x * x + a24 * x * y + a25 * x * z
+///             + a26 * y + a27 * y * y + a28 * y * z + a29 * z + a30 * z * z
+/// ```
+/// The coefficients are shared as a code of 30 letters, in the same way as for `SprottAttractor`.
+/// Only the x and y coordinates are used for the bounds.
+#[derive(Debug)]
+pub struct Sprott3dAttractor {
+    /// The coefficients a1 through a30.
+    pub coeffs: Vec<f64>,
+    /// The current x value.
+    pub x: f64,
+    /// The current y value.
+    pub y: f64,
+    /// The current z value.
+    pub z: f64,
+    /// The smallest box containing the x and y of every finite position visited so far.
+    bounds: Bounds,
+    /// Store all the previously visited points in the history vector
+    pub history: Vec<Vec<f64>>,
+}
+
+impl Sprott3dAttractor {
+    /// Sprott starts every orbit from this position.
+    const START: [f64; 3] = [0.05, 0.05, 0.05];
+
+    /// Create a new 3D Sprott attractor from a 30 letter code.
+    pub fn from_code(code: &str) -> Result<Self, AttractorError> {
+        Self::new(coeffs_from_code(code, Self::NUM_PARAMETERS as usize)?)
+    }
+
+    /// Move to the next position of the quadratic map, growing the bounds to fit it.
+    fn advance(&mut self) -> [f64; 3] {
+        let (x, y, z) = (self.x, self.y, self.z);
+        // The terms are in the same order as the coefficients for each of x, y, and z
+        let terms = [1.0, x, x * x, x * y, x * z, y, y * y, y * z, z, z * z];
+        let mut next = [0.0; 3];
+        for (i, coord) in next.iter_mut().enumerate() {
+            let coeffs = &self.coeffs[10 * i..10 * (i + 1)];
+            *coord = coeffs.iter().zip(terms.iter()).map(|(a, t)| a * t).sum();
+        }
+        [self.x, self.y, self.z] = next;
+        include_in_bounds(&mut self.bounds, &next);
+        next
+    }
+}
+
+impl Attractor for Sprott3dAttractor {
+    /// The name used to specify the attractor in text files.
+    const NAME: &'static str = "sprottthreed";
+    /// 3D Sprott attractors live in 3 dimensions.
+    const DIMENSIONALITY: u8 = 3;
+    /// 3D Sprott attractors require 30 parameters.
+    const NUM_PARAMETERS: u8 = 30;
+
+    fn new(params: Vec<f64>) -> Result<Self, AttractorError> {
+        check_num_params::<Sprott3dAttractor>(params.len())?;
+        let [x, y, z] = Self::START;
+        Ok(Sprott3dAttractor {
+            coeffs: params,
+            x,
+            y,
+            z,
+            bounds: Bounds {
+                xmin: x,
+                xmax: x,
+                ymin: y,
+                ymax: y,
+            },
+            history: vec![vec![x, y, z]],
+        })
+    }
+
+    /// Step the quadratic map `num_steps` times, saving each position to the `.history` vector.
+    fn step(&mut self, num_steps: usize) {
+        for _ in 0..num_steps {
+            let pos = self.advance();
+            self.history.push(pos.to_vec());
+        }
+    }
+
+    fn step_with(&mut self, num_steps: usize, mut visit: impl FnMut(&[f64])) {
+        for _ in 0..num_steps {
+            visit(&self.advance());
+        }
+        self.history = vec![vec![self.x, self.y, self.z]];
+    }
+
+    fn reset(&mut self) {
+        let [x, y, z] = Self::START;
+        self.set_history(vec![vec![x, y, z]]);
+    }
+
+    fn set_params(&mut self, params: Vec<Option<f64>>) -> Result<(), AttractorError> {
+        check_num_params::<Sprott3dAttractor>(params.len())?;
+        update_coeffs(&mut self.coeffs, params);
+        Ok(())
+    }
+
+    fn params(&self) -> Vec<f64> {
+        self.coeffs.clone()
+    }
+
+    /// The coefficients are named `a1` to `a30`, like in Sprott's book.
+    fn param_names(&self) -> Vec<String> {
+        (1..=self.coeffs.len()).map(|i| format!("a{}", i)).collect()
+    }
+
+    fn code(&self) -> Option<String> {
+        code_from_coeffs(&self.coeffs)
+    }
+
+    fn history(&self) -> &Vec<Vec<f64>> {
+        &self.history
+    }
+
+    fn set_history(&mut self, history: Vec<Vec<f64>>) {
+        if let Some(last) = history.last() {
+            self.x = last[0];
+            self.y = last[1];
+            self.z = last[2];
+            self.bounds = Bounds {
+                xmin: last[0],
+                xmax: last[0],
+                ymin: last[1],
+                ymax: last[1],
+            };
+        }
+        for pos in history.iter() {
+            include_in_bounds(&mut self.bounds, pos);
+        }
+        self.history = history;
+    }
+
+    fn bounds(&self) -> Bounds {
+        self.bounds
+    }
+
+    /// Write the 3D Sprott Attractor to the file named `filename`, with its code (if it has one)
+    /// and parameters named `a1` to `a30`.
+    fn to_file(&mut self, filename: String) -> Result<(), AttractorError> {
+        write_trajectory(
+            filename,
+            (Self::NAME, Self::NUM_PARAMETERS, Self::DIMENSIONALITY),
+            self.code(),
+            &self.param_names(),
+            &self.coeffs,
+            &self.history,
+        )
+    }
+}
+
+impl Display for Sprott3dAttractor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "3D Sprott Attractor {} (history={}):\n  {}",
+            self.code().unwrap_or_else(|| "(no code)".to_string()),
+            self.history.len(),
+            describe_lyapunov(self),
+        )
     }
 }
 
@@ -470,6 +1474,211 @@ mod tests {
     use rand::Rng;
     use test::Bencher;
 
+    /// Every attractor adds exactly `num_steps` positions, however it's stepped.
+    fn check_step_count<A: Attractor>(params: Vec<f64>) {
+        let mut attractor = A::new(params).unwrap();
+        let start = attractor.history().len();
+        attractor.step(10);
+        assert_eq!(attractor.history().len(), start + 10, "{}", A::NAME);
+        let kept = attractor.history().to_vec();
+        let mut streamed = A::new(attractor.params()).unwrap();
+        streamed.set_history(kept.clone());
+        let mut visited = Vec::new();
+        streamed.step_with(10, |pos| visited.push(pos.to_vec()));
+        attractor.step(10);
+        assert_eq!(visited, attractor.history()[kept.len()..], "{}", A::NAME);
+        // Streaming only keeps the last position, so memory doesn't grow with the steps
+        assert_eq!(
+            streamed.history(),
+            &attractor.history()[kept.len() + 9..],
+            "{}",
+            A::NAME
+        );
+    }
+
+    #[test]
+    fn step_adds_exactly_num_steps_positions() {
+        check_step_count::<CliffordAttractor>(vec![-1.4, 1.6, 1.0, 0.7]);
+        check_step_count::<DeJongAttractor>(vec![1.4, -2.3, 2.4, -2.1]);
+        check_step_count::<SprottAttractor>(vec![0.1; 12]);
+        check_step_count::<Sprott3dAttractor>(vec![0.1; 30]);
+        check_step_count::<crate::flows::LorenzAttractor>(vec![10.0, 28.0, 8.0 / 3.0]);
+    }
+
+    #[test]
+    fn dejong_densities_use_the_whole_grid() {
+        let mut dejong = DeJongAttractor::new(vec![1.4, -2.3, 2.4, -2.1]).unwrap();
+        dejong.step(10_000);
+        let densities = dejong.get_densities(100, 100);
+        assert_eq!(densities.len(), 100 * 100);
+        // Previously every point landed in pixel 0 or 1
+        assert!(densities.iter().filter(|d| **d > 0.0).count() > 100);
+    }
+
+    #[test]
+    fn wrong_number_of_parameters_is_an_error() {
+        assert!(CliffordAttractor::new(vec![1.0, 2.0, 3.0]).is_err());
+        let mut clifford = CliffordAttractor::new(vec![-1.4, 1.6, 1.0, 0.7]).unwrap();
+        assert!(clifford.set_params(vec![Some(1.0)]).is_err());
+        assert_eq!(clifford.a, -1.4);
+        // Writing into a directory which doesn't exist shouldn't panic
+        assert!(clifford
+            .to_file("cache/does/not/exist.txt".to_string())
+            .is_err());
+    }
+
+    #[test]
+    fn from_file_reads_what_to_file_wrote() {
+        let fname = "cache/clifford/from_file_round_trip.tmp".to_string();
+        let mut clifford = CliffordAttractor::new(vec![-1.4, 1.6, 1.0, 0.7]).unwrap();
+        clifford.step(1_000);
+        clifford.to_file(fname.clone()).unwrap();
+
+        let loaded = CliffordAttractor::from_file(fname.clone()).unwrap();
+        assert_eq!(loaded.history, clifford.history);
+        assert_eq!(
+            (loaded.a, loaded.b, loaded.c, loaded.d),
+            (-1.4, 1.6, 1.0, 0.7)
+        );
+        assert_eq!((loaded.x, loaded.y), (clifford.x, clifford.y));
+
+        // A Clifford file isn't a valid DeJong file
+        assert!(DeJongAttractor::from_file(fname.clone()).is_err());
+        assert_eq!(saved_attractor_name(&fname).unwrap(), "clifford");
+        fs::write(&fname, "").unwrap();
+        assert!(matches!(
+            CliffordAttractor::from_file(fname.clone()),
+            Err(AttractorError::InvalidFile { line: 1, .. })
+        ));
+        fs::remove_file(fname).expect("Failed to delete file");
+    }
+
+    #[test]
+    fn sprott_codes_round_trip() {
+        let sprott = SprottAttractor::from_code("FIRCDERRPVLD").unwrap();
+        assert_eq!(sprott.coeffs[0], -0.7);
+        assert_eq!(sprott.coeffs[2], 0.5);
+        assert_eq!(sprott.code(), Some("FIRCDERRPVLD".to_string()));
+        assert!(SprottAttractor::from_code("FIRCDERRPVL").is_err());
+        assert!(SprottAttractor::from_code("FIRCDERRPVLZ").is_err());
+
+        let code3d = "MCRILFOTIJMELRVIFMRKFMLPDREAOM";
+        assert_eq!(
+            Sprott3dAttractor::from_code(code3d)
+                .unwrap()
+                .code()
+                .unwrap(),
+            code3d
+        );
+
+        // The code is saved to file too, and doesn't get in the way of loading it again
+        let fname = std::env::temp_dir().join("attractors_sprott_code_test.txt");
+        let fname = fname.to_str().unwrap().to_string();
+        let mut sprott = SprottAttractor::from_code("FIRCDERRPVLD").unwrap();
+        sprott.step(100);
+        sprott.to_file(fname.clone()).unwrap();
+        let saved = fs::read_to_string(&fname).unwrap();
+        assert_eq!(saved.lines().nth(1), Some("#code=FIRCDERRPVLD"));
+        let loaded = SprottAttractor::from_file(fname.clone()).unwrap();
+        assert_eq!(loaded.code(), sprott.code());
+        assert_eq!(loaded.history, sprott.history);
+        fs::remove_file(fname).unwrap();
+    }
+
+    #[test]
+    fn sprott_attractor_stays_bounded() {
+        let mut sprott = SprottAttractor::from_code("FIRCDERRPVLD").unwrap();
+        sprott.step(10_000);
+        assert_eq!(sprott.history.len(), 10_001);
+        let bounds = sprott.bounds();
+        assert!(bounds.xmax - bounds.xmin < 10.0 && bounds.xmax > bounds.xmin);
+        let densities = sprott.get_densities(100, 100);
+        assert!(densities.iter().filter(|d| **d > 0.0).count() > 100);
+    }
+
+    #[test]
+    fn histogram_respects_the_border() {
+        let bounds = Bounds {
+            xmin: -1.0,
+            xmax: 1.0,
+            ymin: -1.0,
+            ymax: 1.0,
+        };
+        let mut histogram = Histogram::new(10, 10, bounds, 0.1);
+        histogram.add_all(&[vec![-1.0, -1.0], vec![1.0, 1.0], vec![5.0, 0.0]]);
+        // The corners of the bounds get pushed in by the border, and out of bounds positions
+        // are dropped
+        assert_eq!(histogram.counts[1 + 10], 1.0);
+        assert_eq!(histogram.counts[9 + 9 * 10], 1.0);
+        assert_eq!(histogram.counts.iter().sum::<f64>(), 2.0);
+    }
+
+    #[test]
+    fn empty_histograms_drop_every_position() {
+        let bounds = Bounds {
+            xmin: -1.0,
+            xmax: 1.0,
+            ymin: -1.0,
+            ymax: 1.0,
+        };
+        for (width, height) in [(0, 10), (10, 0), (0, 0)] {
+            let mut histogram = Histogram::new(width, height, bounds, 0.1);
+            assert_eq!(histogram.index_of(&[1.0, 1.0]), None);
+            histogram.splat = Splat::Bilinear;
+            assert_eq!(histogram.splat_weights(&[1.0, 1.0]), None);
+            histogram.add(&[0.0, 0.0]);
+            assert_eq!(histogram.max, 0.0);
+            assert!(histogram.normalised().is_empty());
+        }
+    }
+
+    #[test]
+    fn zooming_keeps_the_mouse_over_the_same_position() {
+        let bounds = Bounds {
+            xmin: -2.0,
+            xmax: 2.0,
+            ymin: -1.0,
+            ymax: 3.0,
+        };
+        let histogram = Histogram::new(200, 100, bounds, 0.05);
+        let mouse = histogram.position_of(50.5, 20.5);
+        assert_eq!(histogram.index_of(&mouse), Some(50 + 20 * 200));
+        let zoomed = Histogram::new(200, 100, bounds.zoomed(mouse, 3.0), 0.05);
+        let after = zoomed.position_of(50.5, 20.5);
+        assert!((after[0] - mouse[0]).abs() < 1e-12 && (after[1] - mouse[1]).abs() < 1e-12);
+        assert!((zoomed.bounds.xmax - zoomed.bounds.xmin - 4.0 / 3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn bilinear_splats_share_positions_between_pixels() {
+        let bounds = Bounds {
+            xmin: 0.0,
+            xmax: 4.0,
+            ymin: 0.0,
+            ymax: 4.0,
+        };
+        let mut histogram = Histogram::new(4, 4, bounds, 0.0);
+        histogram.splat = Splat::Bilinear;
+        // Exactly between the centres of pixels (1, 1) and (2, 1), a quarter of the way to row 2
+        histogram.add(&[2.0, 1.75]);
+        assert_eq!(histogram.counts[1 + 4], 0.375);
+        assert_eq!(histogram.counts[2 + 4], 0.375);
+        assert_eq!(histogram.counts[1 + 2 * 4], 0.125);
+        assert_eq!(histogram.counts[2 + 2 * 4], 0.125);
+        assert_eq!(histogram.counts.iter().sum::<f64>(), 1.0);
+
+        // Supersampling and then downsampling with a box filter gives the same total weight
+        let sampling = Sampling {
+            supersample: 2,
+            ..Sampling::default()
+        };
+        let mut histogram = Histogram::supersampled(4, 4, bounds, 0.0, &sampling);
+        histogram.add(&[0.1, 0.1]);
+        let histogram = histogram.downsampled(2, Filter::Box);
+        assert_eq!((histogram.width, histogram.height), (4, 4));
+        assert_eq!(histogram.counts[0], 0.25);
+    }
+
     #[bench]
     fn bench_clifford_write_to_file_10k(b: &mut Bencher) {
         let mut rng = rand::thread_rng();
@@ -478,7 +1687,8 @@ mod tests {
             rng.gen_range(-2.0..2.0),
             rng.gen_range(-1.0..1.0),
             rng.gen_range(-1.0..1.0),
-        ]);
+        ])
+        .unwrap();
 
         let fname = format!(
             "cache/clifford/{}-a={}-b={}-c={}-d={}.tmp",
@@ -490,7 +1700,7 @@ mod tests {
         );
 
         clifford.step(10_000);
-        b.iter(|| clifford.to_file(fname.to_string()));
+        b.iter(|| clifford.to_file(fname.to_string()).unwrap());
 
         // Remove the temporary files once complete
         fs::remove_file(fname).expect("Failed to delete file");
@@ -504,7 +1714,8 @@ mod tests {
             rng.gen_range(-2.0..2.0),
             rng.gen_range(-1.0..1.0),
             rng.gen_range(-1.0..1.0),
-        ]);
+        ])
+        .unwrap();
         b.iter(|| clifford.step(10_000));
     }
 }
@@ -0,0 +1,384 @@
+//! Rendering every special attractor at once.
+//!
+//! Pressing `m` marks an attractor as special by appending its parameters to
+//! `cache/clifford/special.txt`, but the only way to render one of them is to navigate to it and
+//! press `S`. Batch mode renders each line of the specials file (or a subset of them) to its own
+//! PNG using several worker threads, then tiles small thumbnails of every image into a single
+//! contact sheet so they can all be compared at a glance. Images which have already been
+//! rendered with the same options are skipped, so an interrupted batch can be restarted without
+//! losing any work.
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use image::imageops::{self, FilterType};
+use image::{Rgb, RgbImage};
+
+use crate::attractors::{Attractor, AttractorError};
+use crate::headless::{parse, parse_flags, AttractorJob, Flags, RenderOptions};
+use crate::print::save_png;
+use crate::provenance;
+
+/// Read a specials file, where every line is a comma separated list of parameters like
+/// `a=2.86,b=-2.59,c=0.40,d=1.06`. Anything after a `#` is a comment (like the scores written
+/// by the search), and empty lines are ignored.
+pub fn read_specials(filename: &str) -> Result<Vec<Vec<f64>>, AttractorError> {
+    let reader = BufReader::new(File::open(filename)?);
+    let mut specials = vec![];
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.split('#').next().unwrap_or_default();
+        if line.trim().is_empty() {
+            continue;
+        }
+        let params = line
+            .split(',')
+            .map(|p| {
+                // The parameter name is optional, so `1.0` is read the same as `a=1.0`
+                let value = p.split_once('=').map_or(p, |(_name, value)| value);
+                value
+                    .trim()
+                    .parse::<f64>()
+                    .map_err(|_| AttractorError::InvalidFile {
+                        line: i + 1,
+                        reason: format!("couldn't parse `{}` as a parameter", p),
+                    })
+            })
+            .collect::<Result<_, _>>()?;
+        specials.push(params);
+    }
+    Ok(specials)
+}
+
+/// Parse a list of line numbers and inclusive ranges of line numbers like `0-99,250,300-310`.
+pub fn parse_indices(indices: &str) -> Result<Vec<usize>, String> {
+    let mut parsed = vec![];
+    for part in indices.split(',') {
+        match part.split_once('-') {
+            Some((start, end)) => {
+                let start: usize = parse("indices", start.trim())?;
+                let end: usize = parse("indices", end.trim())?;
+                parsed.extend(start..=end);
+            }
+            None => parsed.push(parse("indices", part.trim())?),
+        }
+    }
+    Ok(parsed)
+}
+
+/// The filename of a rendered special, which only depends on its parameters (named by `names`)
+/// or its code (if the attractor has one) and size, so that it's still found if the specials file
+/// gets reordered. Parameters are written in full, so that different specials never share a file.
+pub fn special_filename(
+    names: &[String],
+    params: &[f64],
+    code: Option<&str>,
+    width: usize,
+    height: usize,
+) -> String {
+    if let Some(code) = code {
+        return format!("{}_{}x{}.png", code, width, height);
+    }
+    let params: Vec<String> = names
+        .iter()
+        .zip(params)
+        .map(|(name, p)| format!("{}={}", name, p))
+        .collect();
+    format!("{}_{}x{}.png", params.join("_"), width, height)
+}
+
+/// The `Attractor::param_names` and `Attractor::code` of an attractor, which its rendered
+/// special is named by.
+struct Naming;
+
+impl AttractorJob for Naming {
+    type Output = (Vec<String>, Option<String>);
+    fn run<A: Attractor + Sync>(self, attractor: A) -> Result<Self::Output, String> {
+        Ok((attractor.param_names(), attractor.code()))
+    }
+}
+
+/// Everything needed to render a batch of specials.
+#[derive(Debug, Clone)]
+pub struct BatchOptions {
+    /// How each special gets rendered. The `params` and `output` are replaced for every special.
+    pub render: RenderOptions,
+    /// The file to read the specials from.
+    pub specials: String,
+    pub output_dir: String,
+    /// Only render the specials on these lines of the file. All of them are rendered if `None`.
+    pub indices: Option<Vec<usize>>,
+    /// The number of specials rendered at the same time.
+    pub workers: usize,
+    /// The size of the longest side of each thumbnail on the contact sheet.
+    pub thumbnail: u32,
+    /// Where the contact sheet is saved. Defaults to `contact_sheet.png` in `output_dir`.
+    pub contact_sheet: Option<String>,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        BatchOptions {
+            render: RenderOptions {
+                // Every worker keeps its whole history in memory, so don't use too many
+                iterations: 2_000_000,
+                ..RenderOptions::default()
+            },
+            specials: "cache/clifford/special.txt".to_string(),
+            output_dir: "cache/clifford/batch".to_string(),
+            indices: None,
+            workers: thread::available_parallelism().map_or(1, |n| n.get()),
+            thumbnail: 100,
+            contact_sheet: None,
+        }
+    }
+}
+
+impl BatchOptions {
+    /// Build the batch options from command line arguments. Any flag which isn't a batch option
+    /// is passed along to the `RenderOptions`.
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut opts = BatchOptions::default();
+        let mut render_flags = Flags::new();
+        for (name, value) in parse_flags(args)? {
+            let n = name.as_str();
+            match n {
+                "specials" => opts.specials = value,
+                "output-dir" => opts.output_dir = value,
+                "indices" => opts.indices = Some(parse_indices(&value)?),
+                "workers" => opts.workers = parse(n, &value)?,
+                "thumbnail" => opts.thumbnail = parse(n, &value)?,
+                "contact-sheet" => opts.contact_sheet = Some(value),
+                "tile-rows" => return Err("`--tile-rows` can't be used in batch mode".to_string()),
+                "paper" | "orientation" | "dpi" | "margin" | "bleed" | "caption" | "poster"
+                | "overlap" | "from-file" | "save-trajectory" => {
+                    return Err(format!("`--{}` can't be used in batch mode", n))
+                }
+                "params" | "code" | "output" => {
+                    return Err(format!(
+                        "`--{}` can't be used in batch mode, the specials are used instead",
+                        name
+                    ))
+                }
+                _ => {
+                    render_flags.insert(name, value);
+                }
+            }
+        }
+        opts.render.update_from_flags(render_flags)?;
+        if opts.workers == 0 || opts.thumbnail == 0 {
+            return Err("The workers and thumbnail size must be larger than 0".to_string());
+        }
+        Ok(opts)
+    }
+
+    /// Render every selected special, skipping any which have already been rendered, then save
+    /// the contact sheet.
+    pub fn run(&self) -> Result<(), String> {
+        let specials = read_specials(&self.specials).map_err(|e| e.to_string())?;
+        let indices = match &self.indices {
+            Some(indices) => indices.clone(),
+            None => (0..specials.len()).collect(),
+        };
+        if let Some(i) = indices.iter().find(|i| **i >= specials.len()) {
+            return Err(format!(
+                "{} only has {} specials, so there's no special #{}",
+                self.specials,
+                specials.len(),
+                i
+            ));
+        }
+        fs::create_dir_all(&self.output_dir)
+            .map_err(|e| format!("Couldn't create {}: {}", self.output_dir, e))?;
+
+        println!(
+            "Rendering {} specials from {} with {} workers",
+            indices.len(),
+            self.specials,
+            self.workers
+        );
+        // Workers take the next special from the queue until there are none left, so that a
+        // slow special doesn't hold up the others
+        let next = AtomicUsize::new(0);
+        let thumbnails: Mutex<Vec<Option<RgbImage>>> = Mutex::new(vec![None; indices.len()]);
+        thread::scope(|s| {
+            for _ in 0..self.workers.min(indices.len()) {
+                s.spawn(|| loop {
+                    let job = next.fetch_add(1, Ordering::Relaxed);
+                    if job >= indices.len() {
+                        break;
+                    }
+                    let idx = indices[job];
+                    match self.render_special(&specials[idx]) {
+                        Ok(image) => {
+                            let thumbnail = self.make_thumbnail(&image);
+                            thumbnails.lock().expect("A worker panicked")[job] = Some(thumbnail);
+                        }
+                        Err(e) => println!("Skipping special #{}: {}", idx, e),
+                    }
+                });
+            }
+        });
+
+        let thumbnails = thumbnails.into_inner().expect("A worker panicked");
+        let num_rendered = thumbnails.iter().filter(|t| t.is_some()).count();
+        let contact_sheet = self.contact_sheet.clone().unwrap_or_else(|| {
+            Path::new(&self.output_dir)
+                .join("contact_sheet.png")
+                .to_string_lossy()
+                .to_string()
+        });
+        self.make_contact_sheet(&thumbnails)
+            .save(&contact_sheet)
+            .map_err(|e| format!("Couldn't save {}: {}", contact_sheet, e))?;
+        println!(
+            "Rendered {} of {} specials, contact sheet saved to {}",
+            num_rendered,
+            indices.len(),
+            contact_sheet
+        );
+        Ok(())
+    }
+
+    /// Render one special to the output directory, or load it if it has already been rendered
+    /// with the same options.
+    fn render_special(&self, params: &[f64]) -> Result<RgbImage, String> {
+        let mut opts = RenderOptions {
+            params: Some(params.to_vec()),
+            ..self.render.clone()
+        };
+        // The names don't depend on where the attractor is drawn, so don't bother fitting it
+        let (names, code) = RenderOptions {
+            fit_bounds: false,
+            ..opts.clone()
+        }
+        .with_attractor(Naming)?;
+        let path: PathBuf = Path::new(&self.output_dir).join(special_filename(
+            &names,
+            params,
+            code.as_deref(),
+            self.render.width,
+            self.render.height,
+        ));
+        opts.output = path.to_string_lossy().to_string();
+        // The filename doesn't say how the image was coloured, so check what the image remembers
+        if path.exists() && provenance::rendered_by(&opts.output, &opts, params) {
+            // A partially written file can't be opened, so just render it again
+            if let Ok(image) = image::open(&path) {
+                return Ok(image.into_rgb8());
+            }
+        }
+        let (image, text) = opts.render_image()?;
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        save_png(&opts.output, width, height, None, |write| {
+            write(image.as_raw())?;
+            Ok(text)
+        })?;
+        println!("Saved {}", opts.output);
+        Ok(image)
+    }
+
+    /// Shrink `image` so that its longest side is `thumbnail` pixels long.
+    fn make_thumbnail(&self, image: &RgbImage) -> RgbImage {
+        let scale = self.thumbnail as f64 / image.width().max(image.height()) as f64;
+        let width = ((image.width() as f64 * scale).round() as u32).max(1);
+        let height = ((image.height() as f64 * scale).round() as u32).max(1);
+        imageops::resize(image, width, height, FilterType::Triangle)
+    }
+
+    /// Tile the thumbnails into a roughly square grid, in the same order as the specials. Any
+    /// specials which failed to render are left as an empty cell, so that the position of each
+    /// thumbnail always matches the position of its special in the batch.
+    fn make_contact_sheet(&self, thumbnails: &[Option<RgbImage>]) -> RgbImage {
+        const GAP: u32 = 4;
+        let cols = (thumbnails.len() as f64).sqrt().ceil().max(1.0) as u32;
+        let rows = (thumbnails.len() as u32).div_ceil(cols);
+        let cell = self.thumbnail + GAP;
+        let mut sheet = RgbImage::from_pixel(
+            cols * cell + GAP,
+            rows.max(1) * cell + GAP,
+            Rgb([20, 20, 20]),
+        );
+        for (i, thumbnail) in thumbnails.iter().enumerate() {
+            if let Some(thumbnail) = thumbnail {
+                let (col, row) = (i as u32 % cols, i as u32 / cols);
+                // Centre thumbnails which aren't square inside their cell
+                let x = GAP + col * cell + (self.thumbnail - thumbnail.width()) / 2;
+                let y = GAP + row * cell + (self.thumbnail - thumbnail.height()) / 2;
+                imageops::replace(&mut sheet, thumbnail, x as i64, y as i64);
+            }
+        }
+        sheet
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attractors::SprottAttractor;
+
+    #[test]
+    fn indices_can_be_ranges() {
+        assert_eq!(parse_indices("0-3,7,9-9").unwrap(), vec![0, 1, 2, 3, 7, 9]);
+        assert!(parse_indices("1-x").is_err());
+    }
+
+    #[test]
+    fn batch_renders_and_skips_existing_images() {
+        let dir = std::env::temp_dir().join("attractors_batch_test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let specials = dir.join("special.txt");
+        fs::write(
+            &specials,
+            "a=-1.4,b=1.6,c=1.0,d=0.7\na=1.7,b=1.7,c=0.6,d=1.2\n",
+        )
+        .unwrap();
+        let opts = BatchOptions {
+            render: RenderOptions {
+                width: 20,
+                height: 10,
+                iterations: 1_000,
+                ..RenderOptions::default()
+            },
+            specials: specials.to_string_lossy().to_string(),
+            output_dir: dir.to_string_lossy().to_string(),
+            workers: 2,
+            thumbnail: 8,
+            ..BatchOptions::default()
+        };
+        opts.run().unwrap();
+        let rendered = dir.join("a=-1.4_b=1.6_c=1_d=0.7_20x10.png");
+        assert!(rendered.exists());
+        // Attractors with codes are named by their code instead, and otherwise by their own
+        // parameter names
+        let sprott = SprottAttractor::from_code("FIRCDERRPVLD").unwrap();
+        let names = sprott.param_names();
+        assert_eq!(
+            special_filename(&names, &sprott.params(), sprott.code().as_deref(), 20, 10),
+            "FIRCDERRPVLD_20x10.png"
+        );
+        let name = special_filename(&names, &[0.25; 12], None, 20, 10);
+        assert!(name.starts_with("a1=0.25_a2=0.25_") && name.ends_with("_a12=0.25_20x10.png"));
+        let sheet = image::open(dir.join("contact_sheet.png")).unwrap();
+        assert_eq!((sheet.width(), sheet.height()), (2 * 12 + 4, 12 + 4));
+
+        // Running the batch again loads the existing image instead of rendering it again
+        let modified = fs::metadata(&rendered).unwrap().modified().unwrap();
+        opts.run().unwrap();
+        assert_eq!(
+            fs::metadata(&rendered).unwrap().modified().unwrap(),
+            modified
+        );
+
+        // But not if it was rendered with different options
+        let mut light = opts.clone();
+        light.render.colours.lch.set_light_background();
+        light.run().unwrap();
+        assert!(fs::metadata(&rendered).unwrap().modified().unwrap() > modified);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,367 @@
+//! A camera for looking at 3D attractors from any direction.
+//!
+//! The density code only ever looks at the first two coordinates of each position, which is fine
+//! for 2D attractors but throws away all the structure of a 3D attractor. The `Camera` rotates
+//! positions in 3D, then projects them onto the 2D screen (either orthographically or with
+//! perspective) so that they can be binned into a density grid like any other attractor. The
+//! depth of each position is kept, so that nearer parts of the attractor can be shaded
+//! differently to further parts.
+use crate::attractors::{Attractor, Bounds, Histogram};
+use crate::sampling::{downsample, Sampling};
+
+/// How 3D positions get flattened onto the 2D screen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    /// Parallel lines stay parallel, and things don't get smaller as they get further away.
+    Orthographic,
+    /// Things further away are smaller, as if looking through a pinhole camera placed `distance`
+    /// radii away from the centre of the attractor. `distance` must be larger than 1.
+    Perspective { distance: f64 },
+}
+
+/// Rotates, scales, and projects 3D positions onto a 2D screen.
+///
+/// The rotations are applied in the order yaw (about the vertical y axis), then pitch (about the
+/// horizontal x axis), then roll (about the axis pointing out of the screen). All the angles are
+/// in radians. With no rotation the camera looks down the z axis, so that x is to the right and y
+/// is up, exactly like a 2D attractor.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Camera {
+    pub yaw: f64,
+    pub pitch: f64,
+    pub roll: f64,
+    /// Values larger than 1 zoom in, values between 0 and 1 zoom out.
+    pub zoom: f64,
+    pub projection: Projection,
+    /// The point which the camera rotates around, usually the centre of the attractor.
+    pub centre: [f64; 3],
+    /// The radius of a sphere around `centre` which contains the whole attractor. This sphere is
+    /// what fills the screen when `zoom` is 1.
+    pub radius: f64,
+    /// The region of the attractor's own (unrotated) space to draw instead of its bounds, for
+    /// looking closely at one part of it. Only used while the camera `is_default`.
+    pub viewport: Option<Bounds>,
+    /// How much fainter the furthest positions are drawn than the nearest ones, from 0.0 (not
+    /// at all) to 1.0 (the furthest disappear). Depths are only known once the camera has been
+    /// moved, so this is ignored while the camera `is_default`.
+    pub depth_shading: f64,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Camera {
+            yaw: 0.0,
+            pitch: 0.0,
+            roll: 0.0,
+            zoom: 1.0,
+            projection: Projection::Orthographic,
+            centre: [0.0; 3],
+            radius: 1.0,
+            viewport: None,
+            depth_shading: 0.0,
+        }
+    }
+}
+
+/// The result of projecting a history through a `Camera`.
+pub struct Projected {
+    /// How often each pixel was landed on, normalised to [0.0, 1.0] like
+    /// `Attractor::get_densities_with_border`.
+    pub densities: Vec<f64>,
+    /// The average depth of every position which landed on each pixel, where 0.0 is the furthest
+    /// from the camera and 1.0 is the nearest. Pixels which were never landed on have a depth of
+    /// 0.0.
+    pub depths: Vec<f64>,
+}
+
+impl Projected {
+    /// The densities, made fainter by up to `strength` (from 0.0 to 1.0) the further each pixel
+    /// is from the camera, so that the nearer parts of a 3D attractor stand out.
+    pub fn shaded(&self, strength: f64) -> Vec<f64> {
+        self.densities
+            .iter()
+            .zip(self.depths.iter())
+            .map(|(density, depth)| density * (1.0 - strength * (1.0 - depth)))
+            .collect()
+    }
+}
+
+impl Camera {
+    /// True if the camera hasn't been rotated, zoomed, or switched to perspective, in which case
+    /// it shows the attractor exactly as it would be shown without a camera.
+    pub fn is_default(&self) -> bool {
+        self.yaw == 0.0
+            && self.pitch == 0.0
+            && self.roll == 0.0
+            && self.zoom == 1.0
+            && self.projection == Projection::Orthographic
+    }
+
+    /// The region of `attractor`'s space which gets drawn while the camera `is_default`: the
+    /// `viewport` if there is one, otherwise the attractor's own bounds.
+    pub fn bounds_of<A: Attractor>(&self, attractor: &A) -> Bounds {
+        self.viewport.unwrap_or_else(|| attractor.bounds())
+    }
+
+    /// Set the `centre` and `radius` so that every finite position in `history` is in view.
+    /// Positions with only two coordinates are treated as having a z coordinate of 0.
+    pub fn fit(&mut self, history: &[Vec<f64>]) {
+        let mut min = [f64::INFINITY; 3];
+        let mut max = [f64::NEG_INFINITY; 3];
+        include_in_box(&mut min, &mut max, history);
+        self.fit_box(min, max);
+    }
+
+    /// Set the `centre` and `radius` so that the box from `min` to `max` is in view. Nothing
+    /// changes if the box is empty.
+    pub fn fit_box(&mut self, min: [f64; 3], max: [f64; 3]) {
+        if min[0] > max[0] {
+            // There weren't any finite positions
+            return;
+        }
+        for i in 0..3 {
+            self.centre[i] = 0.5 * (min[i] + max[i]);
+        }
+        // Half the diagonal of the bounding box is the radius of a sphere containing the box
+        let radius = (0..3)
+            .map(|i| (max[i] - min[i]).powi(2))
+            .sum::<f64>()
+            .sqrt()
+            * 0.5;
+        self.radius = if radius > 0.0 { radius } else { 1.0 };
+    }
+
+    /// Rotate and project a position, returning the screen x, screen y, and depth. The screen
+    /// coordinates of the sphere described by `centre` and `radius` will be within [-1, 1] when
+    /// `zoom` is 1, and the depth is 0.0 at the back of the sphere and 1.0 at the front.
+    pub fn project(&self, pos: &[f64]) -> [f64; 3] {
+        let [x, y, z] = to_3d(pos);
+        // Move the centre to the origin, and shrink the attractor to fit inside a unit sphere
+        let (x, y, z) = (
+            (x - self.centre[0]) / self.radius,
+            (y - self.centre[1]) / self.radius,
+            (z - self.centre[2]) / self.radius,
+        );
+        // Yaw rotates around the y axis
+        let (sin, cos) = self.yaw.sin_cos();
+        let (x, z) = (x * cos + z * sin, -x * sin + z * cos);
+        // Pitch rotates around the x axis
+        let (sin, cos) = self.pitch.sin_cos();
+        let (y, z) = (y * cos - z * sin, y * sin + z * cos);
+        // Roll rotates around the z axis
+        let (sin, cos) = self.roll.sin_cos();
+        let (x, y) = (x * cos - y * sin, x * sin + y * cos);
+
+        let scale = match self.projection {
+            Projection::Orthographic => self.zoom,
+            // The camera sits at z = distance, looking towards the origin
+            Projection::Perspective { distance } => self.zoom * distance / (distance - z),
+        };
+        [x * scale, y * scale, 0.5 * (z + 1.0)]
+    }
+
+    /// The bounds of the screen, in the coordinates returned by `project`. The shorter side of a
+    /// `width` by `height` screen always covers [-1, 1], so that the attractor isn't stretched.
+    pub fn screen_bounds(&self, width: usize, height: usize) -> Bounds {
+        let aspect = width as f64 / height as f64;
+        let (half_width, half_height) = if aspect >= 1.0 {
+            (aspect, 1.0)
+        } else {
+            (1.0, 1.0 / aspect)
+        };
+        Bounds {
+            xmin: -half_width,
+            xmax: half_width,
+            ymin: -half_height,
+            ymax: half_height,
+        }
+    }
+
+    /// Get the densities of `attractor` as seen through the camera, anti-aliased with
+    /// `sampling`. If the camera hasn't been moved, `bounds_of` the attractor are used so that it
+    /// looks exactly the same as it would without a camera (apart from any `viewport`). Otherwise
+    /// the camera is fitted to the attractor's history first, and shaded by depth with
+    /// `depth_shading`.
+    pub fn get_densities<A: Attractor>(
+        &mut self,
+        attractor: &mut A,
+        width: usize,
+        height: usize,
+        perc_border: f64,
+        sampling: &Sampling,
+    ) -> Vec<f64> {
+        if self.is_default() {
+            let bounds = self.bounds_of(attractor);
+            return attractor.get_densities_within(bounds, width, height, perc_border, sampling);
+        }
+        self.fit(attractor.history());
+        self.densities(attractor.history(), width, height, perc_border, sampling)
+            .shaded(self.depth_shading)
+    }
+
+    /// Project every position in `history` and bin them into a `width` by `height` density grid
+    /// (anti-aliased with `sampling`), keeping track of the average depth of each pixel.
+    pub fn densities(
+        &self,
+        history: &[Vec<f64>],
+        width: usize,
+        height: usize,
+        perc_border: f64,
+        sampling: &Sampling,
+    ) -> Projected {
+        let mut grid = self.depth_histogram(width, height, perc_border, sampling);
+        for pos in history {
+            grid.add(pos);
+        }
+        grid.projected()
+    }
+
+    /// An empty `width` by `height` density grid which positions can be projected into one at a
+    /// time, like `densities` but without needing every position in memory at once.
+    pub fn depth_histogram(
+        &self,
+        width: usize,
+        height: usize,
+        perc_border: f64,
+        sampling: &Sampling,
+    ) -> DepthHistogram {
+        let histogram = Histogram::supersampled(
+            width,
+            height,
+            self.screen_bounds(width, height),
+            perc_border,
+            sampling,
+        );
+        DepthHistogram {
+            camera: self.clone(),
+            depth_sums: vec![0.0; histogram.counts.len()],
+            histogram,
+            sampling: *sampling,
+        }
+    }
+}
+
+/// A density grid which positions are projected into through a camera, keeping track of the
+/// total depth of each pixel. See `Camera::depth_histogram`.
+pub struct DepthHistogram {
+    camera: Camera,
+    histogram: Histogram,
+    depth_sums: Vec<f64>,
+    sampling: Sampling,
+}
+
+impl DepthHistogram {
+    /// Project one position and add it to the grid.
+    pub fn add(&mut self, pos: &[f64]) {
+        let projected = self.camera.project(pos);
+        if let Some(weights) = self.histogram.splat_weights(&projected) {
+            for (i, weight) in weights {
+                self.histogram.counts[i] += weight;
+                self.depth_sums[i] += weight * projected[2];
+            }
+        }
+    }
+
+    /// The densities and average depths of every position added so far.
+    pub fn projected(self) -> Projected {
+        let factor = self.sampling.supersample.max(1);
+        let depth_sums = downsample(
+            &self.depth_sums,
+            self.histogram.width,
+            self.histogram.height,
+            factor,
+            self.sampling.filter,
+        );
+        let histogram = self.histogram.downsampled(factor, self.sampling.filter);
+        let depths = depth_sums
+            .iter()
+            .zip(histogram.counts.iter())
+            .map(|(sum, count)| if *count > 0.0 { sum / count } else { 0.0 })
+            .collect();
+        Projected {
+            densities: histogram.normalised(),
+            depths,
+        }
+    }
+}
+
+/// Grow the box from `min` to `max` so that it contains every finite position in `positions`.
+/// Positions with only two coordinates are treated as having a z coordinate of 0.
+pub fn include_in_box<P: AsRef<[f64]>>(
+    min: &mut [f64; 3],
+    max: &mut [f64; 3],
+    positions: impl IntoIterator<Item = P>,
+) {
+    for pos in positions.into_iter().map(|p| to_3d(p.as_ref())) {
+        if pos.iter().all(|c| c.is_finite()) {
+            for i in 0..3 {
+                min[i] = min[i].min(pos[i]);
+                max[i] = max[i].max(pos[i]);
+            }
+        }
+    }
+}
+
+/// Pad 2D positions out to 3D with a z coordinate of 0.
+fn to_3d(pos: &[f64]) -> [f64; 3] {
+    [pos[0], pos[1], pos.get(2).copied().unwrap_or(0.0)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::FRAC_PI_2;
+
+    #[test]
+    fn rotations_move_the_axes_around() {
+        let camera = Camera::default();
+        assert_eq!(camera.project(&[1.0, 0.0, 0.0]), [1.0, 0.0, 0.5]);
+
+        // A quarter turn of yaw brings the z axis to the right hand side of the screen
+        let camera = Camera {
+            yaw: FRAC_PI_2,
+            ..Camera::default()
+        };
+        let [x, y, _depth] = camera.project(&[0.0, 0.0, 1.0]);
+        assert!((x - 1.0).abs() < 1e-12 && y.abs() < 1e-12);
+
+        // A quarter turn of pitch brings the y axis towards the camera
+        let camera = Camera {
+            pitch: FRAC_PI_2,
+            ..Camera::default()
+        };
+        let [_x, _y, depth] = camera.project(&[0.0, 1.0, 0.0]);
+        assert!((depth - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn perspective_makes_near_things_bigger() {
+        let camera = Camera {
+            projection: Projection::Perspective { distance: 3.0 },
+            ..Camera::default()
+        };
+        let near = camera.project(&[0.5, 0.0, 0.5]);
+        let far = camera.project(&[0.5, 0.0, -0.5]);
+        assert!(near[0] > far[0]);
+        assert!(near[2] > far[2]);
+    }
+
+    #[test]
+    fn depth_shading_fades_further_pixels() {
+        let camera = Camera {
+            yaw: 0.1,
+            ..Camera::default()
+        };
+        // Two equally dense pixels, one at the front and one at the back
+        let history = vec![vec![-0.5, 0.0, 1.0], vec![0.5, 0.0, -1.0]];
+        let projected = camera.densities(&history, 2, 1, 0.0, &Sampling::default());
+        assert_eq!(projected.shaded(0.0), projected.densities);
+        let shaded = projected.shaded(0.5);
+        let (near, far) = if projected.depths[0] > projected.depths[1] {
+            (shaded[0], shaded[1])
+        } else {
+            (shaded[1], shaded[0])
+        };
+        assert!(near > far && far > 0.0);
+    }
+}
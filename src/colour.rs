@@ -0,0 +1,401 @@
+//! Converting densities into colours.
+use std::fmt::Display;
+use std::sync::OnceLock;
+
+use image::{ImageBuffer, RgbImage};
+
+use crate::palette::Palette;
+use crate::tone::ToneMap;
+
+/// Unpack a bit-packed u32 colour into it's alpha, red, green, and blue components.
+pub fn u32_to_argb(packed: u32) -> (u8, u8, u8, u8) {
+    let a = ((0xFF_00_00_00 & packed) >> 24) as u8;
+    let r = ((0x00_FF_00_00 & packed) >> 16) as u8;
+    let g = ((0x00_00_FF_00 & packed) >> 8) as u8;
+    let b = (0x00_00_00_FF & packed) as u8;
+    return (a, r, g, b);
+}
+
+/// Pack alpha, red, green, and blue components into a single u32, as used by minifb.
+pub fn argb_to_u32(a: u8, r: u8, g: u8, b: u8) -> u32 {
+    let (a, r, g, b) = (a as u32, r as u32, g as u32, b as u32);
+    (a << 24) | (r << 16) | (g << 8) | b
+}
+
+/// The CIE XYZ coordinates of the D65 white point, which sRGB is defined relative to.
+const D65_WHITE: [f64; 3] = [0.95047, 1.0, 1.08883];
+/// The largest chroma (in CIE units) which `lch_to_u32` maps a chroma of 1.0 to. The most
+/// colourful sRGB colours have a chroma of about 132.
+const MAX_CHROMA: f64 = 132.0;
+
+/// Convert a CIE L*C*h colour (with L in [0, 100], C in [0, ~132], and h in degrees) to linear
+/// sRGB, going via CIE L*a*b* and CIE XYZ. The result is only in [0, 1] if the colour is inside
+/// the sRGB gamut. See <http://www.brucelindbloom.com/index.html?Math.html> for the maths.
+pub fn lch_to_linear_srgb(l: f64, c: f64, h: f64) -> [f64; 3] {
+    // LCh is just Lab in polar coordinates
+    let (sin, cos) = h.to_radians().sin_cos();
+    let (a, b) = (c * cos, c * sin);
+
+    // Lab -> XYZ
+    const EPSILON: f64 = 216.0 / 24389.0;
+    const KAPPA: f64 = 24389.0 / 27.0;
+    let fy = (l + 16.0) / 116.0;
+    let fx = fy + a / 500.0;
+    let fz = fy - b / 200.0;
+    let finv = |f: f64| {
+        if f.powi(3) > EPSILON {
+            f.powi(3)
+        } else {
+            (116.0 * f - 16.0) / KAPPA
+        }
+    };
+    let y = if l > KAPPA * EPSILON {
+        fy.powi(3)
+    } else {
+        l / KAPPA
+    };
+    let (x, y, z) = (
+        finv(fx) * D65_WHITE[0],
+        y * D65_WHITE[1],
+        finv(fz) * D65_WHITE[2],
+    );
+
+    // XYZ -> linear sRGB
+    [
+        3.2404542 * x - 1.5371385 * y - 0.4985314 * z,
+        -0.9692660 * x + 1.8760108 * y + 0.0415560 * z,
+        0.0556434 * x - 0.2040259 * y + 1.0572252 * z,
+    ]
+}
+
+/// Apply the sRGB transfer function to a linear sRGB channel in [0, 1], returning a byte.
+fn linear_to_srgb_u8(c: f64) -> u8 {
+    let c = c.clamp(0.0, 1.0);
+    let encoded = if c <= 0.0031308 {
+        12.92 * c
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+    (encoded * 255.0).round() as u8
+}
+
+/// True if every channel of a linear sRGB colour is (very nearly) within [0, 1].
+fn in_gamut(rgb: &[f64; 3]) -> bool {
+    rgb.iter().all(|c| (-1e-6..=1.0 + 1e-6).contains(c))
+}
+
+/// The most colourful chroma which is still inside the sRGB gamut for a lightness `l` and hue
+/// `h` (in the same units as `lch_to_linear_srgb`), found by binary search.
+fn max_chroma_in_gamut(l: f64, h: f64) -> f64 {
+    let (mut lo, mut hi) = (0.0, 2.0 * MAX_CHROMA);
+    for _ in 0..24 {
+        let mid = 0.5 * (lo + hi);
+        if in_gamut(&lch_to_linear_srgb(l, mid, h)) {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    lo
+}
+
+/// The largest in-gamut chroma for every whole number lightness in [0, 100] and every whole
+/// degree of hue in [0, 360], calculated the first time it's needed. Searching for the gamut
+/// boundary for every pixel of every frame would be far too slow.
+fn chroma_table() -> &'static Vec<f64> {
+    static TABLE: OnceLock<Vec<f64>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        (0..=100)
+            .flat_map(|l| (0..=360).map(move |h| max_chroma_in_gamut(l as f64, h as f64)))
+            .collect()
+    })
+}
+
+/// Look up the largest in-gamut chroma for lightness `l` in [0, 100] and hue `h` in [0, 360),
+/// interpolating between the entries in `chroma_table`.
+fn max_chroma(l: f64, h: f64) -> f64 {
+    let table = chroma_table();
+    let (l0, h0) = (l.floor().min(99.0), h.floor().min(359.0));
+    let (tl, th) = (l - l0, h - h0);
+    let at = |l: f64, h: f64| table[l as usize * 361 + h as usize];
+    let low = at(l0, h0) * (1.0 - th) + at(l0, h0 + 1.0) * th;
+    let high = at(l0 + 1.0, h0) * (1.0 - th) + at(l0 + 1.0, h0 + 1.0) * th;
+    low * (1.0 - tl) + high * tl
+}
+
+/// Convert CIE Light Chroma Hue to a bit-packed u32 value. https://css.land/lch/
+/// l, c, and h are all within [0, 1]:
+/// - Light -> 0.0 is black, 0.5 is full colour, 1.0 is white
+/// - Chroma -> 0.0 is grey, 1.0 is full colourfulness (values above 1.0 are allowed)
+/// - Hue -> 0.0 to 1.0 is: pink, red, orange, yellow, green, light blue, dark blue, purple,
+///   pink. Values outside [0, 1] wrap around.
+///
+/// Most combinations of light and chroma can't be shown on a screen, so colours outside the sRGB
+/// gamut are mapped into it by reducing the chroma (keeping the light and hue the same) until
+/// the colour fits. That way equal steps in light or hue always look like equal steps.
+pub fn lch_to_u32(l: f64, c: f64, h: f64) -> u32 {
+    let l = l.clamp(0.0, 1.0) * 100.0;
+    let h = h.rem_euclid(1.0) * 360.0;
+    let c = (c.max(0.0) * MAX_CHROMA).min(max_chroma(l, h));
+    let [r, g, b] = lch_to_linear_srgb(l, c, h);
+    argb_to_u32(
+        0,
+        linear_to_srgb_u8(r),
+        linear_to_srgb_u8(g),
+        linear_to_srgb_u8(b),
+    )
+}
+
+/// Contains the constants that get multiplied by the value at each pixel in order to convert that
+/// scalar value to a color in CIE LCh space (see `lch_to_u32`). The value is the tone of the
+/// pixel given by a `ToneMap`, and the conversion is done as:
+/// ```
+/// light_component  = tone * light_slope  + light_intercept
+/// chroma_component = tone * chroma_slope + chroma_intercept
+/// hue_component    = tone * hue_slope    + hue_intercept
+/// ```
+/// For example
+#[derive(Debug, Clone, PartialEq)]
+pub struct LchParams {
+    pub light_intercept: f64,
+    /// Light -> 0.0 is black, 0.5 is full color, 1.0 is  white
+    pub light_slope: f64,
+    pub chroma_intercept: f64,
+    /// Chroma -> 0.0 is grey, 1.0 is full colourfulness
+    pub chroma_slope: f64,
+    pub hue_intercept: f64,
+    /// Hue -> 0.0 to 1.0 is: pink, red, orange, yellow, green, light blue, dark blue, purple, pink
+    pub hue_slope: f64,
+}
+
+impl Display for LchParams {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "LchParams:\n  light = val * {light_slope:+.4} + {light_intercept:+.4}\n  chroma = val * {chroma_slope:+.4} + {chroma_intercept:+.4}\n  hue = val * {hue_slope:+.4} + {hue_intercept:+.4}",
+               light_slope=self.light_slope,
+               light_intercept=self.light_intercept,
+               chroma_slope=self.chroma_slope,
+               chroma_intercept=self.chroma_intercept,
+               hue_slope=self.hue_slope,
+               hue_intercept=self.hue_intercept,
+        )
+    }
+}
+
+impl Default for LchParams {
+    /// These parameters have been manually tuned
+    fn default() -> Self {
+        LchParams {
+            light_intercept: 0.0, // no touchie
+            light_slope: 1.0,
+            chroma_intercept: 1.5,
+            chroma_slope: 0.2,
+            hue_intercept: 0.45,
+            hue_slope: 0.15, // values over 0.5 give a bit of a blowout effect
+        }
+    }
+}
+
+impl LchParams {
+    /// Change the light and chroma so the attractor is drawn on a dark background.
+    pub fn set_dark_background(&mut self) {
+        self.light_intercept = 0.0;
+        self.light_slope = 1.0;
+        self.chroma_intercept = 1.5;
+        self.chroma_slope = 0.2;
+    }
+
+    /// Change the light and chroma so the attractor is drawn on a light background.
+    pub fn set_light_background(&mut self) {
+        self.light_intercept = 1.0;
+        self.light_slope = -1.0;
+        self.chroma_intercept = 0.7;
+        self.chroma_slope = 1.5;
+    }
+
+    /// Convert a tone in [0.0, 1.0] (see `ToneMap`) to a bit-packed colour.
+    pub fn to_u32(&self, tone: f64) -> u32 {
+        lch_to_u32(
+            tone * self.light_slope + self.light_intercept,
+            tone * self.chroma_slope + self.chroma_intercept,
+            tone * self.hue_slope + self.hue_intercept,
+        )
+    }
+}
+
+/// The number of entries in a `ColourMap`.
+const COLOUR_MAP_SIZE: usize = 4096;
+
+/// A lookup table of the colours given by `ColourScheme::to_u32`, since converting from LCh is too
+/// slow to do for every pixel of every frame.
+pub struct ColourMap {
+    table: Vec<u32>,
+}
+
+impl ColourMap {
+    /// Build a `ColourMap` from `colour`, which converts a tone in [0.0, 1.0] to a bit-packed
+    /// colour.
+    pub fn new(colour: impl Fn(f64) -> u32) -> Self {
+        let table = (0..COLOUR_MAP_SIZE)
+            .map(|i| colour(i as f64 / (COLOUR_MAP_SIZE - 1) as f64))
+            .collect();
+        ColourMap { table }
+    }
+
+    /// The colour of a tone in [0.0, 1.0].
+    pub fn get(&self, tone: f64) -> u32 {
+        self.table[(tone.clamp(0.0, 1.0) * (COLOUR_MAP_SIZE - 1) as f64).round() as usize]
+    }
+}
+
+/// How densities get coloured: a `ToneMap` turns them into tones, which are then coloured either
+/// with `LchParams`, or with a gradient `Palette` if one has been chosen.
+#[derive(Debug, Clone, Default)]
+pub struct ColourScheme {
+    pub lch: LchParams,
+    /// Used instead of `lch` if it isn't `None`.
+    pub palette: Option<Palette>,
+    pub tone: ToneMap,
+}
+
+impl Display for ColourScheme {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.palette {
+            Some(palette) => write!(f, "{}\n{}", palette, self.tone),
+            None => write!(f, "{}\n{}", self.lch, self.tone),
+        }
+    }
+}
+
+impl ColourScheme {
+    /// Convert a tone in [0.0, 1.0] (see `ToneMap`) to a bit-packed colour.
+    pub fn to_u32(&self, tone: f64) -> u32 {
+        match &self.palette {
+            Some(palette) => palette.to_u32(tone),
+            None => self.lch.to_u32(tone),
+        }
+    }
+
+    /// Build a `ColourMap` for this colour scheme.
+    pub fn colour_map(&self) -> ColourMap {
+        ColourMap::new(|tone| self.to_u32(tone))
+    }
+
+    /// Tone map every density and convert it to a bit-packed colour.
+    pub fn densities_to_u32(&self, densities: &[f64]) -> Vec<u32> {
+        let colour_map = self.colour_map();
+        self.tone
+            .apply(densities)
+            .into_iter()
+            .map(|tone| colour_map.get(tone))
+            .collect()
+    }
+
+    /// Switch to the next (or previous, if `forwards` is false) palette in `palettes`. Going
+    /// past either end switches back to the `LchParams`.
+    pub fn cycle_palette(&mut self, palettes: &[Palette], forwards: bool) {
+        let current = self
+            .palette
+            .as_ref()
+            .and_then(|palette| palettes.iter().position(|p| p.name == palette.name));
+        // Index 0 is the LchParams, and the palettes start from index 1
+        let num_options = palettes.len() + 1;
+        let idx = current.map_or(0, |i| i + 1);
+        let idx = if forwards {
+            (idx + 1) % num_options
+        } else {
+            (idx + num_options - 1) % num_options
+        };
+        self.palette = idx.checked_sub(1).map(|i| palettes[i].clone());
+    }
+}
+
+/// Convert a `width` by `height` grid of densities into an image, using `colours` to colour
+/// each pixel.
+pub fn densities_to_image(
+    densities: &[f64],
+    width: usize,
+    height: usize,
+    colours: &ColourScheme,
+) -> RgbImage {
+    let mut image: RgbImage = ImageBuffer::new(width as u32, height as u32);
+    for (i, colour) in colours.densities_to_u32(densities).into_iter().enumerate() {
+        let (_a, r, g, b) = u32_to_argb(colour);
+        image.put_pixel(
+            (i % width) as u32,
+            (i / width) as u32,
+            image::Rgb([r, g, b]),
+        );
+    }
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lch_matches_known_srgb_colours() {
+        // White, black, and mid grey have no chroma
+        assert_eq!(u32_to_argb(lch_to_u32(1.0, 0.0, 0.0)), (0, 255, 255, 255));
+        assert_eq!(u32_to_argb(lch_to_u32(0.0, 0.0, 0.0)), (0, 0, 0, 0));
+        // sRGB red is about LCh(53.24, 104.55, 40.0), which is right on the edge of the gamut so
+        // the gamut mapping might take off a little chroma
+        let (_a, r, g, b) = u32_to_argb(lch_to_u32(0.5324, 104.55 / MAX_CHROMA, 40.0 / 360.0));
+        assert!(r >= 250 && g <= 15 && b <= 15, "{:?}", (r, g, b));
+    }
+
+    #[test]
+    fn colour_maps_match_the_exact_colours() {
+        let lch = LchParams::default();
+        let colour_map = ColourScheme::default().colour_map();
+        for tone in [0.0, 1e-3, 0.01, 0.5, 1.0] {
+            let (_, r1, g1, b1) = u32_to_argb(colour_map.get(tone));
+            let (_, r2, g2, b2) = u32_to_argb(lch.to_u32(tone));
+            for (c1, c2) in [(r1, r2), (g1, g2), (b1, b2)] {
+                assert!((c1 as i32 - c2 as i32).abs() <= 2, "tone {}", tone);
+            }
+        }
+    }
+
+    #[test]
+    fn palettes_cycle_back_to_lch() {
+        let palettes = Palette::built_in();
+        let mut colours = ColourScheme::default();
+        colours.cycle_palette(&palettes, true);
+        assert_eq!(colours.palette.as_ref(), Some(&palettes[0]));
+        colours.cycle_palette(&palettes, false);
+        assert!(colours.palette.is_none());
+        colours.cycle_palette(&palettes, false);
+        assert_eq!(colours.palette.as_ref(), palettes.last());
+        // The darkest density uses the first colour of the palette
+        let (_a, r, g, b) = u32_to_argb(colours.colour_map().get(0.0));
+        assert_eq!([r, g, b], palettes.last().unwrap().colour_at(0.0));
+    }
+
+    #[test]
+    fn out_of_gamut_colours_keep_their_lightness() {
+        for h in 0..20 {
+            let h = h as f64 / 20.0;
+            let (_a, r, g, b) = u32_to_argb(lch_to_u32(0.7, 5.0, h));
+            // The relative luminance of L = 70 is about 0.41, whatever the hue
+            let lum = 0.2126 * srgb_u8_to_linear(r)
+                + 0.7152 * srgb_u8_to_linear(g)
+                + 0.0722 * srgb_u8_to_linear(b);
+            assert!(
+                (lum - 0.4075).abs() < 0.01,
+                "hue {} has luminance {}",
+                h,
+                lum
+            );
+        }
+    }
+
+    fn srgb_u8_to_linear(c: u8) -> f64 {
+        let c = c as f64 / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    }
+}
@@ -0,0 +1,479 @@
+//! Numbers which describe how an attractor behaves, without having to look at it.
+//!
+//! The [largest Lyapunov exponent](https://en.wikipedia.org/wiki/Lyapunov_exponent) measures how
+//! quickly two positions which start very close together drift apart. If it's positive then tiny
+//! differences grow exponentially, which is the hallmark of chaos (and of the interesting,
+//! wispy attractors). If it's negative then nearby positions get pulled together, so the
+//! attractor collapses onto a fixed point or a loop.
+//!
+//! The [fractal dimension](https://en.wikipedia.org/wiki/Fractal_dimension) measures how "filled
+//! in" an attractor is. A loop has a dimension of 1, a solid patch of the plane has a dimension
+//! of 2, and the wispy attractors are somewhere in between. Two estimators are available, both
+//! of which fit a straight line on a log-log plot and report how straight that line is:
+//! - box-counting, which counts how many boxes of a grid are landed on as the grid gets finer.
+//! - [correlation dimension](https://en.wikipedia.org/wiki/Correlation_dimension) (the
+//!   Grassberger-Procaccia algorithm), which counts how many pairs of positions are closer than
+//!   a distance `r` as `r` gets smaller.
+//!
+//! Finally, `classify` puts all of this together to decide what sort of thing an attractor has
+//! settled into after a short burn-in: a point, a loop, or something chaotic.
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::attractors::Attractor;
+
+/// How far apart the two trajectories are kept while estimating the Lyapunov exponent.
+const SEPARATION: f64 = 1e-8;
+/// The number of renormalisations ignored at the start, while the separation between the two
+/// trajectories swings round to point in the direction of fastest growth.
+const LYAPUNOV_TRANSIENT: usize = 100;
+
+/// Estimate the largest Lyapunov exponent of `attractor` (per iteration, in nats) using the
+/// two-trajectory renormalisation method.
+///
+/// Two copies of the attractor are started from the last position in its history, one of them
+/// nudged `SEPARATION` away along the x axis. After every step the distance `d` between them is
+/// measured, and the nudged copy is pulled back along the line between them so that they're
+/// `SEPARATION` apart again. The exponent is then the average of:
+/// ```
+/// ln(d / SEPARATION)
+/// ```
+/// over `num_steps` steps. The attractor itself isn't changed.
+///
+/// For flows, one iteration is one step of size `dt`, so divide by `dt` to get the exponent per
+/// unit of time.
+///
+/// Returns `None` if the attractor escapes to infinity.
+pub fn lyapunov_exponent<A: Attractor>(attractor: &A, num_steps: usize) -> Option<f64> {
+    let mut base = attractor.empty_copy();
+    let mut start = match attractor.history().last() {
+        Some(pos) => pos.clone(),
+        None => {
+            // Let the attractor settle onto itself before starting
+            base.step(LYAPUNOV_TRANSIENT);
+            base.history().last()?.clone()
+        }
+    };
+    let mut nudged = start.clone();
+    nudged[0] += SEPARATION;
+    let mut other = attractor.empty_copy();
+
+    let mut sum = 0.0;
+    let mut num_iterations = 0;
+    for i in 0..(LYAPUNOV_TRANSIENT + num_steps) {
+        base.set_history(vec![start]);
+        other.set_history(vec![nudged]);
+        base.step(1);
+        other.step(1);
+        let next_base = base.history().last()?.clone();
+        let next_other = other.history().last()?.clone();
+
+        let dist = next_base
+            .iter()
+            .zip(next_other.iter())
+            .map(|(b, o)| (o - b).powi(2))
+            .sum::<f64>()
+            .sqrt();
+        if !dist.is_finite() || next_base.iter().any(|c| !c.is_finite()) {
+            return None;
+        }
+        // If the trajectories have merged completely they're converging as fast as possible
+        let dist = dist.max(f64::MIN_POSITIVE);
+        if i >= LYAPUNOV_TRANSIENT {
+            sum += (dist / SEPARATION).ln();
+            num_iterations += 1;
+        }
+
+        // Pull the nudged trajectory back towards the base trajectory
+        nudged = next_base
+            .iter()
+            .zip(next_other.iter())
+            .map(|(b, o)| b + (o - b) * SEPARATION / dist)
+            .collect();
+        if dist == f64::MIN_POSITIVE {
+            nudged[0] += SEPARATION;
+        }
+        start = next_base;
+    }
+    Some(sum / num_iterations.max(1) as f64)
+}
+
+/// A short description of the Lyapunov exponent of `attractor`, for use in `Display` impls.
+pub fn describe_lyapunov<A: Attractor>(attractor: &A) -> String {
+    match lyapunov_exponent(attractor, 2_000) {
+        Some(exp) if exp > 0.0 => format!("lyapunov exponent = {:+.4} (chaotic)", exp),
+        Some(exp) => format!("lyapunov exponent = {:+.4} (not chaotic)", exp),
+        None => "lyapunov exponent = none (diverges)".to_string(),
+    }
+}
+
+/// A fractal dimension, along with how well the straight line it came from fits the data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DimensionEstimate {
+    /// The slope of the line of best fit.
+    pub dimension: f64,
+    /// The coefficient of determination of the line of best fit, where 1.0 is a perfect fit. Below
+    /// about 0.95 the points don't really lie on a line, so the dimension isn't trustworthy.
+    pub r_squared: f64,
+    /// The (log scale, log count) points which the line was fitted to.
+    pub points: Vec<(f64, f64)>,
+}
+
+impl fmt::Display for DimensionEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:.3} (r^2={:.3})", self.dimension, self.r_squared)
+    }
+}
+
+/// At least `num_samples` positions of `attractor` to estimate its dimensions from. This is its
+/// history if that's long enough, and otherwise the positions visited by a copy of it which
+/// carries on from its latest position (like when it's being accumulated into a histogram, and
+/// only keeps that one position).
+pub fn dimension_samples<A: Attractor>(attractor: &A, num_samples: usize) -> Cow<'_, [Vec<f64>]> {
+    if attractor.history().len() >= num_samples {
+        return Cow::Borrowed(attractor.history());
+    }
+    let mut copy = attractor.empty_copy();
+    if let Some(last) = attractor.history().last() {
+        copy.set_history(vec![last.clone()]);
+    }
+    copy.step(num_samples);
+    let mut history = copy.history().clone();
+    history.drain(..history.len() - num_samples);
+    Cow::Owned(history)
+}
+
+/// Fit the least squares line `y = slope * x + intercept` through `points`, returning the slope
+/// and the coefficient of determination. Returns `None` if there are fewer than 3 points or all
+/// the x values are the same.
+fn fit_line(points: &[(f64, f64)]) -> Option<(f64, f64)> {
+    if points.len() < 3 {
+        return None;
+    }
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_y = points.iter().map(|p| p.1).sum::<f64>() / n;
+    let sxx: f64 = points.iter().map(|p| (p.0 - mean_x).powi(2)).sum();
+    let sxy: f64 = points.iter().map(|p| (p.0 - mean_x) * (p.1 - mean_y)).sum();
+    let syy: f64 = points.iter().map(|p| (p.1 - mean_y).powi(2)).sum();
+    if sxx == 0.0 {
+        return None;
+    }
+    let slope = sxy / sxx;
+    // A perfectly flat line is a perfect fit
+    let r_squared = if syy == 0.0 {
+        1.0
+    } else {
+        sxy * sxy / (sxx * syy)
+    };
+    Some((slope, r_squared))
+}
+
+/// About `num_samples` of the finite positions in `history`, taken evenly from the whole history
+/// and scaled so that they all fit inside the unit cube. Every axis is scaled by the same amount,
+/// so that the shape of the attractor isn't distorted. Only the samples are copied, since this
+/// gets called on histories of millions of positions.
+fn normalised_samples(history: &[Vec<f64>], num_samples: usize) -> Vec<Vec<f64>> {
+    let finite = |pos: &&Vec<f64>| pos.iter().all(|c| c.is_finite());
+    let stride = (history.iter().filter(finite).count() / num_samples.max(1)).max(1);
+    let samples: Vec<&Vec<f64>> = history.iter().filter(finite).step_by(stride).collect();
+    let Some(first) = samples.first() else {
+        return vec![];
+    };
+    let dims = first.len();
+    let mut min = vec![f64::INFINITY; dims];
+    let mut max = vec![f64::NEG_INFINITY; dims];
+    for pos in samples.iter() {
+        for i in 0..dims {
+            min[i] = min[i].min(pos[i]);
+            max[i] = max[i].max(pos[i]);
+        }
+    }
+    let extent = (0..dims).map(|i| max[i] - min[i]).fold(0.0, f64::max);
+    let extent = if extent > 0.0 { extent } else { 1.0 };
+    samples
+        .iter()
+        .map(|pos| (0..dims).map(|i| (pos[i] - min[i]) / extent).collect())
+        .collect()
+}
+
+/// Estimate the box-counting dimension of the positions in `history`.
+///
+/// `num_samples` positions are taken evenly from `history`, and covered by grids with `2^k` boxes
+/// along each side, and the number of boxes `N(k)` containing at least one position is counted. The dimension is the slope of:
+/// ```
+/// log(N(k)) against log(2^k)
+/// ```
+/// Grids much finer than the number of samples would just count every sample separately, so `k`
+/// only goes up to `log2(num_samples) / 2`. A hundred thousand samples gives grids up to 256 by
+/// 256.
+///
+/// Returns `None` if there are too few positions to fit a line.
+pub fn box_counting_dimension(
+    history: &[Vec<f64>],
+    num_samples: usize,
+) -> Option<DimensionEstimate> {
+    let positions = normalised_samples(history, num_samples);
+    let max_k = ((positions.len() as f64).log2() / 2.0).floor().min(12.0) as u32;
+    let points: Vec<(f64, f64)> = (1..=max_k)
+        .map(|k| {
+            let boxes_per_side = (1u64 << k) as f64;
+            let occupied: HashSet<Vec<u64>> = positions
+                .iter()
+                .map(|pos| {
+                    pos.iter()
+                        // Keep positions on the top edge inside the last box
+                        .map(|c| ((c * boxes_per_side) as u64).min((1 << k) - 1))
+                        .collect()
+                })
+                .collect();
+            (boxes_per_side.ln(), (occupied.len() as f64).ln())
+        })
+        .collect();
+    let (dimension, r_squared) = fit_line(&points)?;
+    Some(DimensionEstimate {
+        dimension,
+        r_squared,
+        points,
+    })
+}
+
+/// Estimate the correlation dimension of the positions in `history` with the
+/// Grassberger-Procaccia algorithm.
+///
+/// `num_samples` positions are taken evenly from `history` (so that neighbouring samples aren't
+/// just consecutive steps), and the fraction `C(r)` of pairs of samples closer together than `r`
+/// is found for a range of radii. The dimension is the slope of:
+/// ```
+/// log(C(r)) against log(r)
+/// ```
+/// Only radii where `C(r)` is between 0.001 and 0.3 are used, since very small radii contain too
+/// few pairs to be reliable and large radii are affected by the edges of the attractor. The
+/// number of pairs grows like `num_samples^2`, so a thousand or two samples is plenty.
+///
+/// Returns `None` if there are too few positions to fit a line.
+pub fn correlation_dimension(
+    history: &[Vec<f64>],
+    num_samples: usize,
+) -> Option<DimensionEstimate> {
+    let samples = normalised_samples(history, num_samples);
+    let mut distances = Vec::with_capacity(samples.len() * samples.len() / 2);
+    for (i, a) in samples.iter().enumerate() {
+        for b in samples[i + 1..].iter() {
+            let dist_sq: f64 = a.iter().zip(b.iter()).map(|(a, b)| (a - b).powi(2)).sum();
+            distances.push(dist_sq.sqrt());
+        }
+    }
+    if distances.is_empty() {
+        return None;
+    }
+    distances.sort_by(|a, b| a.partial_cmp(b).expect("Distances are always finite"));
+
+    let num_pairs = distances.len() as f64;
+    // Halve the radius by a factor of sqrt(2) each time, starting from the diagonal of the cube
+    let points: Vec<(f64, f64)> = (0..60)
+        .map(|i| 2f64.sqrt().powi(1 - i))
+        .filter_map(|r| {
+            let fraction = distances.partition_point(|d| *d < r) as f64 / num_pairs;
+            (0.001..=0.3)
+                .contains(&fraction)
+                .then(|| (r.ln(), fraction.ln()))
+        })
+        .collect();
+    let (dimension, r_squared) = fit_line(&points)?;
+    Some(DimensionEstimate {
+        dimension,
+        r_squared,
+        points,
+    })
+}
+
+/// What an attractor settles into after its burn-in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Classification {
+    /// The positions escape to infinity.
+    Divergent,
+    /// Every position lands on the same point.
+    FixedPoint,
+    /// The positions cycle through the same `period` points over and over again. Flows and very
+    /// long cycles don't repeat exactly, so their period is `None`.
+    LimitCycle { period: Option<usize> },
+    /// The positions never repeat, but nearby positions don't drift apart either. These usually
+    /// look like smooth closed curves.
+    QuasiPeriodic,
+    /// Nearby positions drift apart exponentially. These are the interesting ones.
+    Chaotic,
+}
+
+impl Classification {
+    /// True if the attractor is worth looking at, which is only the case for chaotic attractors.
+    pub fn is_interesting(&self) -> bool {
+        *self == Classification::Chaotic
+    }
+}
+
+impl fmt::Display for Classification {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Classification::Divergent => write!(f, "divergent"),
+            Classification::FixedPoint => write!(f, "fixed point"),
+            Classification::LimitCycle { period: Some(p) } => {
+                write!(f, "limit cycle (period {})", p)
+            }
+            Classification::LimitCycle { period: None } => write!(f, "limit cycle"),
+            Classification::QuasiPeriodic => write!(f, "quasi-periodic"),
+            Classification::Chaotic => write!(f, "chaotic"),
+        }
+    }
+}
+
+/// Positions further than this from the origin are assumed to be escaping to infinity.
+const ESCAPE_RADIUS: f64 = 1e6;
+/// The longest cycle that `classify` looks for.
+const MAX_PERIOD: usize = 256;
+/// Lyapunov exponents closer to zero than this are treated as zero.
+const LYAPUNOV_TOLERANCE: f64 = 1e-3;
+
+/// Classify what `attractor` settles into, by running a copy of it for `burn_in` steps and then
+/// looking at the next `num_steps` positions. The attractor itself isn't changed.
+///
+/// The checks are done in this order:
+/// 1. Any position which isn't finite or is outside `ESCAPE_RADIUS` means it's `Divergent`.
+/// 2. If the last positions are all (nearly) the same, it's a `FixedPoint`.
+/// 3. If the last positions repeat every `p <= MAX_PERIOD` steps, it's a `LimitCycle`.
+/// 4. Otherwise the Lyapunov exponent decides: positive is `Chaotic`, zero is `QuasiPeriodic`,
+///    and negative is a `LimitCycle` which is too long (or too inexact) to have found the period.
+pub fn classify<A: Attractor>(attractor: &A, burn_in: usize, num_steps: usize) -> Classification {
+    let mut copy = attractor.empty_copy();
+    copy.step(burn_in);
+    let Some(last) = copy.history().last().cloned() else {
+        return Classification::Divergent;
+    };
+    copy.set_history(vec![last]);
+    copy.step(num_steps.max(2 * MAX_PERIOD));
+    let history = copy.history();
+    let escaped = |pos: &Vec<f64>| {
+        pos.iter()
+            .any(|c| !c.is_finite() || c.abs() > ESCAPE_RADIUS)
+    };
+    if history.iter().any(escaped) {
+        return Classification::Divergent;
+    }
+
+    // Positions closer than `tolerance` are treated as the same position
+    let scale = history
+        .iter()
+        .flat_map(|pos| pos.iter())
+        .fold(1.0, |acc: f64, c| acc.max(c.abs()));
+    let tolerance = 1e-9 * scale;
+    let same = |a: &Vec<f64>, b: &Vec<f64>| {
+        a.iter()
+            .zip(b.iter())
+            .all(|(a, b)| (a - b).abs() < tolerance)
+    };
+
+    // Check that the last `MAX_PERIOD` positions each match the position `period` steps earlier
+    let n = history.len();
+    let recent = &history[n - MAX_PERIOD..];
+    if recent.iter().all(|pos| same(pos, &recent[0])) {
+        return Classification::FixedPoint;
+    }
+    for period in 1..=MAX_PERIOD {
+        if (n - MAX_PERIOD..n).all(|i| same(&history[i], &history[i - period])) {
+            return Classification::LimitCycle {
+                period: Some(period),
+            };
+        }
+    }
+
+    match lyapunov_exponent(&copy, num_steps) {
+        None => Classification::Divergent,
+        Some(exp) if exp > LYAPUNOV_TOLERANCE => Classification::Chaotic,
+        Some(exp) if exp < -LYAPUNOV_TOLERANCE => Classification::LimitCycle { period: None },
+        Some(_) => Classification::QuasiPeriodic,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attractors::CliffordAttractor;
+    use crate::flows::LorenzAttractor;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn chaotic_attractors_have_positive_exponents() {
+        let clifford = CliffordAttractor::new(vec![-1.4, 1.6, 1.0, 0.7]).unwrap();
+        assert!(lyapunov_exponent(&clifford, 5_000).unwrap() > 0.1);
+
+        // The Lorenz attractor's exponent is about 0.9 per unit of time
+        let mut lorenz = LorenzAttractor::with_defaults();
+        lorenz.step(1);
+        let exp = lyapunov_exponent(&lorenz, 100_000).unwrap() / lorenz.dt;
+        assert!(0.7 < exp && exp < 1.1, "exponent was {}", exp);
+    }
+
+    #[test]
+    fn classify_clifford_attractors() {
+        let classify_params =
+            |params: Vec<f64>| classify(&CliffordAttractor::new(params).unwrap(), 1_000, 2_000);
+        assert_eq!(
+            classify_params(vec![-1.4, 1.6, 1.0, 0.7]),
+            Classification::Chaotic
+        );
+        assert_eq!(
+            classify_params(vec![0.1, 0.1, 0.1, 0.1]),
+            Classification::FixedPoint
+        );
+        assert!(matches!(
+            classify_params(vec![1.5, -1.8, 1.6, 0.9]),
+            Classification::LimitCycle { .. } | Classification::QuasiPeriodic
+        ));
+    }
+
+    #[test]
+    fn dimensions_of_simple_shapes() {
+        // A straight line has a dimension of 1, and a filled-in square has a dimension of 2
+        let line: Vec<Vec<f64>> = (0..100_000)
+            .map(|i| vec![i as f64, 0.5 * i as f64])
+            .collect();
+        let mut rng = StdRng::seed_from_u64(11);
+        let square: Vec<Vec<f64>> = (0..100_000)
+            .map(|_| vec![rng.gen::<f64>(), rng.gen::<f64>()])
+            .collect();
+        for (shape, expected) in [(line, 1.0), (square, 2.0)] {
+            let boxes = box_counting_dimension(&shape, 100_000).unwrap();
+            let corr = correlation_dimension(&shape, 2_000).unwrap();
+            assert!((boxes.dimension - expected).abs() < 0.1, "{:?}", boxes);
+            // Pairs near the edges of the square have fewer neighbours, which biases the
+            // correlation dimension low by about 0.1
+            assert!((corr.dimension - expected).abs() < 0.2, "{:?}", corr);
+            assert!(boxes.r_squared > 0.99 && corr.r_squared > 0.99);
+        }
+    }
+
+    #[test]
+    fn dimensions_can_be_estimated_without_a_history() {
+        let mut clifford = CliffordAttractor::new(vec![-1.4, 1.6, 1.0, 0.7]).unwrap();
+        clifford.step(1_000);
+        let kept = box_counting_dimension(&dimension_samples(&clifford, 50_000), 50_000).unwrap();
+        // Accumulating forgets all but the latest position
+        clifford.step_with(1_000, |_| {});
+        assert_eq!(clifford.history().len(), 1);
+        let samples = dimension_samples(&clifford, 50_000);
+        assert_eq!(samples.len(), 50_000);
+        assert_eq!(clifford.history().len(), 1);
+        let accumulated = box_counting_dimension(&samples, 50_000).unwrap();
+        assert!(correlation_dimension(&samples, 1_000).is_some());
+        assert!((kept.dimension - accumulated.dimension).abs() < 0.05);
+    }
+
+    #[test]
+    fn fixed_points_have_negative_exponents() {
+        // With these parameters every position falls onto a single fixed point
+        let clifford = CliffordAttractor::new(vec![0.1, 0.1, 0.1, 0.1]).unwrap();
+        assert!(lyapunov_exponent(&clifford, 1_000).unwrap() < 0.0);
+    }
+}
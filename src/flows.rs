@@ -0,0 +1,475 @@
+//! Continuous-time attractors, defined by a differential equation instead of a map.
+//!
+//! Each flow only has to say how to calculate it's derivative (see `Flow`), and `FlowAttractor`
+//! turns that into an `Attractor` by integrating the flow with one of the integrators in `ode`.
+//! The available flows are:
+//! - [Lorenz](https://en.wikipedia.org/wiki/Lorenz_system)
+//! - [Rössler](https://en.wikipedia.org/wiki/R%C3%B6ssler_attractor)
+//! - [Arneodo](http://paulbourke.net/fractals/arneodo/)
+//! - [Burke-Shaw](http://paulbourke.net/fractals/burkeshaw/)
+//! - [Yu-Wang](http://paulbourke.net/fractals/yuwang/)
+//! - [Thomas](https://en.wikipedia.org/wiki/Thomas%27_cyclically_symmetric_attractor)
+//! - [Aizawa](http://www.algosome.com/articles/aizawa-attractor-chaos.html)
+use std::{
+    fmt::{self, Display},
+    marker::PhantomData,
+};
+
+use crate::attractors::{
+    check_num_params, include_in_bounds, update_coeffs, write_trajectory, Attractor,
+    AttractorError, Bounds,
+};
+use crate::diagnostics::describe_lyapunov;
+use crate::ode::Integrator;
+
+/// A 3D flow, defined by it's derivative `d(pos)/dt = derivative(params, pos)`.
+pub trait Flow {
+    /// A name for the flow matching [a-zA-Z]+, used when saving a sequence of points to file.
+    const NAME: &'static str;
+    /// A human-readable name for the flow.
+    const TITLE: &'static str;
+    /// The names of each of the parameters, used when saving to file.
+    const PARAM_NAMES: &'static [&'static str];
+    /// Parameters which are known to give a strange attractor.
+    const DEFAULT_PARAMS: &'static [f64];
+    /// The position from which every orbit starts.
+    const START: [f64; 3];
+    /// A time step which is small enough to integrate the flow accurately with RK4.
+    const DT: f64;
+
+    /// Calculate the derivative of the flow at `pos`.
+    fn derivative(params: &[f64], pos: &[f64; 3]) -> [f64; 3];
+}
+
+/// Any `Flow`, integrated over time to turn it into an `Attractor`.
+///
+/// Every call to `step` integrates the flow over `dt` time units and saves the new position to
+/// the history. Before the first position is saved, `transient` steps are taken and thrown away
+/// so that the orbit has time to settle onto the attractor.
+///
+/// The bounds are grown to fit every position visited so far, using only the x and y coordinates.
+pub struct FlowAttractor<F: Flow> {
+    /// The parameters of the flow, in the same order as `Flow::PARAM_NAMES`.
+    pub params: Vec<f64>,
+    /// The current position.
+    pub pos: [f64; 3],
+    /// The amount of time to integrate the flow over for each step.
+    pub dt: f64,
+    /// The method used to integrate the flow.
+    pub integrator: Integrator,
+    /// The number of steps to throw away after every reset, before any positions are saved.
+    pub transient: usize,
+    /// True if the transient steps have already been taken since the last reset.
+    settled: bool,
+    /// The size of the most recent adaptive sub-step, used by the RK45 integrator.
+    substep: f64,
+    /// The smallest box containing the x and y of every finite position visited so far.
+    bounds: Bounds,
+    /// Store all the previously visited points in the history vector
+    pub history: Vec<Vec<f64>>,
+    flow: PhantomData<F>,
+}
+
+/// The bounds of an attractor which hasn't visited any positions yet.
+const EMPTY_BOUNDS: Bounds = Bounds {
+    xmin: f64::INFINITY,
+    xmax: f64::NEG_INFINITY,
+    ymin: f64::INFINITY,
+    ymax: f64::NEG_INFINITY,
+};
+
+impl<F: Flow> FlowAttractor<F> {
+    /// The number of transient steps which are thrown away by default.
+    pub const DEFAULT_TRANSIENT: usize = 1_000;
+
+    /// Create a new flow attractor with the flow's default parameters.
+    pub fn with_defaults() -> Self {
+        Self::new(F::DEFAULT_PARAMS.to_vec()).expect("Default parameters should be valid")
+    }
+
+    /// Take the transient steps, if they haven't been taken since the last reset.
+    fn settle(&mut self) {
+        if !self.settled {
+            for _ in 0..self.transient {
+                self.advance();
+            }
+            self.settled = true;
+        }
+    }
+
+    /// Integrate the flow over one time step of size `dt`.
+    fn advance(&mut self) {
+        let params = &self.params;
+        self.pos = self.integrator.advance(
+            |pos| F::derivative(params, pos),
+            &self.pos,
+            self.dt,
+            &mut self.substep,
+        );
+    }
+}
+
+impl<F: Flow> Attractor for FlowAttractor<F> {
+    const NAME: &'static str = F::NAME;
+    /// Flows all live in 3 dimensions.
+    const DIMENSIONALITY: u8 = 3;
+    const NUM_PARAMETERS: u8 = F::PARAM_NAMES.len() as u8;
+
+    /// Create a new flow attractor with the RK4 integrator, the flow's default `dt`, and
+    /// `DEFAULT_TRANSIENT` transient steps.
+    fn new(params: Vec<f64>) -> Result<Self, AttractorError> {
+        check_num_params::<Self>(params.len())?;
+        Ok(FlowAttractor {
+            params,
+            pos: F::START,
+            dt: F::DT,
+            integrator: Integrator::Rk4,
+            transient: Self::DEFAULT_TRANSIENT,
+            settled: false,
+            substep: F::DT,
+            bounds: EMPTY_BOUNDS,
+            history: vec![],
+            flow: PhantomData,
+        })
+    }
+
+    /// Integrate the flow over `num_steps` time steps of size `dt`, saving each position to the
+    /// `.history` vector. The transient steps are taken first if they haven't been already.
+    fn step(&mut self, num_steps: usize) {
+        self.settle();
+        for _ in 0..num_steps {
+            self.advance();
+            include_in_bounds(&mut self.bounds, &self.pos);
+            self.history.push(self.pos.to_vec());
+        }
+    }
+
+    fn step_with(&mut self, num_steps: usize, mut visit: impl FnMut(&[f64])) {
+        self.settle();
+        for _ in 0..num_steps {
+            self.advance();
+            include_in_bounds(&mut self.bounds, &self.pos);
+            visit(&self.pos);
+        }
+        // Like `step`, nothing is saved until the transient steps have been taken
+        self.history = match num_steps {
+            0 => std::mem::take(&mut self.history),
+            _ => vec![self.pos.to_vec()],
+        };
+    }
+
+    /// Go back to the starting position and forget the history. The transient steps will be
+    /// taken again on the next call to `step`.
+    fn reset(&mut self) {
+        self.pos = F::START;
+        self.substep = self.dt;
+        self.settled = false;
+        self.bounds = EMPTY_BOUNDS;
+        self.history = vec![];
+    }
+
+    /// Copy the parameters along with `dt`, the integrator, and the number of transient steps.
+    fn empty_copy(&self) -> Self {
+        FlowAttractor {
+            dt: self.dt,
+            integrator: self.integrator,
+            transient: self.transient,
+            substep: self.dt,
+            ..FlowAttractor::new(self.params.clone()).expect("A flow's own parameters are valid")
+        }
+    }
+
+    fn set_params(&mut self, params: Vec<Option<f64>>) -> Result<(), AttractorError> {
+        check_num_params::<Self>(params.len())?;
+        update_coeffs(&mut self.params, params);
+        Ok(())
+    }
+
+    fn params(&self) -> Vec<f64> {
+        self.params.clone()
+    }
+
+    fn param_names(&self) -> Vec<String> {
+        F::PARAM_NAMES.iter().map(|n| n.to_string()).collect()
+    }
+
+    /// The parameters by name, since a flow's formula is a derivative rather than a map.
+    fn equations(&self) -> Vec<String> {
+        let params: Vec<String> = F::PARAM_NAMES
+            .iter()
+            .zip(self.params.iter())
+            .map(|(name, value)| format!("{}={:+.4}", name, value))
+            .collect();
+        vec![params.join(", "), format!("dt={}", self.dt)]
+    }
+
+    fn history(&self) -> &Vec<Vec<f64>> {
+        &self.history
+    }
+
+    fn set_history(&mut self, history: Vec<Vec<f64>>) {
+        self.bounds = EMPTY_BOUNDS;
+        for pos in history.iter() {
+            include_in_bounds(&mut self.bounds, pos);
+        }
+        if let Some(last) = history.last() {
+            self.pos = [last[0], last[1], last[2]];
+            // Positions from a history are already on the attractor
+            self.settled = true;
+        }
+        self.history = history;
+    }
+
+    fn bounds(&self) -> Bounds {
+        self.bounds
+    }
+
+    /// Write the flow attractor to the file named `filename`, with parameters named by
+    /// `Flow::PARAM_NAMES`.
+    fn to_file(&mut self, filename: String) -> Result<(), AttractorError> {
+        write_trajectory(
+            filename,
+            (Self::NAME, Self::NUM_PARAMETERS, Self::DIMENSIONALITY),
+            None,
+            &self.param_names(),
+            &self.params,
+            &self.history,
+        )
+    }
+}
+
+impl<F: Flow> Display for FlowAttractor<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let params: Vec<String> = F::PARAM_NAMES
+            .iter()
+            .zip(self.params.iter())
+            .map(|(name, value)| format!("{}={:+.4}", name, value))
+            .collect();
+        write!(
+            f,
+            "{} Attractor (history={}, dt={}, {:?}):\n  {}\n  {}",
+            F::TITLE,
+            self.history.len(),
+            self.dt,
+            self.integrator,
+            params.join(", "),
+            describe_lyapunov(self),
+        )
+    }
+}
+
+/// The [Lorenz system](https://en.wikipedia.org/wiki/Lorenz_system):
+/// ```
+/// dx/dt = sigma * (y - x)
+/// dy/dt = x * (rho - z) - y
+/// dz/dt = x * y - beta * z
+/// ```
+pub struct Lorenz;
+pub type LorenzAttractor = FlowAttractor<Lorenz>;
+
+impl Flow for Lorenz {
+    const NAME: &'static str = "lorenz";
+    const TITLE: &'static str = "Lorenz";
+    const PARAM_NAMES: &'static [&'static str] = &["sigma", "rho", "beta"];
+    const DEFAULT_PARAMS: &'static [f64] = &[10.0, 28.0, 8.0 / 3.0];
+    const START: [f64; 3] = [0.1, 0.0, 0.0];
+    const DT: f64 = 0.01;
+
+    fn derivative(p: &[f64], pos: &[f64; 3]) -> [f64; 3] {
+        let [x, y, z] = *pos;
+        [p[0] * (y - x), x * (p[1] - z) - y, x * y - p[2] * z]
+    }
+}
+
+/// The [Rössler attractor](https://en.wikipedia.org/wiki/R%C3%B6ssler_attractor):
+/// ```
+/// dx/dt = -y - z
+/// dy/dt = x + a * y
+/// dz/dt = b + z * (x - c)
+/// ```
+pub struct Rossler;
+pub type RosslerAttractor = FlowAttractor<Rossler>;
+
+impl Flow for Rossler {
+    const NAME: &'static str = "rossler";
+    const TITLE: &'static str = "Rössler";
+    const PARAM_NAMES: &'static [&'static str] = &["a", "b", "c"];
+    const DEFAULT_PARAMS: &'static [f64] = &[0.2, 0.2, 5.7];
+    const START: [f64; 3] = [0.1, 0.0, 0.0];
+    const DT: f64 = 0.02;
+
+    fn derivative(p: &[f64], pos: &[f64; 3]) -> [f64; 3] {
+        let [x, y, z] = *pos;
+        [-y - z, x + p[0] * y, p[1] + z * (x - p[2])]
+    }
+}
+
+/// The [Arneodo attractor](http://paulbourke.net/fractals/arneodo/):
+/// ```
+/// dx/dt = y
+/// dy/dt = z
+/// dz/dt = -a * x - b * y - z + c * x^3
+/// ```
+pub struct Arneodo;
+pub type ArneodoAttractor = FlowAttractor<Arneodo>;
+
+impl Flow for Arneodo {
+    const NAME: &'static str = "arneodo";
+    const TITLE: &'static str = "Arneodo";
+    const PARAM_NAMES: &'static [&'static str] = &["a", "b", "c"];
+    const DEFAULT_PARAMS: &'static [f64] = &[-5.5, 3.5, -1.0];
+    const START: [f64; 3] = [0.1, 0.0, 0.0];
+    const DT: f64 = 0.01;
+
+    fn derivative(p: &[f64], pos: &[f64; 3]) -> [f64; 3] {
+        let [x, y, z] = *pos;
+        [y, z, -p[0] * x - p[1] * y - z + p[2] * x * x * x]
+    }
+}
+
+/// The [Burke-Shaw attractor](http://paulbourke.net/fractals/burkeshaw/):
+/// ```
+/// dx/dt = -s * (x + y)
+/// dy/dt = -y - s * x * z
+/// dz/dt = s * x * y + v
+/// ```
+pub struct BurkeShaw;
+pub type BurkeShawAttractor = FlowAttractor<BurkeShaw>;
+
+impl Flow for BurkeShaw {
+    const NAME: &'static str = "burkeshaw";
+    const TITLE: &'static str = "Burke-Shaw";
+    const PARAM_NAMES: &'static [&'static str] = &["s", "v"];
+    const DEFAULT_PARAMS: &'static [f64] = &[10.0, 4.272];
+    const START: [f64; 3] = [0.1, 0.0, 0.0];
+    const DT: f64 = 0.005;
+
+    fn derivative(p: &[f64], pos: &[f64; 3]) -> [f64; 3] {
+        let [x, y, z] = *pos;
+        [-p[0] * (x + y), -y - p[0] * x * z, p[0] * x * y + p[1]]
+    }
+}
+
+/// The [Yu-Wang attractor](http://paulbourke.net/fractals/yuwang/):
+/// ```
+/// dx/dt = a * (y - x)
+/// dy/dt = b * x - c * x * z
+/// dz/dt = e^(x * y) - d * z
+/// ```
+/// The exponential makes this flow quite stiff, so it needs a small `dt` (or the RK45 integrator).
+pub struct YuWang;
+pub type YuWangAttractor = FlowAttractor<YuWang>;
+
+impl Flow for YuWang {
+    const NAME: &'static str = "yuwang";
+    const TITLE: &'static str = "Yu-Wang";
+    const PARAM_NAMES: &'static [&'static str] = &["a", "b", "c", "d"];
+    const DEFAULT_PARAMS: &'static [f64] = &[10.0, 40.0, 2.0, 2.5];
+    const START: [f64; 3] = [0.1, 0.0, 0.0];
+    const DT: f64 = 0.001;
+
+    fn derivative(p: &[f64], pos: &[f64; 3]) -> [f64; 3] {
+        let [x, y, z] = *pos;
+        [
+            p[0] * (y - x),
+            p[1] * x - p[2] * x * z,
+            (x * y).exp() - p[3] * z,
+        ]
+    }
+}
+
+/// [Thomas' cyclically symmetric attractor](https://en.wikipedia.org/wiki/Thomas%27_cyclically_symmetric_attractor):
+/// ```
+/// dx/dt = sin(y) - b * x
+/// dy/dt = sin(z) - b * y
+/// dz/dt = sin(x) - b * z
+/// ```
+pub struct Thomas;
+pub type ThomasAttractor = FlowAttractor<Thomas>;
+
+impl Flow for Thomas {
+    const NAME: &'static str = "thomas";
+    const TITLE: &'static str = "Thomas";
+    const PARAM_NAMES: &'static [&'static str] = &["b"];
+    const DEFAULT_PARAMS: &'static [f64] = &[0.208186];
+    const START: [f64; 3] = [0.1, 0.0, 0.0];
+    const DT: f64 = 0.05;
+
+    fn derivative(p: &[f64], pos: &[f64; 3]) -> [f64; 3] {
+        let [x, y, z] = *pos;
+        [y.sin() - p[0] * x, z.sin() - p[0] * y, x.sin() - p[0] * z]
+    }
+}
+
+/// The [Aizawa attractor](http://www.algosome.com/articles/aizawa-attractor-chaos.html):
+/// ```
+/// dx/dt = (z - b) * x - d * y
+/// dy/dt = d * x + (z - b) * y
+/// dz/dt = c + a * z - z^3 / 3 - (x^2 + y^2) * (1 + e * z) + f * z * x^3
+/// ```
+pub struct Aizawa;
+pub type AizawaAttractor = FlowAttractor<Aizawa>;
+
+impl Flow for Aizawa {
+    const NAME: &'static str = "aizawa";
+    const TITLE: &'static str = "Aizawa";
+    const PARAM_NAMES: &'static [&'static str] = &["a", "b", "c", "d", "e", "f"];
+    const DEFAULT_PARAMS: &'static [f64] = &[0.95, 0.7, 0.6, 3.5, 0.25, 0.1];
+    const START: [f64; 3] = [0.1, 0.0, 0.0];
+    const DT: f64 = 0.01;
+
+    fn derivative(p: &[f64], pos: &[f64; 3]) -> [f64; 3] {
+        let [x, y, z] = *pos;
+        [
+            (z - p[1]) * x - p[3] * y,
+            p[3] * x + (z - p[1]) * y,
+            p[2] + p[0] * z - z * z * z / 3.0 - (x * x + y * y) * (1.0 + p[4] * z)
+                + p[5] * z * x * x * x,
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Run `A` with it's default parameters and check it settles onto a bounded attractor.
+    fn assert_bounded<F: Flow>(integrator: Integrator) {
+        let mut attractor = FlowAttractor::<F>::with_defaults();
+        attractor.integrator = integrator;
+        attractor.step(5_000);
+        assert_eq!(attractor.history.len(), 5_000);
+        let bounds = attractor.bounds();
+        let width = bounds.xmax - bounds.xmin;
+        assert!(
+            width > 0.0 && width < 1_000.0,
+            "{} has width {}",
+            F::NAME,
+            width
+        );
+    }
+
+    #[test]
+    fn every_flow_is_bounded() {
+        for integrator in [Integrator::Rk4, Integrator::Rk45 { tolerance: 1e-6 }] {
+            assert_bounded::<Lorenz>(integrator);
+            assert_bounded::<Rossler>(integrator);
+            assert_bounded::<Arneodo>(integrator);
+            assert_bounded::<BurkeShaw>(integrator);
+            assert_bounded::<YuWang>(integrator);
+            assert_bounded::<Thomas>(integrator);
+            assert_bounded::<Aizawa>(integrator);
+        }
+    }
+
+    #[test]
+    fn transient_steps_are_discarded() {
+        let mut lorenz = LorenzAttractor::with_defaults();
+        lorenz.transient = 0;
+        lorenz.step(10);
+        let mut settled = LorenzAttractor::with_defaults();
+        settled.transient = 5;
+        settled.step(5);
+        assert_eq!(settled.history[..], lorenz.history[5..]);
+    }
+}
@@ -0,0 +1,122 @@
+//! A tiny bitmap font, for writing captions onto prints without needing a font file.
+//!
+//! Every printable ASCII character is 5 pixels wide and 7 pixels high, and gets scaled up by a
+//! whole number so that it's big enough to read at print resolutions. Any other character is
+//! drawn as a `?`.
+use image::{Rgb, RgbImage};
+
+/// The width and height of each character, in font pixels.
+pub const GLYPH_WIDTH: usize = 5;
+pub const GLYPH_HEIGHT: usize = 7;
+/// The space between characters, in font pixels.
+const SPACING: usize = 1;
+/// The distance from the top of one line to the top of the next, in font pixels.
+pub const LINE_HEIGHT: usize = 10;
+
+/// The characters from ' ' to '~', each as 5 columns from left to right. Bit 0 of each column is
+/// the top row.
+#[rustfmt::skip]
+const GLYPHS: [[u8; GLYPH_WIDTH]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], [0x00, 0x00, 0x5F, 0x00, 0x00], [0x00, 0x07, 0x00, 0x07, 0x00],
+    [0x14, 0x7F, 0x14, 0x7F, 0x14], [0x24, 0x2A, 0x7F, 0x2A, 0x12], [0x23, 0x13, 0x08, 0x64, 0x62],
+    [0x36, 0x49, 0x55, 0x22, 0x50], [0x00, 0x05, 0x03, 0x00, 0x00], [0x00, 0x1C, 0x22, 0x41, 0x00],
+    [0x00, 0x41, 0x22, 0x1C, 0x00], [0x08, 0x2A, 0x1C, 0x2A, 0x08], [0x08, 0x08, 0x3E, 0x08, 0x08],
+    [0x00, 0x50, 0x30, 0x00, 0x00], [0x08, 0x08, 0x08, 0x08, 0x08], [0x00, 0x60, 0x60, 0x00, 0x00],
+    [0x20, 0x10, 0x08, 0x04, 0x02], [0x3E, 0x51, 0x49, 0x45, 0x3E], [0x00, 0x42, 0x7F, 0x40, 0x00],
+    [0x42, 0x61, 0x51, 0x49, 0x46], [0x21, 0x41, 0x45, 0x4B, 0x31], [0x18, 0x14, 0x12, 0x7F, 0x10],
+    [0x27, 0x45, 0x45, 0x45, 0x39], [0x3C, 0x4A, 0x49, 0x49, 0x30], [0x01, 0x71, 0x09, 0x05, 0x03],
+    [0x36, 0x49, 0x49, 0x49, 0x36], [0x06, 0x49, 0x49, 0x29, 0x1E], [0x00, 0x36, 0x36, 0x00, 0x00],
+    [0x00, 0x56, 0x36, 0x00, 0x00], [0x08, 0x14, 0x22, 0x41, 0x00], [0x14, 0x14, 0x14, 0x14, 0x14],
+    [0x00, 0x41, 0x22, 0x14, 0x08], [0x02, 0x01, 0x51, 0x09, 0x06], [0x32, 0x49, 0x79, 0x41, 0x3E],
+    [0x7E, 0x11, 0x11, 0x11, 0x7E], [0x7F, 0x49, 0x49, 0x49, 0x36], [0x3E, 0x41, 0x41, 0x41, 0x22],
+    [0x7F, 0x41, 0x41, 0x22, 0x1C], [0x7F, 0x49, 0x49, 0x49, 0x41], [0x7F, 0x09, 0x09, 0x01, 0x01],
+    [0x3E, 0x41, 0x41, 0x51, 0x32], [0x7F, 0x08, 0x08, 0x08, 0x7F], [0x00, 0x41, 0x7F, 0x41, 0x00],
+    [0x20, 0x40, 0x41, 0x3F, 0x01], [0x7F, 0x08, 0x14, 0x22, 0x41], [0x7F, 0x40, 0x40, 0x40, 0x40],
+    [0x7F, 0x02, 0x04, 0x02, 0x7F], [0x7F, 0x04, 0x08, 0x10, 0x7F], [0x3E, 0x41, 0x41, 0x41, 0x3E],
+    [0x7F, 0x09, 0x09, 0x09, 0x06], [0x3E, 0x41, 0x51, 0x21, 0x5E], [0x7F, 0x09, 0x19, 0x29, 0x46],
+    [0x46, 0x49, 0x49, 0x49, 0x31], [0x01, 0x01, 0x7F, 0x01, 0x01], [0x3F, 0x40, 0x40, 0x40, 0x3F],
+    [0x1F, 0x20, 0x40, 0x20, 0x1F], [0x7F, 0x20, 0x18, 0x20, 0x7F], [0x63, 0x14, 0x08, 0x14, 0x63],
+    [0x03, 0x04, 0x78, 0x04, 0x03], [0x61, 0x51, 0x49, 0x45, 0x43], [0x00, 0x7F, 0x41, 0x41, 0x00],
+    [0x02, 0x04, 0x08, 0x10, 0x20], [0x00, 0x41, 0x41, 0x7F, 0x00], [0x04, 0x02, 0x01, 0x02, 0x04],
+    [0x40, 0x40, 0x40, 0x40, 0x40], [0x00, 0x01, 0x02, 0x04, 0x00], [0x20, 0x54, 0x54, 0x54, 0x78],
+    [0x7F, 0x48, 0x44, 0x44, 0x38], [0x38, 0x44, 0x44, 0x44, 0x20], [0x38, 0x44, 0x44, 0x48, 0x7F],
+    [0x38, 0x54, 0x54, 0x54, 0x18], [0x08, 0x7E, 0x09, 0x01, 0x02], [0x08, 0x14, 0x54, 0x54, 0x3C],
+    [0x7F, 0x08, 0x04, 0x04, 0x78], [0x00, 0x44, 0x7D, 0x40, 0x00], [0x20, 0x40, 0x44, 0x3D, 0x00],
+    [0x00, 0x7F, 0x10, 0x28, 0x44], [0x00, 0x41, 0x7F, 0x40, 0x00], [0x7C, 0x04, 0x18, 0x04, 0x78],
+    [0x7C, 0x08, 0x04, 0x04, 0x78], [0x38, 0x44, 0x44, 0x44, 0x38], [0x7C, 0x14, 0x14, 0x14, 0x08],
+    [0x08, 0x14, 0x14, 0x18, 0x7C], [0x7C, 0x08, 0x04, 0x04, 0x08], [0x48, 0x54, 0x54, 0x54, 0x20],
+    [0x04, 0x3F, 0x44, 0x40, 0x20], [0x3C, 0x40, 0x40, 0x20, 0x7C], [0x1C, 0x20, 0x40, 0x20, 0x1C],
+    [0x3C, 0x40, 0x30, 0x40, 0x3C], [0x44, 0x28, 0x10, 0x28, 0x44], [0x0C, 0x50, 0x50, 0x50, 0x3C],
+    [0x44, 0x64, 0x54, 0x4C, 0x44], [0x00, 0x08, 0x36, 0x41, 0x00], [0x00, 0x00, 0x7F, 0x00, 0x00],
+    [0x00, 0x41, 0x36, 0x08, 0x00], [0x08, 0x04, 0x08, 0x10, 0x08],
+];
+
+/// The columns of the glyph for `c`.
+fn glyph(c: char) -> &'static [u8; GLYPH_WIDTH] {
+    let idx = match c {
+        ' '..='~' => c as usize - ' ' as usize,
+        _ => '?' as usize - ' ' as usize,
+    };
+    &GLYPHS[idx]
+}
+
+/// The width and height in image pixels of `text` drawn with each font pixel `scale` image
+/// pixels wide. Each line of `text` is drawn below the previous one.
+pub fn text_size(text: &str, scale: usize) -> (usize, usize) {
+    let lines: Vec<&str> = text.lines().collect();
+    let longest = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+    let width = (longest * (GLYPH_WIDTH + SPACING)).saturating_sub(SPACING);
+    let height = match lines.len() {
+        0 => 0,
+        n => (n - 1) * LINE_HEIGHT + GLYPH_HEIGHT,
+    };
+    (width * scale, height * scale)
+}
+
+/// Draw `text` onto `image` with its top left corner at (`x`, `y`), with each font pixel `scale`
+/// image pixels wide. Anything which doesn't fit on the image is cut off.
+pub fn draw_text(
+    image: &mut RgbImage,
+    x: usize,
+    y: usize,
+    scale: usize,
+    text: &str,
+    colour: Rgb<u8>,
+) {
+    for (line_idx, line) in text.lines().enumerate() {
+        let top = y + line_idx * LINE_HEIGHT * scale;
+        for (char_idx, c) in line.chars().enumerate() {
+            let left = x + char_idx * (GLYPH_WIDTH + SPACING) * scale;
+            for (col, bits) in glyph(c).iter().enumerate() {
+                for row in (0..GLYPH_HEIGHT).filter(|row| bits >> row & 1 == 1) {
+                    for py in top + row * scale..top + (row + 1) * scale {
+                        for px in left + col * scale..left + (col + 1) * scale {
+                            if px < image.width() as usize && py < image.height() as usize {
+                                image.put_pixel(px as u32, py as u32, colour);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_fits_in_its_size() {
+        let text = "x' = sin(a y)\nAbc";
+        let (width, height) = text_size(text, 2);
+        assert_eq!((width, height), ((13 * 6 - 1) * 2, (10 + 7) * 2));
+        // Drawing on an image exactly the right size touches every edge, and nothing is lost
+        let mut image = RgbImage::new(width as u32 + 2, height as u32 + 2);
+        draw_text(&mut image, 0, 0, 2, text, Rgb([255, 255, 255]));
+        let lit = |x: u32, y: u32| image.get_pixel(x, y)[0] == 255;
+        assert!((0..image.height()).all(|y| !lit(width as u32, y)));
+        assert!((0..image.width()).all(|x| !lit(x, height as u32)));
+        assert!((0..image.height()).any(|y| lit(0, y)));
+    }
+}
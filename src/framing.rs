@@ -0,0 +1,225 @@
+//! Framing attractors tightly, using where they actually go instead of their analytic bounds.
+//!
+//! Map attractors like Clifford's derive their bounds from their parameters (`|x| <= 1 + |c|`),
+//! which is always big enough but is often much bigger than the orbit itself, wasting most of
+//! the image. `Framed` wraps any attractor and replaces its bounds with ones fitted to a sample of
+//! its positions:
+//! 1. The attractor is stepped through a burn-in, so that the start point doesn't count.
+//! 2. A sample of positions is taken, and the bounds cover everything between a low and a high
+//!    percentile on each axis, so that a few stray positions can't blow the frame up.
+//! 3. The bounds are widened along one axis to have the same aspect ratio as the image, so that
+//!    the attractor isn't stretched.
+//!
+//! Every renderer gets its bounds from `Attractor::bounds`, so any of them can draw a `Framed`
+//! attractor.
+use crate::attractors::{Attractor, AttractorError, Bounds};
+
+/// How `Framed` fits the bounds of an attractor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundsFit {
+    /// The number of positions stepped and thrown away before sampling.
+    pub burn_in: usize,
+    /// The number of positions sampled to find the percentiles.
+    pub samples: usize,
+    /// The percentage of the sample left outside the bounds at each end of each axis.
+    pub trim: f64,
+    /// The width divided by the height of the image the bounds are for.
+    pub aspect: f64,
+}
+
+impl Default for BoundsFit {
+    fn default() -> Self {
+        BoundsFit {
+            burn_in: 1_000,
+            samples: 100_000,
+            trim: 0.1,
+            aspect: 1.0,
+        }
+    }
+}
+
+/// Bounds which contain every position in `positions` between the `trim` and `100 - trim`
+/// percentiles of each axis, widened along one axis so that their width divided by their height
+/// is `aspect`. Non-finite positions are ignored, and `None` is returned if none are left.
+pub fn fit_bounds(positions: &[Vec<f64>], trim: f64, aspect: f64) -> Option<Bounds> {
+    let percentiles = |axis: usize| {
+        let mut values: Vec<f64> = positions
+            .iter()
+            .map(|pos| pos[axis])
+            .filter(|v| v.is_finite())
+            .collect();
+        if values.is_empty() {
+            return None;
+        }
+        values.sort_by(|a, b| a.total_cmp(b));
+        let at = |p: f64| values[(p / 100.0 * (values.len() - 1) as f64).round() as usize];
+        Some((at(trim), at(100.0 - trim)))
+    };
+    let (xmin, xmax) = percentiles(0)?;
+    let (ymin, ymax) = percentiles(1)?;
+    let (x_mid, y_mid) = (0.5 * (xmin + xmax), 0.5 * (ymin + ymax));
+    // Even a single point needs some area to be drawn in
+    let mut width = (xmax - xmin).max(f64::EPSILON);
+    let mut height = (ymax - ymin).max(f64::EPSILON);
+    if width < height * aspect {
+        width = height * aspect;
+    } else {
+        height = width / aspect;
+    }
+    Some(Bounds {
+        xmin: x_mid - 0.5 * width,
+        xmax: x_mid + 0.5 * width,
+        ymin: y_mid - 0.5 * height,
+        ymax: y_mid + 0.5 * height,
+    })
+}
+
+/// An attractor drawn within bounds fitted to a sample of its positions. See the module docs.
+#[derive(Debug, Clone)]
+pub struct Framed<A> {
+    pub attractor: A,
+    pub fit: BoundsFit,
+    bounds: Bounds,
+}
+
+impl<A: Attractor> Framed<A> {
+    /// Wrap `attractor`, fitting its bounds with `fit`. If no positions could be sampled (because
+    /// the attractor diverges), the attractor's own bounds are used.
+    pub fn new(attractor: A, fit: BoundsFit) -> Self {
+        let bounds = sample_bounds(&attractor, &fit);
+        Framed {
+            attractor,
+            fit,
+            bounds,
+        }
+    }
+}
+
+/// Fit bounds to a fresh copy of `attractor`, as described in the module docs.
+fn sample_bounds<A: Attractor>(attractor: &A, fit: &BoundsFit) -> Bounds {
+    let mut sampler = attractor.empty_copy();
+    sampler.step_with(fit.burn_in, |_| {});
+    let mut sample = Vec::with_capacity(fit.samples);
+    sampler.step_with(fit.samples, |pos| sample.push(pos.to_vec()));
+    fit_bounds(&sample, fit.trim, fit.aspect).unwrap_or_else(|| attractor.bounds())
+}
+
+impl<A: Attractor> Attractor for Framed<A> {
+    const NAME: &'static str = A::NAME;
+    const DIMENSIONALITY: u8 = A::DIMENSIONALITY;
+    const NUM_PARAMETERS: u8 = A::NUM_PARAMETERS;
+
+    /// Wrap a new attractor, fitted with the default `BoundsFit`.
+    fn new(params: Vec<f64>) -> Result<Self, AttractorError> {
+        Ok(Framed::new(A::new(params)?, BoundsFit::default()))
+    }
+
+    fn step(&mut self, num_steps: usize) {
+        self.attractor.step(num_steps);
+    }
+
+    fn step_with(&mut self, num_steps: usize, visit: impl FnMut(&[f64])) {
+        self.attractor.step_with(num_steps, visit);
+    }
+
+    /// Change the parameters, and fit the bounds again.
+    fn set_params(&mut self, params: Vec<Option<f64>>) -> Result<(), AttractorError> {
+        self.attractor.set_params(params)?;
+        self.bounds = sample_bounds(&self.attractor, &self.fit);
+        Ok(())
+    }
+
+    fn params(&self) -> Vec<f64> {
+        self.attractor.params()
+    }
+
+    fn param_names(&self) -> Vec<String> {
+        self.attractor.param_names()
+    }
+
+    fn code(&self) -> Option<String> {
+        self.attractor.code()
+    }
+
+    fn equations(&self) -> Vec<String> {
+        self.attractor.equations()
+    }
+
+    fn history(&self) -> &Vec<Vec<f64>> {
+        self.attractor.history()
+    }
+
+    fn set_history(&mut self, history: Vec<Vec<f64>>) {
+        self.attractor.set_history(history);
+    }
+
+    /// The fitted bounds, which don't change as the attractor is stepped.
+    fn bounds(&self) -> Bounds {
+        self.bounds
+    }
+
+    fn reset(&mut self) {
+        self.attractor.reset();
+    }
+
+    /// An empty copy of the wrapped attractor, keeping the bounds which were already fitted.
+    fn empty_copy(&self) -> Self {
+        Framed {
+            attractor: self.attractor.empty_copy(),
+            fit: self.fit,
+            bounds: self.bounds,
+        }
+    }
+
+    fn to_file(&mut self, directory: String) -> Result<(), AttractorError> {
+        self.attractor.to_file(directory)
+    }
+
+    fn from_file(filename: String) -> Result<Self, AttractorError> {
+        Ok(Framed::new(A::from_file(filename)?, BoundsFit::default()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attractors::CliffordAttractor;
+
+    #[test]
+    fn fitting_ignores_outliers_and_keeps_the_aspect_ratio() {
+        let mut positions: Vec<Vec<f64>> = (0..=100)
+            .map(|i| vec![i as f64 / 100.0, i as f64 / 100.0])
+            .collect();
+        positions.push(vec![1000.0, -1000.0]);
+        positions.push(vec![f64::NAN, 0.0]);
+        let bounds = fit_bounds(&positions, 1.0, 2.0).unwrap();
+        // The outlier is trimmed, and the square sample is widened to twice as wide as it's high
+        assert!(bounds.ymax < 1.5 && bounds.ymin > -0.5);
+        let (width, height) = (bounds.xmax - bounds.xmin, bounds.ymax - bounds.ymin);
+        assert!((width / height - 2.0).abs() < 1e-9);
+        assert!(fit_bounds(&[vec![f64::NAN, 0.0]], 1.0, 1.0).is_none());
+    }
+
+    #[test]
+    fn framed_attractors_contain_almost_every_position() {
+        let fit = BoundsFit {
+            aspect: 1.5,
+            ..BoundsFit::default()
+        };
+        let mut framed = CliffordAttractor::new(vec![1.7, 1.7, 0.6, 1.2])
+            .unwrap()
+            .framed(fit);
+        let bounds = framed.bounds();
+        assert!(((bounds.xmax - bounds.xmin) / (bounds.ymax - bounds.ymin) - 1.5).abs() < 1e-9);
+        framed.step(50_000);
+        let inside = framed
+            .history()
+            .iter()
+            .filter(|p| (bounds.xmin..=bounds.xmax).contains(&p[0]))
+            .filter(|p| (bounds.ymin..=bounds.ymax).contains(&p[1]))
+            .count();
+        assert!(inside as f64 > 0.99 * framed.history().len() as f64);
+        // Copies keep the same bounds without fitting them again
+        assert_eq!(framed.empty_copy().bounds(), bounds);
+    }
+}
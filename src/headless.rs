@@ -0,0 +1,989 @@
+//! Rendering attractors to PNG from the command line, without opening any windows.
+//!
+//! This lets attractors be rendered on servers which don't have a display. Run it like:
+//! ```
+//! cargo run --release -- render --attractor clifford --params -1.4,1.6,1.0,0.7 \
+//!     --width 7016 --height 9933 --iterations 40000000 --output clifford.png
+//! ```
+//! See `USAGE` for every available option.
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use image::RgbImage;
+
+use crate::attractors::*;
+use crate::batch::BatchOptions;
+use crate::camera::{include_in_box, Camera, Projection};
+use crate::colour::{densities_to_image, ColourScheme};
+use crate::flows::*;
+use crate::framing::BoundsFit;
+use crate::ode::Integrator;
+use crate::palette::{load_palettes, Palette};
+use crate::parallel::Orbits;
+use crate::poster::Poster;
+use crate::print::{self, save_png, PrintLayout, Rows, Text};
+use crate::provenance;
+use crate::sampling::Sampling;
+use crate::search::SearchOptions;
+use crate::tiled::TiledRender;
+use crate::tone::ToneCurve;
+use crate::trajectory::{self, Precision};
+
+/// The help text printed for `--help` or when the arguments can't be understood.
+pub const USAGE: &str = "\
+Usage: attractors render --attractor <NAME> [OPTIONS] --output <FILE.png>
+       attractors rerender <IMAGE.png> [OPTIONS] --output <FILE.png>
+       attractors batch [BATCH OPTIONS] [OPTIONS]
+       attractors search --attractor <NAME> [SEARCH OPTIONS]
+       attractors window --attractor <NAME> [OPTIONS]
+
+Attractors:
+  clifford, dejong                          (4 parameters each)
+  sprott, sprottthreed                      (12 and 30 parameters, or use --code)
+  lorenz, rossler, arneodo, burkeshaw,
+  yuwang, thomas, aizawa                    (default parameters if --params is left out)
+
+Options:
+  --params <P1,P2,...>      Comma separated parameters for the attractor
+  --code <LETTERS>          Sprott letter code, like FIRCDERRPVLD, instead of --params
+  --width <PIXELS>          Width of the image [default: 1000]
+  --height <PIXELS>         Height of the image [default: 1000]
+  --iterations <N>          Number of positions to calculate [default: 10000000]
+  --border <FRACTION>       Fraction of the image left empty on each side [default: 0.05]
+  --background <dark|light> Draw on a dark or light background [default: dark]
+  --hue <H>                 LCh hue intercept [default: 0.45]
+  --hue-slope <H>           LCh hue slope [default: 0.15]
+  --tone <CURVE>            How densities are brightened: gamma:<EXPONENT>, log:<SCALE>,
+                            asinh:<SCALE>, or equalise [default: equalise]
+  --clip <LOW,HIGH>         Percentiles of the non-empty pixels which become the darkest
+                            and brightest colours [default: 0,99.9]
+  --light-power <P>         Same as --tone gamma:<P>
+  --palette <NAME>          Colour with a gradient palette instead of LCh: viridis, magma,
+                            inferno, plasma, fire, greys, or one from --palette-file
+  --palette-file <FILE>     Load extra palettes from a file, like palettes.toml
+  --splat <nearest|bilinear>  Add each position to its nearest pixel, or share it between
+                            the four nearest pixels for smoother lines [default: nearest]
+  --supersample <N>         Render N times larger in each direction, then shrink [default: 1]
+  --filter <box|tent|gaussian>  How supersampled images are shrunk [default: box]
+  --tile-rows <N|auto>      Render N rows at a time without keeping every position in
+                            memory, for images too large to fit in RAM (like A0 prints)
+  --fit-bounds <PERCENT>    Frame the image tightly around a sample of positions, leaving
+                            out PERCENT% of them at each edge (like 0.1) instead of using
+                            the attractor's own bounds
+  --burn-in <N>             Positions skipped before sampling for --fit-bounds [default: 1000]
+  --orbits <N>              Split the iterations between N orbits from jittered start
+                            points, run in parallel [default: 1]
+  --orbit-transient <N>     Positions discarded at the start of each orbit [default: 1000]
+  --jitter <FRACTION>       How far apart the orbits start, as a fraction of the size of
+                            the attractor [default: 0.01]
+  --seed <N>                Seed for the start points, the same seed gives the same image
+  --threads <N>             Number of orbits run at once [default: number of CPUs]
+  --paper <SIZE>            Lay the image out on paper, sized to fit: a0-a6, b0-b6, letter,
+                            legal, tabloid, arch-a to arch-e, or like 300x200mm or 24x36in.
+                            The width and height come from the paper
+  --orientation <portrait|landscape>  Which way up the paper is [default: portrait]
+  --dpi <DPI>               Pixels per inch of the printed page or poster [default: 600]
+  --margin <MM>             Space around the attractor, or 0 to print to the edge [default: 0]
+  --bleed <MM>              Extra paper around the page, cut off after printing [default: 0]
+  --caption <TEXT|auto>     Print TEXT below the attractor (\n starts a new line), or the
+                            attractor's name and formula for auto
+  --poster <SHEET>          Split the image onto SHEET sized paper (any --paper size) with
+                            crop and registration marks, saving each sheet and a manifest
+                            next to the output. Sheets are turned to need the fewest
+  --overlap <MM>            How far each sheet of a poster overlaps the next [default: 10]
+  --yaw, --pitch, --roll <RADIANS>  Rotate the camera
+  --zoom <Z>                Zoom the camera in (Z > 1) or out (Z < 1) [default: 1]
+  --viewport <XMIN,XMAX,YMIN,YMAX>  Draw exactly this region, instead of the attractor's own
+                            bounds
+  --perspective <DISTANCE>  Use a perspective projection, from DISTANCE radii away
+  --depth-shading <S>       Draw the furthest positions up to S (0 to 1) fainter than the
+                            nearest, once the camera has been moved [default: 0]
+  --from-file <FILE>        Render the positions saved in FILE (by `to_file` or
+                            --save-trajectory) instead of calculating them, at any size. The
+                            attractor comes from the file
+  --save-trajectory <FILE>  Also save every position to FILE, in a compact binary format
+  --dt <DT>                 Time step for flows [default: depends on the flow]
+  --transient <N>           Steps to discard before recording flows [default: 1000]
+  --rk45 <TOLERANCE>        Integrate flows with adaptive RK45 instead of RK4
+  --output <FILE.png>       Where to save the image
+
+Rendered images remember how they were rendered. `rerender` renders an image again from what
+it remembers, with any OPTIONS changed (like a larger --width and --height, or --palette-file
+for a palette which came from a file). Images split into posters don't remember anything.
+
+`window` shows any attractor (including 3D ones) in a window, through a camera which can be
+moved with the same keys as the main window. Running with no command opens the main window,
+which only shows Clifford attractors.
+
+Batch options (render every line of a specials file, in parallel):
+  --specials <FILE>         File of parameters like a=1,b=2,c=3,d=4
+                            [default: cache/clifford/special.txt]
+  --output-dir <DIR>        Where to save the images [default: cache/clifford/batch]
+  --indices <RANGES>        Only render these lines (counting from 0), like 0-99,250
+  --workers <N>             Number of attractors to render at once [default: number of CPUs]
+  --thumbnail <PIXELS>      Size of each image on the contact sheet [default: 100]
+  --contact-sheet <FILE>    Where to save the contact sheet
+                            [default: <output-dir>/contact_sheet.png]
+
+Search options (score random parameters and save the best ones as specials):
+  --samples <N>             Number of random parameter sets to try [default: 1000]
+  --top <N>                 Number of the best parameter sets to save [default: 10]
+  --iterations <N>          Iterations used to score each parameter set [default: 200000]
+  --range <R>               Sample parameters from [-R, R] [default: 4, or 1.2 for sprott]
+  --weights <L,D,C,E>       Weights of the Lyapunov exponent, dimension, coverage, and
+                            entropy in the score [default: 1,1,1,1]
+  --workers <N>             Number of parameter sets to score at once [default: number of CPUs]
+  --output <FILE>           Specials file to append to [default: cache/<NAME>/special.txt]
+";
+
+/// Everything needed to render an attractor to an image file.
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    /// The `Attractor::NAME` of the attractor to render.
+    pub attractor: String,
+    /// The parameters of the attractor. Flows use their default parameters if this is `None`.
+    pub params: Option<Vec<f64>>,
+    /// A Sprott letter code, used instead of `params` for Sprott attractors.
+    pub code: Option<String>,
+    pub width: usize,
+    pub height: usize,
+    /// The number of positions to calculate before rendering.
+    pub iterations: usize,
+    /// The fraction of the image left empty on each side.
+    pub border: f64,
+    pub colours: ColourScheme,
+    pub camera: Camera,
+    pub sampling: Sampling,
+    /// Render with `TiledRender`, so that images too large for memory can be rendered.
+    pub tiled: bool,
+    /// The number of rows in each tile, or `None` to choose automatically.
+    pub tile_rows: Option<usize>,
+    /// Draw the attractor within bounds fitted to a sample of its positions, instead of its own.
+    pub fit_bounds: bool,
+    /// How the bounds are fitted. The aspect ratio always comes from `width` and `height`.
+    pub bounds_fit: BoundsFit,
+    /// With more than one orbit, the iterations are split between orbits run in parallel.
+    pub orbits: Orbits,
+    /// Lay the image out on a page for printing. The width and height are then replaced by the
+    /// size of the attractor on the page.
+    pub print: Option<PrintLayout>,
+    /// Caption the page with the attractor's name and formula.
+    pub auto_caption: bool,
+    /// Split the image (or page) onto sheets of paper instead of saving it as one PNG.
+    pub poster: Option<Poster>,
+    /// Render the positions saved in this file by `Attractor::to_file` (or in the binary
+    /// `trajectory` format), instead of calculating them. The attractor is the one named in the
+    /// file.
+    pub from_file: Option<String>,
+    /// Stream the positions to this binary trajectory file as they're calculated, and render
+    /// the positions as they were saved.
+    pub save_trajectory: Option<String>,
+    /// Overrides the flow's default `dt`.
+    pub dt: Option<f64>,
+    /// Overrides the default number of transient steps for flows.
+    pub transient: Option<usize>,
+    pub integrator: Integrator,
+    /// Where the PNG is saved.
+    pub output: String,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            attractor: CliffordAttractor::NAME.to_string(),
+            params: None,
+            code: None,
+            width: 1000,
+            height: 1000,
+            iterations: 10_000_000,
+            border: 0.05,
+            colours: ColourScheme::default(),
+            camera: Camera::default(),
+            sampling: Sampling::default(),
+            tiled: false,
+            tile_rows: None,
+            fit_bounds: false,
+            bounds_fit: BoundsFit::default(),
+            orbits: Orbits::default(),
+            print: None,
+            auto_caption: false,
+            poster: None,
+            from_file: None,
+            save_trajectory: None,
+            dt: None,
+            transient: None,
+            integrator: Integrator::Rk4,
+            output: String::new(),
+        }
+    }
+}
+
+/// Parse the value of the flag `--name`.
+pub(crate) fn parse<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value
+        .parse::<T>()
+        .map_err(|_| format!("Couldn't understand `--{} {}`", name, value))
+}
+
+/// Command line flags, from each flag's name (without the `--`) to its value. They're kept sorted
+/// by name, so that they're always applied in the same order.
+pub type Flags = BTreeMap<String, String>;
+
+/// Split `--name value` pairs into a map from `name` to `value`. Each flag can only be given once.
+pub fn parse_flags(args: &[String]) -> Result<Flags, String> {
+    let mut flags = Flags::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let name = arg
+            .strip_prefix("--")
+            .ok_or_else(|| format!("Expected a flag like `--name` but found `{}`", arg))?;
+        let value = args
+            .next()
+            .ok_or_else(|| format!("The flag `--{}` needs a value", name))?;
+        if flags.insert(name.to_string(), value.to_string()).is_some() {
+            return Err(format!("`--{}` was given more than once", name));
+        }
+    }
+    Ok(flags)
+}
+
+impl RenderOptions {
+    /// Build the render options from command line arguments like `--width 1000 --height 500`.
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut opts = RenderOptions::default();
+        opts.update_from_flags(parse_flags(args)?)?;
+        if opts.output.is_empty() {
+            return Err("An output file must be given with `--output`".to_string());
+        }
+        Ok(opts)
+    }
+
+    /// Update the render options from already-parsed flags, returning an error for any flag
+    /// which isn't a render option.
+    pub fn update_from_flags(&mut self, mut flags: Flags) -> Result<(), String> {
+        // The palette might be in the palette file, so it can't be found until the file is read
+        if let Some(name) = flags.remove("palette") {
+            let palettes = match flags.remove("palette-file") {
+                Some(file) => load_palettes(&file).map_err(|e| format!("{}: {}", file, e))?,
+                None => vec![],
+            };
+            let palette = Palette::find(&name, &palettes)
+                .ok_or_else(|| format!("Unknown palette `{}`", name))?;
+            self.colours.palette = Some(palette);
+        } else if flags.contains_key("palette-file") {
+            return Err("`--palette-file` needs a `--palette` to choose from it".to_string());
+        }
+        let attractor_given = flags.contains_key("attractor");
+        let paper_given = flags.contains_key("paper");
+        let poster_given = flags.contains_key("poster");
+        let mut dpi = None;
+        if paper_given && (flags.contains_key("width") || flags.contains_key("height")) {
+            return Err("`--width` and `--height` can't be used with `--paper`".to_string());
+        }
+        if flags.contains_key("tone") && flags.contains_key("light-power") {
+            return Err("`--light-power` can't be used with `--tone`".to_string());
+        }
+        for (name, value) in flags {
+            let n = name.as_str();
+            match n {
+                "attractor" => self.attractor = value,
+                "params" => {
+                    self.params = Some(
+                        value
+                            .split(',')
+                            .map(|p| parse(n, p.trim()))
+                            .collect::<Result<_, _>>()?,
+                    )
+                }
+                "code" => self.code = Some(value),
+                "width" => self.width = parse(n, &value)?,
+                "height" => self.height = parse(n, &value)?,
+                "iterations" => self.iterations = parse(n, &value)?,
+                "border" => self.border = parse(n, &value)?,
+                "background" => match value.as_str() {
+                    "dark" => self.colours.lch.set_dark_background(),
+                    "light" => self.colours.lch.set_light_background(),
+                    _ => return Err(format!("Unknown background `{}`", value)),
+                },
+                "hue" => self.colours.lch.hue_intercept = parse(n, &value)?,
+                "hue-slope" => self.colours.lch.hue_slope = parse(n, &value)?,
+                "tone" => self.colours.tone.curve = value.parse()?,
+                "clip" => {
+                    let clip = value
+                        .split_once(',')
+                        .ok_or_else(|| format!("`--{}` needs two percentiles like 0,99.9", n))?;
+                    self.colours.tone.clip = (parse(n, clip.0.trim())?, parse(n, clip.1.trim())?);
+                    if !(0.0 <= self.colours.tone.clip.0
+                        && self.colours.tone.clip.0 < self.colours.tone.clip.1
+                        && self.colours.tone.clip.1 <= 100.0)
+                    {
+                        return Err(format!("`--{}` must be two increasing percentiles", n));
+                    }
+                }
+                "light-power" => self.colours.tone.curve = ToneCurve::Gamma(parse(n, &value)?),
+                "splat" => self.sampling.splat = value.parse()?,
+                "supersample" => self.sampling.supersample = parse(n, &value)?,
+                "filter" => self.sampling.filter = value.parse()?,
+                "tile-rows" => {
+                    self.tiled = true;
+                    self.tile_rows = match value.as_str() {
+                        "auto" => None,
+                        _ => Some(parse(n, &value)?),
+                    };
+                }
+                "fit-bounds" => {
+                    self.fit_bounds = true;
+                    self.bounds_fit.trim = parse(n, &value)?;
+                    if !(0.0..50.0).contains(&self.bounds_fit.trim) {
+                        return Err(format!("`--{}` must be in [0, 50)", n));
+                    }
+                }
+                "burn-in" => self.bounds_fit.burn_in = parse(n, &value)?,
+                "orbits" => self.orbits.count = parse(n, &value)?,
+                "orbit-transient" => self.orbits.transient = parse(n, &value)?,
+                "jitter" => self.orbits.jitter = parse(n, &value)?,
+                "seed" => self.orbits.seed = parse(n, &value)?,
+                "threads" => self.orbits.workers = parse(n, &value)?,
+                "paper" | "orientation" | "margin" | "bleed" | "caption" => {
+                    let layout = self.print.get_or_insert_with(PrintLayout::default);
+                    match n {
+                        "paper" => layout.paper = value.parse()?,
+                        "orientation" => layout.orientation = value.parse()?,
+                        "margin" => layout.margin_mm = parse(n, &value)?,
+                        "bleed" => layout.bleed_mm = parse(n, &value)?,
+                        _ if value == "auto" => self.auto_caption = true,
+                        _ => layout.caption = Some(value.replace("\\n", "\n")),
+                    }
+                }
+                "dpi" => dpi = Some(parse::<f64>(n, &value)?),
+                "poster" => {
+                    self.poster.get_or_insert_with(Poster::default).sheet.paper = value.parse()?
+                }
+                "overlap" => {
+                    self.poster.get_or_insert_with(Poster::default).overlap_mm = parse(n, &value)?
+                }
+                "yaw" => self.camera.yaw = parse(n, &value)?,
+                "pitch" => self.camera.pitch = parse(n, &value)?,
+                "roll" => self.camera.roll = parse(n, &value)?,
+                "zoom" => self.camera.zoom = parse(n, &value)?,
+                "depth-shading" => self.camera.depth_shading = parse(n, &value)?,
+                "viewport" => {
+                    let edges = value
+                        .split(',')
+                        .map(|e| parse(n, e.trim()))
+                        .collect::<Result<Vec<f64>, _>>()?;
+                    let [xmin, xmax, ymin, ymax] = edges[..] else {
+                        return Err(format!("`--{}` needs four numbers like -2,2,-1,1", n));
+                    };
+                    if !(xmin < xmax && ymin < ymax) {
+                        return Err(format!("`--{}` must be XMIN,XMAX,YMIN,YMAX", n));
+                    }
+                    self.camera.viewport = Some(Bounds {
+                        xmin,
+                        xmax,
+                        ymin,
+                        ymax,
+                    });
+                }
+                "perspective" => {
+                    self.camera.projection = Projection::Perspective {
+                        distance: parse(n, &value)?,
+                    }
+                }
+                "from-file" => self.from_file = Some(value),
+                "save-trajectory" => self.save_trajectory = Some(value),
+                "dt" => self.dt = Some(parse(n, &value)?),
+                "transient" => self.transient = Some(parse(n, &value)?),
+                "rk45" => {
+                    self.integrator = Integrator::Rk45 {
+                        tolerance: parse(n, &value)?,
+                    }
+                }
+                "output" => self.output = value,
+                _ => return Err(format!("Unknown option `--{}`", name)),
+            }
+        }
+        if self.width == 0 || self.height == 0 || self.sampling.supersample == 0 {
+            return Err("The width, height, and supersampling must be larger than 0".to_string());
+        }
+        if self.tiled && self.sampling.supersample > 1 {
+            return Err("`--supersample` can't be used with `--tile-rows`".to_string());
+        }
+        if self.orbits.count == 0 || self.orbits.workers == 0 {
+            return Err("The number of orbits and threads must be larger than 0".to_string());
+        }
+        if self.tiled && self.orbits.count > 1 {
+            return Err("`--orbits` can't be used with `--tile-rows`".to_string());
+        }
+        if let Some(filename) = &self.from_file {
+            if self.tiled || self.orbits.count > 1 {
+                return Err(
+                    "`--from-file` can't be used with `--tile-rows` or `--orbits`".to_string(),
+                );
+            }
+            if !attractor_given {
+                self.attractor =
+                    trajectory::saved_name(filename).map_err(|e| format!("{}: {}", filename, e))?;
+            }
+        }
+        if self.save_trajectory.is_some()
+            && (self.tiled || self.orbits.count > 1 || self.from_file.is_some())
+        {
+            return Err(
+                "`--save-trajectory` can't be used with `--tile-rows`, `--orbits`, or `--from-file`"
+                    .to_string(),
+            );
+        }
+        if self.camera.zoom.is_nan() || self.camera.zoom <= 0.0 {
+            return Err("The zoom must be larger than 0".to_string());
+        }
+        if let Projection::Perspective { distance } = self.camera.projection {
+            if distance.is_nan() || distance <= 1.0 {
+                return Err(
+                    "The perspective distance must be larger than 1, so the camera is outside \
+                     the attractor"
+                        .to_string(),
+                );
+            }
+        }
+        if self.camera.depth_shading != 0.0 {
+            if !(0.0..=1.0).contains(&self.camera.depth_shading) {
+                return Err("The depth shading must be between 0 and 1".to_string());
+            }
+            if self.camera.is_default() {
+                return Err("`--depth-shading` needs a moved camera, like `--yaw 0.5`".to_string());
+            }
+            if self.tiled || self.orbits.count > 1 {
+                return Err(
+                    "`--depth-shading` can't be used with `--tile-rows` or `--orbits`".to_string(),
+                );
+            }
+        }
+        if self.fit_bounds && self.camera.viewport.is_some() {
+            return Err("`--fit-bounds` can't be used with `--viewport`".to_string());
+        }
+        if let Some(layout) = &self.print {
+            if !paper_given {
+                return Err("Print options like `--margin` need a `--paper` size".to_string());
+            }
+            if !(layout.margin_mm >= 0.0 && layout.bleed_mm >= 0.0) {
+                return Err("The margin and bleed can't be negative".to_string());
+            }
+        }
+        if let Some(poster) = &self.poster {
+            if !poster_given {
+                return Err("`--overlap` needs a `--poster` sheet size".to_string());
+            }
+            if !(0.0..).contains(&poster.overlap_mm) {
+                return Err("The overlap can't be negative".to_string());
+            }
+        }
+        if let Some(dpi) = dpi {
+            if !(dpi > 0.0 && dpi.is_finite()) {
+                return Err("The DPI must be positive".to_string());
+            }
+            if self.print.is_none() && self.poster.is_none() {
+                return Err("`--dpi` needs a `--paper` or `--poster` size".to_string());
+            }
+            if let Some(layout) = &mut self.print {
+                layout.dpi = dpi;
+            }
+            if let Some(poster) = &mut self.poster {
+                poster.sheet.dpi = dpi;
+            }
+        }
+        Ok(())
+    }
+
+    /// The parameters for a map-based attractor, which must have been given.
+    fn required_params(&self) -> Result<Vec<f64>, String> {
+        self.params
+            .clone()
+            .ok_or_else(|| format!("`{}` attractors need `--params`", self.attractor))
+    }
+
+    /// Create a flow attractor, using the default parameters if none were given.
+    fn flow<F: Flow>(&self) -> Result<FlowAttractor<F>, String> {
+        let mut flow = match &self.params {
+            Some(params) => FlowAttractor::<F>::new(params.clone()).map_err(|e| e.to_string())?,
+            None => FlowAttractor::<F>::with_defaults(),
+        };
+        flow.dt = self.dt.unwrap_or(flow.dt);
+        flow.transient = self.transient.unwrap_or(flow.transient);
+        flow.integrator = self.integrator;
+        Ok(flow)
+    }
+
+    /// Create the attractor, step it `iterations` times, and save it to `output`.
+    pub fn render(&self) -> Result<(), String> {
+        print!("Rendering {} to {}...", self.attractor, self.output);
+        std::io::stdout().flush().ok();
+        let opts = self.laid_out()?;
+        opts.with_attractor(SaveImage(&opts))?;
+        println!("done");
+        Ok(())
+    }
+
+    /// A copy of these options with the width and height of the attractor on the page, and the
+    /// caption filled in, if there's a print layout. Poster sheets get the page's DPI.
+    fn laid_out(&self) -> Result<RenderOptions, String> {
+        let mut opts = self.clone();
+        if let Some(layout) = &mut opts.print {
+            if self.auto_caption {
+                layout.caption = Some(self.with_attractor(Caption)?);
+            }
+            let art = layout.art_area()?;
+            (opts.width, opts.height) = (art.width, art.height);
+            if let Some(poster) = &mut opts.poster {
+                poster.sheet.dpi = layout.dpi;
+            }
+        }
+        Ok(opts)
+    }
+
+    /// The width and height of the saved image, which is the whole page if there's a print
+    /// layout.
+    fn image_size(&self) -> (usize, usize) {
+        match &self.print {
+            Some(layout) => layout.page_size(),
+            None => (self.width, self.height),
+        }
+    }
+
+    /// Render `attractor` and give every row of the image (laid out on the page, if there's a
+    /// print layout) to `write`. Returns the bounds which were drawn, unless the camera isn't the
+    /// default camera.
+    fn write_rows<A: Attractor + Sync>(
+        &self,
+        attractor: A,
+        write: &mut Rows,
+    ) -> Result<Option<Bounds>, String> {
+        let art = |art_rows: &mut Rows| {
+            if self.tiled {
+                // Tiles are slow, so print a dot as each pass over the attractor starts
+                let progress = || {
+                    print!(".");
+                    std::io::stdout().flush().ok();
+                };
+                let bounds = self
+                    .tiled_render()
+                    .write_rows(&attractor, progress, art_rows)?;
+                Ok(self.camera.is_default().then_some(bounds))
+            } else {
+                let (image, bounds) = self.render_attractor(attractor)?;
+                art_rows(image.as_raw())?;
+                Ok(bounds)
+            }
+        };
+        match &self.print {
+            Some(layout) => layout.write_rows(&self.colours, art, write),
+            None => art(write),
+        }
+    }
+
+    /// Create the attractor, step it `iterations` times, and return the rendered image along
+    /// with the text describing how it was rendered (see `provenance`).
+    pub fn render_image(&self) -> Result<(RgbImage, Text), String> {
+        self.with_attractor(RenderImage(self))
+    }
+
+    /// Create the attractor described by these options, and run `job` with it.
+    pub fn with_attractor<J: AttractorJob>(&self, job: J) -> Result<J::Output, String> {
+        let err = |e: AttractorError| e.to_string();
+        match self.attractor.as_str() {
+            "clifford" => self.run_job(
+                job,
+                self.create(|| CliffordAttractor::new(self.required_params()?).map_err(err))?,
+            ),
+            "dejong" => self.run_job(
+                job,
+                self.create(|| DeJongAttractor::new(self.required_params()?).map_err(err))?,
+            ),
+            "sprott" => self.run_job(
+                job,
+                self.create(|| match &self.code {
+                    Some(code) => SprottAttractor::from_code(code).map_err(err),
+                    None => SprottAttractor::new(self.required_params()?).map_err(err),
+                })?,
+            ),
+            "sprottthreed" => self.run_job(
+                job,
+                self.create(|| match &self.code {
+                    Some(code) => Sprott3dAttractor::from_code(code).map_err(err),
+                    None => Sprott3dAttractor::new(self.required_params()?).map_err(err),
+                })?,
+            ),
+            "lorenz" => self.run_job(job, self.create(|| self.flow::<Lorenz>())?),
+            "rossler" => self.run_job(job, self.create(|| self.flow::<Rossler>())?),
+            "arneodo" => self.run_job(job, self.create(|| self.flow::<Arneodo>())?),
+            "burkeshaw" => self.run_job(job, self.create(|| self.flow::<BurkeShaw>())?),
+            "yuwang" => self.run_job(job, self.create(|| self.flow::<YuWang>())?),
+            "thomas" => self.run_job(job, self.create(|| self.flow::<Thomas>())?),
+            "aizawa" => self.run_job(job, self.create(|| self.flow::<Aizawa>())?),
+            _ => Err(format!("Unknown attractor `{}`", self.attractor)),
+        }
+    }
+
+    /// Load the attractor and its history from `from_file` if there is one (in either format),
+    /// otherwise create it with `new`.
+    fn create<A: Attractor>(&self, new: impl FnOnce() -> Result<A, String>) -> Result<A, String> {
+        let filename = match &self.from_file {
+            Some(filename) => filename,
+            None => return new(),
+        };
+        let loaded = match trajectory::is_binary(filename) {
+            Ok(true) => trajectory::load(filename),
+            Ok(false) => A::from_file(filename.clone()),
+            Err(e) => Err(e),
+        };
+        loaded.map_err(|e| format!("{}: {}", filename, e))
+    }
+
+    /// Run `job` with `attractor`, framed tightly if `fit_bounds` is set.
+    fn run_job<J: AttractorJob, A: Attractor + Sync>(
+        &self,
+        job: J,
+        attractor: A,
+    ) -> Result<J::Output, String> {
+        if !self.fit_bounds {
+            return job.run(attractor);
+        }
+        let fit = BoundsFit {
+            aspect: self.width as f64 / self.height as f64,
+            ..self.bounds_fit
+        };
+        job.run(attractor.framed(fit))
+    }
+
+    /// The tiled renderer for these options.
+    pub fn tiled_render(&self) -> TiledRender<'_> {
+        TiledRender {
+            width: self.width,
+            height: self.height,
+            iterations: self.iterations,
+            border: self.border,
+            colours: &self.colours,
+            camera: &self.camera,
+            splat: self.sampling.splat,
+            tile_rows: self.tile_rows,
+        }
+    }
+
+    /// Step `attractor` until it has `iterations` positions, then render it. Also returns the
+    /// bounds which were drawn, unless the camera isn't the default camera.
+    fn render_attractor<A: Attractor + Sync>(
+        &self,
+        mut attractor: A,
+    ) -> Result<(RgbImage, Option<Bounds>), String> {
+        let default_camera = self.camera.is_default();
+        if self.orbits.count > 1 {
+            let densities = self.orbits.densities(
+                &attractor,
+                self.iterations,
+                self.width,
+                self.height,
+                self.border,
+                &self.camera,
+                &self.sampling,
+            );
+            let image = densities_to_image(&densities, self.width, self.height, &self.colours);
+            let bounds = default_camera.then(|| self.orbits.bounds(&attractor, &self.camera));
+            return Ok((image, bounds));
+        }
+        if let Some(filename) = &self.save_trajectory {
+            let (densities, bounds) = self.save_and_draw(attractor, filename)?;
+            let image = densities_to_image(&densities, self.width, self.height, &self.colours);
+            return Ok((image, bounds));
+        }
+        // Attractors loaded from a file already have every position they're drawn with
+        while self.from_file.is_none() && attractor.history().len() < self.iterations {
+            let remaining = self.iterations - attractor.history().len();
+            attractor.step(remaining.min(5_000_000));
+        }
+        let mut camera = self.camera.clone();
+        let densities = camera.get_densities(
+            &mut attractor,
+            self.width,
+            self.height,
+            self.border,
+            &self.sampling,
+        );
+        let image = densities_to_image(&densities, self.width, self.height, &self.colours);
+        Ok((image, default_camera.then(|| camera.bounds_of(&attractor))))
+    }
+
+    /// Step `attractor` until it has `iterations` positions, saving each one to `filename` and
+    /// drawing it as it's saved, so that none of them are kept in memory. Positions are drawn as
+    /// they'll be read back from the file, so the file renders the same image. Returns the
+    /// densities, and the bounds which were drawn if the camera is the default camera.
+    fn save_and_draw<A: Attractor>(
+        &self,
+        mut attractor: A,
+        filename: &str,
+    ) -> Result<(Vec<f64>, Option<Bounds>), String> {
+        let remaining = self.iterations.saturating_sub(attractor.history().len());
+        let save = |attractor: &mut A, visit: &mut dyn FnMut(&[f64])| {
+            trajectory::save_steps(attractor, remaining, filename, Precision::F32, true, visit)
+                .map_err(|e| format!("{}: {}", filename, e))
+        };
+        // Nothing can be drawn until the bounds (or the fit of the camera) are known, so a copy
+        // is stepped through the same positions first, like a tiled render
+        let mut copy = attractor.empty_copy();
+        copy.set_history(attractor.history().to_vec());
+        let mut camera = self.camera.clone();
+        if camera.is_default() {
+            if camera.viewport.is_none() {
+                copy.step_with(remaining, |_| {});
+            }
+            let bounds = camera.bounds_of(&copy);
+            let mut histogram = Histogram::supersampled(
+                self.width,
+                self.height,
+                bounds,
+                self.border,
+                &self.sampling,
+            );
+            save(&mut attractor, &mut |pos| histogram.add(pos))?;
+            let densities = histogram
+                .downsampled(self.sampling.supersample.max(1), self.sampling.filter)
+                .normalised();
+            return Ok((densities, Some(bounds)));
+        }
+        let mut min = [f64::INFINITY; 3];
+        let mut max = [f64::NEG_INFINITY; 3];
+        let mut include = |pos: &[f64]| {
+            let mut saved = [0.0; 3];
+            for (saved, coord) in saved.iter_mut().zip(pos) {
+                *saved = Precision::F32.round(*coord);
+            }
+            include_in_box(&mut min, &mut max, [&saved[..pos.len()]]);
+        };
+        for pos in copy.history() {
+            include(pos);
+        }
+        copy.step_with(remaining, &mut include);
+        camera.fit_box(min, max);
+        let mut grid = camera.depth_histogram(self.width, self.height, self.border, &self.sampling);
+        save(&mut attractor, &mut |pos| grid.add(pos))?;
+        Ok((grid.projected().shaded(camera.depth_shading), None))
+    }
+}
+
+/// Something which can be done with any kind of attractor. Every attractor is a different type,
+/// so this lets `RenderOptions::with_attractor` create the attractor in one place for every job.
+pub trait AttractorJob {
+    type Output;
+    fn run<A: Attractor + Sync>(self, attractor: A) -> Result<Self::Output, String>;
+}
+
+/// Render the attractor to an image in memory, and describe how it was rendered.
+struct RenderImage<'a>(&'a RenderOptions);
+
+impl AttractorJob for RenderImage<'_> {
+    type Output = (RgbImage, Text);
+    fn run<A: Attractor + Sync>(self, attractor: A) -> Result<(RgbImage, Text), String> {
+        let (params, code) = (attractor.params(), attractor.code());
+        let (image, bounds) = self.0.render_attractor(attractor)?;
+        Ok((image, provenance::text(self.0, &params, code, bounds)))
+    }
+}
+
+/// Render the attractor and save it to the output file (with how it was rendered, so that it
+/// can be rendered again), split onto sheets if it's a poster.
+struct SaveImage<'a>(&'a RenderOptions);
+
+impl AttractorJob for SaveImage<'_> {
+    type Output = ();
+    fn run<A: Attractor + Sync>(self, attractor: A) -> Result<(), String> {
+        let opts = self.0;
+        let (width, height) = opts.image_size();
+        let (params, code) = (attractor.params(), attractor.code());
+        let rows = |write: &mut Rows| opts.write_rows(attractor, write);
+        match &opts.poster {
+            Some(poster) => {
+                poster.save(width, height, &opts.output, |write| rows(write).map(|_| ()))
+            }
+            None => {
+                let dpi = opts.print.as_ref().map(|layout| layout.dpi);
+                save_png(&opts.output, width, height, dpi, |write| {
+                    let bounds = rows(write)?;
+                    Ok(provenance::text(opts, &params, code, bounds))
+                })
+            }
+        }
+    }
+}
+
+/// The caption for a print of the attractor.
+struct Caption;
+
+impl AttractorJob for Caption {
+    type Output = String;
+    fn run<A: Attractor + Sync>(self, attractor: A) -> Result<String, String> {
+        Ok(print::caption(&attractor))
+    }
+}
+
+/// Run the command line interface with `args` (not including the program name), returning an
+/// error message if something went wrong.
+pub fn run(args: &[String]) -> Result<(), String> {
+    match args.first().map(|a| a.as_str()) {
+        Some("render") => RenderOptions::from_args(&args[1..])?.render(),
+        Some("rerender") => match args.get(1) {
+            Some(image) if !image.starts_with("--") => {
+                provenance::render_options(image, parse_flags(&args[2..])?)?.render()
+            }
+            _ => Err(format!(
+                "`rerender` needs an image to render again\n\n{}",
+                USAGE
+            )),
+        },
+        Some("batch") => BatchOptions::from_args(&args[1..])?.run(),
+        Some("search") => SearchOptions::from_args(&args[1..])?.run(),
+        Some("--help") | Some("-h") | Some("help") => {
+            print!("{}", USAGE);
+            Ok(())
+        }
+        Some(other) => Err(format!("Unknown command `{}`\n\n{}", other, USAGE)),
+        None => Err(USAGE.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampling::{Filter, Splat};
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_render_options() {
+        let opts = RenderOptions::from_args(&args(
+            "--attractor dejong --params 1.4,-2.3,2.4,-2.1 --width 30 --background light \
+             --perspective 3 --tone log:100 --clip 1,99 \
+             --splat bilinear --supersample 3 --filter tent --output out.png",
+        ))
+        .unwrap();
+        assert_eq!(opts.attractor, "dejong");
+        assert_eq!(opts.params, Some(vec![1.4, -2.3, 2.4, -2.1]));
+        assert_eq!((opts.width, opts.height), (30, 1000));
+        assert_eq!(opts.colours.lch.light_intercept, 1.0);
+        assert!(opts.colours.palette.is_none());
+        assert_eq!(opts.colours.tone.curve, ToneCurve::Log(100.0));
+        assert_eq!(opts.colours.tone.clip, (1.0, 99.0));
+        assert_eq!(
+            opts.sampling,
+            Sampling {
+                splat: Splat::Bilinear,
+                supersample: 3,
+                filter: Filter::Tent
+            }
+        );
+        assert_eq!(
+            opts.camera.projection,
+            Projection::Perspective { distance: 3.0 }
+        );
+        assert_eq!(opts.output, "out.png");
+    }
+
+    #[test]
+    fn saved_trajectories_are_rendered_without_stepping() {
+        let fname = std::env::temp_dir().join("attractors_from_file_test.txt");
+        let fname = fname.to_str().unwrap();
+        let mut clifford = CliffordAttractor::new(vec![-1.4, 1.6, 1.0, 0.7]).unwrap();
+        clifford.step(5_000);
+        clifford.to_file(fname.to_string()).unwrap();
+
+        let opts = RenderOptions::from_args(&args(&format!(
+            "--from-file {} --width 20 --height 10 --output out.png",
+            fname
+        )))
+        .unwrap();
+        assert_eq!(opts.attractor, "clifford");
+        let (image, _text) = opts.render_image().unwrap();
+        assert_eq!(image.dimensions(), (20, 10));
+        // The file says which attractor it's for
+        let wrong = format!("--from-file {} --attractor dejong --output out.png", fname);
+        assert!(RenderOptions::from_args(&args(&wrong))
+            .unwrap()
+            .render_image()
+            .is_err());
+        std::fs::remove_file(fname).unwrap();
+
+        // Binary trajectories render the same image they were saved with
+        let traj = std::env::temp_dir().join("attractors_save_trajectory_test.traj");
+        let traj = traj.to_str().unwrap();
+        let saved = RenderOptions::from_args(&args(&format!(
+            "--params -1.4,1.6,1.0,0.7 --iterations 5000 --width 20 --height 10 \
+             --save-trajectory {} --output out.png",
+            traj
+        )))
+        .unwrap();
+        let (saved, _text) = saved.render_image().unwrap();
+        let loaded = RenderOptions::from_args(&args(&format!(
+            "--from-file {} --width 20 --height 10 --output out.png",
+            traj
+        )))
+        .unwrap();
+        assert_eq!(loaded.render_image().unwrap().0, saved);
+
+        // Including flows seen through a moved camera
+        let saved = RenderOptions::from_args(&args(&format!(
+            "--attractor lorenz --iterations 5000 --width 20 --height 10 --yaw 0.5 \
+             --depth-shading 0.5 --save-trajectory {} --output out.png",
+            traj
+        )))
+        .unwrap();
+        let (saved, _text) = saved.render_image().unwrap();
+        let loaded = RenderOptions::from_args(&args(&format!(
+            "--from-file {} --width 20 --height 10 --yaw 0.5 --depth-shading 0.5 \
+             --output out.png",
+            traj
+        )))
+        .unwrap();
+        assert_eq!(loaded.render_image().unwrap().0, saved);
+        std::fs::remove_file(traj).unwrap();
+    }
+
+    #[test]
+    fn bad_arguments_are_errors() {
+        assert!(RenderOptions::from_args(&args("--width 10")).is_err());
+        assert!(RenderOptions::from_args(&args("--width ten --output a.png")).is_err());
+        assert!(RenderOptions::from_args(&args("--colour red --output a.png")).is_err());
+        assert!(RenderOptions::from_args(&args("--output")).is_err());
+        assert!(RenderOptions::from_args(&args("--width 1 --width 2 --output a.png")).is_err());
+        assert!(RenderOptions::from_args(&args("--palette nope --output a.png")).is_err());
+        assert!(RenderOptions::from_args(&args("--clip 99,1 --output a.png")).is_err());
+        assert!(RenderOptions::from_args(&args("--dpi 300 --output a.png")).is_err());
+        assert!(RenderOptions::from_args(&args("--overlap 5 --output a.png")).is_err());
+        assert!(RenderOptions::from_args(&args("--paper a4 --width 9 --output a.png")).is_err());
+        assert!(
+            RenderOptions::from_args(&args("--tone log:10 --light-power 0.5 --output a.png"))
+                .is_err()
+        );
+        assert!(RenderOptions::from_args(&args("--viewport 1,0,0,1 --output a.png")).is_err());
+        assert!(RenderOptions::from_args(&args("--viewport 0,1,0 --output a.png")).is_err());
+        assert!(RenderOptions::from_args(&args(
+            "--viewport 0,1,0,1 --fit-bounds 1 --output a.png"
+        ))
+        .is_err());
+        assert!(RenderOptions::from_args(&args("--depth-shading 0.5 --output a.png")).is_err());
+        assert!(
+            RenderOptions::from_args(&args("--yaw 1 --depth-shading 2 --output a.png")).is_err()
+        );
+        assert!(
+            RenderOptions::from_args(&args("--yaw 1 --depth-shading -0.5 --output a.png")).is_err()
+        );
+        assert!(
+            RenderOptions::from_args(&args("--yaw 1 --depth-shading 0.5 --output a.png")).is_ok()
+        );
+        assert!(RenderOptions::from_args(&args("--zoom 0 --output a.png")).is_err());
+        assert!(RenderOptions::from_args(&args("--zoom -2 --output a.png")).is_err());
+        assert!(RenderOptions::from_args(&args("--zoom 2 --output a.png")).is_ok());
+        assert!(RenderOptions::from_args(&args("--perspective 1 --output a.png")).is_err());
+        assert!(RenderOptions::from_args(&args("--perspective 0.5 --output a.png")).is_err());
+        assert!(RenderOptions::from_args(&args("--perspective 3 --output a.png")).is_ok());
+    }
+}
@@ -1,32 +1,50 @@
 #![feature(test)]
 extern crate minifb;
 extern crate test;
+mod accumulator;
 mod attractors;
+mod batch;
+mod camera;
+mod colour;
+mod diagnostics;
+mod flows;
+mod font;
+mod framing;
+mod headless;
+mod ode;
+mod palette;
+mod parallel;
+mod poster;
+mod print;
+mod provenance;
+mod sampling;
+mod search;
+mod tiled;
+mod tone;
+mod trajectory;
 use std::fs::File;
 use std::thread::{sleep, sleep_ms};
 use std::time::Duration;
-use std::{fmt::Display, fs::OpenOptions, io::BufWriter, path::Path};
+use std::{fs::OpenOptions, io::BufWriter, path::Path};
 
+use crate::accumulator::Accumulator;
 use crate::attractors::*;
-use image::{ImageBuffer, RgbImage};
-use minifb::{clamp, CursorStyle, Key, MouseButton, MouseMode, Window, WindowOptions};
+use crate::camera::{Camera, Projection};
+use crate::colour::*;
+use crate::diagnostics::{
+    box_counting_dimension, classify, correlation_dimension, dimension_samples, lyapunov_exponent,
+    DimensionEstimate,
+};
+use crate::headless::{AttractorJob, RenderOptions};
+use crate::palette::Palette;
+use crate::poster::Poster;
+use crate::print::{IsoPaper, Paper, PrintLayout};
+use crate::sampling::{Sampling, Splat};
+use crate::trajectory::Precision;
+use minifb::{clamp, CursorStyle, Key, KeyRepeat, MouseButton, MouseMode, Window, WindowOptions};
 use rand::Rng;
 use std::io::{prelude::*, BufReader};
 
-enum IsoPaper {
-    A0,
-    A1,
-    A2,
-    A3,
-    A4,
-    A5,
-}
-
-const A0_600_DPI: (usize, usize) = (19866, 28087);
-const A1_600_DPI: (usize, usize) = (14043, 19866);
-const _A2_600_DPI: (usize, usize) = (9933, 14043);
-const A3_600_DPI: (usize, usize) = (7016, 9933);
-const _A4_600_DPI: (usize, usize) = (4960, 7016);
 const ISO_PAPER_FORMAT: (usize, usize) = (636, 900);
 
 const REELS_WIDTH: usize = 506;
@@ -41,21 +59,49 @@ const WIDTH: usize = ISO_PAPER_FORMAT.0;
 const HEIGHT: usize = ISO_PAPER_FORMAT.1;
 const FIRST_DRAW_SIZE: usize = 9_000_000;
 const MIN_NUM_STEPS: usize = 200_000;
+/// How many random parameters `R` tries before giving up on finding a chaotic attractor.
+const MAX_RANDOM_ATTEMPTS: usize = 100;
+/// Extra palettes for the live window, in the format described in `palette.rs`.
+const PALETTE_FILE: &str = "palettes.toml";
+/// The number of independent orbits the `S` export splits its positions between.
+const EXPORT_ORBITS: usize = 64;
+/// How much one notch of the mouse wheel zooms the viewport in or out.
+const ZOOM_PER_SCROLL: f64 = 1.1;
+/// How far the camera rotates (in radians) or zooms (as a fraction) each frame a key is held.
+const CAMERA_STEP: f64 = 0.02;
+/// How much each press of `D` changes the camera's depth shading.
+const DEPTH_SHADING_STEP: f64 = 0.1;
 
 fn main() {
+    // With any command line arguments, render without opening a window
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(|a| a.as_str()) == Some("window") {
+        if let Err(e) = view_from_args(&args[1..]) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if !args.is_empty() {
+        if let Err(e) = headless::run(&args) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     // Create parameters for the clifford attractor
     let mut delta = 0.01;
     let mut specials = get_specials();
-    let mut clifford: CliffordAttractor = CliffordAttractor::new(vec![-1.4, 1.6, 1.0, 0.7]);
+    let mut clifford: CliffordAttractor = CliffordAttractor::new(vec![-1.4, 1.6, 1.0, 0.7])
+        .expect("Clifford attractors take 4 parameters");
     if let Some(ref specials) = specials {
         let mut rng = rand::thread_rng();
         let special_idx = rng.gen_range(0..specials.len());
-        clifford.set_params(vec![
-            Some(specials[special_idx][0]),
-            Some(specials[special_idx][1]),
-            Some(specials[special_idx][2]),
-            Some(specials[special_idx][3]),
-        ]);
+        let params = specials[special_idx].iter().map(|p| Some(*p)).collect();
+        if let Err(e) = clifford.set_params(params) {
+            println!("Skipping special #{}: {}", special_idx, e);
+        }
     }
     // clifford.to_file(format!(
     //         "cache/clifford/{}-a={}-b={}-c={}-d={}.txt",
@@ -79,11 +125,14 @@ fn main() {
     // current one, reducing stuttering or flashing effects
     let mut noodle_factor = 0.9;
 
+    // Pressing `g` cycles through the built in palettes, then any in the palette file
+    let palettes = live_palettes();
+
     let commands = vec![
         Command { // j -> a--
             keys: vec![Key::J],
-            action: Box::new(|clifford, _buffer, _keys, _lch, _specials, _decay_factor, delta| {
-                clifford.set_params(vec![Some(clifford.a - *delta), None, None, None]);
+            action: Box::new(|clifford, _buffer, _keys, _colours, _specials, _decay_factor, delta, _camera, _accumulate| {
+                clifford.set_params(vec![Some(clifford.a - *delta), None, None, None]).expect("Clifford attractors take 4 parameters");
                 clifford.reset();
                 clifford.step(MIN_NUM_STEPS);
             }),
@@ -92,8 +141,8 @@ fn main() {
         },
         Command { // k -> b--
             keys: vec![Key::K],
-            action: Box::new(|clifford, _buffer, _keys, _lch, _specials, _decay_factor, delta| {
-                clifford.set_params(vec![None, Some(clifford.b - *delta), None, None]);
+            action: Box::new(|clifford, _buffer, _keys, _colours, _specials, _decay_factor, delta, _camera, _accumulate| {
+                clifford.set_params(vec![None, Some(clifford.b - *delta), None, None]).expect("Clifford attractors take 4 parameters");
                 clifford.reset();
                 clifford.step(MIN_NUM_STEPS);
             }),
@@ -102,8 +151,8 @@ fn main() {
         },
         Command { // l -> c--
             keys: vec![Key::L],
-            action: Box::new(|clifford, _buffer, _keys, _lch, _specials, _decay_factor, delta| {
-                clifford.set_params(vec![None, None, Some(clifford.c - *delta), None]);
+            action: Box::new(|clifford, _buffer, _keys, _colours, _specials, _decay_factor, delta, _camera, _accumulate| {
+                clifford.set_params(vec![None, None, Some(clifford.c - *delta), None]).expect("Clifford attractors take 4 parameters");
                 clifford.reset();
                 clifford.step(MIN_NUM_STEPS);
             }),
@@ -112,8 +161,8 @@ fn main() {
         },
         Command { // ; -> d--
             keys: vec![Key::Semicolon],
-            action: Box::new(|clifford, _buffer, _keys, _lch, _specials, _decay_factor, delta| {
-                clifford.set_params(vec![None, None, None, Some(clifford.d - *delta)]);
+            action: Box::new(|clifford, _buffer, _keys, _colours, _specials, _decay_factor, delta, _camera, _accumulate| {
+                clifford.set_params(vec![None, None, None, Some(clifford.d - *delta)]).expect("Clifford attractors take 4 parameters");
                 clifford.reset();
                 clifford.step(MIN_NUM_STEPS);
             }),
@@ -122,8 +171,8 @@ fn main() {
         },
         Command { // u -> a++
             keys: vec![Key::U],
-            action: Box::new(|clifford, _buffer, _keys, _lch, _specials, _decay_factor, delta| {
-                clifford.set_params(vec![Some(clifford.a + *delta), None, None, None]);
+            action: Box::new(|clifford, _buffer, _keys, _colours, _specials, _decay_factor, delta, _camera, _accumulate| {
+                clifford.set_params(vec![Some(clifford.a + *delta), None, None, None]).expect("Clifford attractors take 4 parameters");
                 clifford.reset();
                 clifford.step(MIN_NUM_STEPS);
             }),
@@ -132,8 +181,8 @@ fn main() {
         },
         Command { // i -> b++
             keys: vec![Key::I],
-            action: Box::new(|clifford, _buffer, _keys, _lch, _specials, _decay_factor, delta| {
-                clifford.set_params(vec![None, Some(clifford.b + *delta), None, None]);
+            action: Box::new(|clifford, _buffer, _keys, _colours, _specials, _decay_factor, delta, _camera, _accumulate| {
+                clifford.set_params(vec![None, Some(clifford.b + *delta), None, None]).expect("Clifford attractors take 4 parameters");
                 clifford.reset();
                 clifford.step(MIN_NUM_STEPS);
             }),
@@ -142,8 +191,8 @@ fn main() {
         },
         Command { // o -> c++
             keys: vec![Key::O],
-            action: Box::new(|clifford, _buffer, _keys, _lch, _specials, _decay_factor, delta| {
-                clifford.set_params(vec![None, None, Some(clifford.c + *delta), None]);
+            action: Box::new(|clifford, _buffer, _keys, _colours, _specials, _decay_factor, delta, _camera, _accumulate| {
+                clifford.set_params(vec![None, None, Some(clifford.c + *delta), None]).expect("Clifford attractors take 4 parameters");
                 clifford.reset();
                 clifford.step(MIN_NUM_STEPS);
             }),
@@ -152,8 +201,8 @@ fn main() {
         },
         Command { // p -> d++
             keys: vec![Key::P],
-            action: Box::new(|clifford, _buffer, _keys, _lch, _specials, _decay_factor, delta| {
-                clifford.set_params(vec![None, None, None, Some(clifford.d + *delta)]);
+            action: Box::new(|clifford, _buffer, _keys, _colours, _specials, _decay_factor, delta, _camera, _accumulate| {
+                clifford.set_params(vec![None, None, None, Some(clifford.d + *delta)]).expect("Clifford attractors take 4 parameters");
                 clifford.reset();
                 clifford.step(MIN_NUM_STEPS);
             }),
@@ -162,10 +211,10 @@ fn main() {
         },
         Command { // Hue Intercept
             keys: vec![Key::E],
-            action: Box::new(|clifford, _buffer, keys, lch, _specials, _decay_factor, _delta| {
+            action: Box::new(|clifford, _buffer, keys, colours, _specials, _decay_factor, _delta, _camera, _accumulate| {
                 let sign = if keys.contains(&Key::LeftShift) { -1.0 } else { 1.0 };
-                lch.hue_intercept += 0.01 * sign;
-                println!("{:#}", lch);
+                colours.lch.hue_intercept += 0.01 * sign;
+                println!("{:#}", colours.lch);
                 clifford.step(1);
             }),
             description: "Increase or decrease the LCH hue intercept by 0.01".to_string(),
@@ -173,93 +222,146 @@ fn main() {
         },
         Command { // Reset and randomise
             keys: vec![Key::R],
-            action: Box::new(|clifford, buffer, keys, _lch, specials, _decay_factor, _delta| {
+            action: Box::new(|clifford, buffer, keys, _colours, specials, _decay_factor, _delta, _camera, _accumulate| {
                 for item in buffer.iter_mut() { *item = 0; }
-                // If r => Choose random from specials
-                if !keys.contains(&Key::LeftShift) {
-                    if let Some(specials) = specials {
-                        let mut rng = rand::thread_rng();
+                let mut rng = rand::thread_rng();
+                for attempt in 1..=MAX_RANDOM_ATTEMPTS {
+                    // If r => Choose random from specials
+                    if !keys.contains(&Key::LeftShift) {
+                        // Without any specials there's nothing to choose from
+                        let Some(specials) = specials else { break };
                         let special_idx = rng.gen_range(0..specials.len());
-                        clifford.set_params(vec![
-                                            Some(specials[special_idx][0]),
-                                            Some(specials[special_idx][1]),
-                                            Some(specials[special_idx][2]),
-                                            Some(specials[special_idx][3]),
-                        ]);
+                        let params = specials[special_idx].iter().map(|p| Some(*p)).collect();
+                        match clifford.set_params(params) {
+                            // The specials were picked by hand, so show them however they look
+                            Ok(()) => break,
+                            Err(e) => {
+                                println!("Skipping special #{}: {}", special_idx, e);
+                                continue;
+                            }
+                        }
                     }
-                } else {
                     // If R => Choose random range -4, 4
-                    let mut rng = rand::thread_rng();
                     clifford.set_params(vec![
                                         Some(rng.gen_range(-4.0..4.0)),
                                         Some(rng.gen_range(-4.0..4.0)),
                                         Some(rng.gen_range(-4.0..4.0)),
                                         Some(rng.gen_range(-4.0..4.0)),
-                    ]);
-                };
+                    ]).expect("Clifford attractors take 4 parameters");
+                    // Keep trying until the attractor is chaotic, so that we don't land on a
+                    // boring dot or loop
+                    let classification = classify(&*clifford, 1_000, 2_000);
+                    if classification.is_interesting() {
+                        break;
+                    }
+                    println!("Rejected a {} attractor (attempt {}/{})", classification, attempt, MAX_RANDOM_ATTEMPTS);
+                }
                 clifford.reset();
                 clifford.step(MIN_NUM_STEPS);
             }),
-            description: "Randomize the Clifford parameters and re-run the attractor with these new parameters".to_string(),
+            description: "Randomize the Clifford parameters (skipping random ones which aren't chaotic) and re-run the attractor with these new parameters".to_string(),
             enabled: true,
         },
         Command { // Print to disc
             keys: vec![Key::S],
-            action: Box::new(|clifford, _buffer, keys, lch, _specials, _decay_factor, _delta| {
-                let filename = format!("cache/clifford/a={:.6}_b={:.6}_c={:.6}_d={:.6}_iters={}.png", clifford.a, clifford.b, clifford.c, clifford.d, clifford.history.len());
-                print!("Saving data to {}", filename);
-                let size = if keys.contains(&Key::LeftShift) { A0_600_DPI } else { A3_600_DPI };
-                let mut image: RgbImage = ImageBuffer::new(size.0.try_into().unwrap(), size.1.try_into().unwrap());
-                while clifford.history.len() < 40_000_000 {
-                    print!(".");
-                    clifford.step(5_000_000);
+            action: Box::new(|clifford, _buffer, keys, colours, _specials, _decay_factor, _delta, camera, _accumulate| {
+                let name = format!("cache/clifford/a={:.6}_b={:.6}_c={:.6}_d={:.6}_iters={}", clifford.a, clifford.b, clifford.c, clifford.d, 40_000_000);
+                // Render just like `attractors render` does, so the image remembers how it was made
+                let mut opts = RenderOptions {
+                    attractor: CliffordAttractor::NAME.to_string(),
+                    params: Some(clifford.params()),
+                    iterations: 40_000_000,
+                    colours: colours.clone(),
+                    camera: camera.clone(),
+                    // Share each position between the nearest pixels, so thin lines print smoothly
+                    sampling: Sampling { splat: Splat::Bilinear, ..Sampling::default() },
+                    print: Some(PrintLayout::default()),
+                    ..RenderOptions::default()
+                };
+                if keys.contains(&Key::LeftShift) || keys.contains(&Key::LeftCtrl) {
+                    // A0 is too large to render in memory, so render it a few rows at a time
+                    opts.print = Some(PrintLayout { paper: Paper::Iso(IsoPaper::A0), ..PrintLayout::default() });
+                    opts.tiled = true;
+                    if keys.contains(&Key::LeftCtrl) {
+                        // Split the A0 page onto A3 sheets, for printers which can't print A0
+                        opts.poster = Some(Poster::default());
+                        opts.output = format!("{}_a0_on_a3.png", name);
+                    } else {
+                        opts.output = format!("{}_a0.png", name);
+                    }
+                } else {
+                    // Split the positions between orbits on every core, instead of one long
+                    // history (orbits can't be shaded by depth though)
+                    if camera.depth_shading == 0.0 {
+                        opts.orbits.count = EXPORT_ORBITS;
+                    }
+                    opts.output = format!("{}.png", name);
                 }
-                let densities = clifford.get_densities_with_border(size.0 as usize, size.1 as usize, 0.05);
-                for (i, val) in densities.iter().enumerate() {
-                    let packed = hsla_to_u32(
-                        val * lch.hue_slope + lch.hue_intercept,
-                        val * lch.chroma_slope + lch.chroma_intercept,
-                        // The lightness is inversely proportional to the size of of the grid, so
-                        // adjust the lightness power accordingly
-                        val.powf(0.1) * lch.light_slope + lch.light_intercept,
-                        0.0,
-                    );
-                    let x = i % size.0 as usize;
-                    let y = i / size.0 as usize;
-                    let (_a, r, g, b) = u32_to_argb(packed);
-                    image.put_pixel(x as u32, y as u32, image::Rgb([r, g, b]));
+                if let Err(e) = opts.render() {
+                    println!("failed: {}", e);
                 }
-                image.save(filename).unwrap();
-                println!("done");
             }),
             description: "Save the attractor in high resolution to disc as png".to_string(),
             enabled: true,
         },
+        Command { // Write the trajectory to disc, to be rendered later with `render --from-file`
+            keys: vec![Key::W],
+            action: Box::new(|clifford, _buffer, _keys, _colours, _specials, _decay_factor, _delta, _camera, _accumulate| {
+                let filename = format!("cache/clifford/a={:.6}_b={:.6}_c={:.6}_d={:.6}_iters={}.traj", clifford.a, clifford.b, clifford.c, clifford.d, 40_000_000);
+                println!("Writing trajectory to {}", filename);
+                // Stream the positions straight to the file, instead of keeping them all in memory
+                let mut copy = clifford.empty_copy();
+                match trajectory::save_steps(&mut copy, 40_000_000, &filename, Precision::F32, true, |_| {}) {
+                    Ok(()) => println!("done"),
+                    Err(e) => println!("failed: {}", e),
+                }
+                sleep(Duration::from_millis(150));
+            }),
+            description: "Write 40 million positions of the attractor to disc, to render later".to_string(),
+            enabled: true,
+        },
         Command { // Change from black bg to white bg
             keys: vec![Key::B],
-            action: Box::new(|clifford, _buffer, _keys, lch, _specials, _decay_factor, _delta| {
-                if lch.light_intercept == 1.0 {
-                    // Dark background
-                    lch.light_intercept = 0.0;
-                    lch.light_slope = 1.0;
-                    lch.chroma_intercept = 1.5;
-                    lch.chroma_slope = 0.2;
+            action: Box::new(|clifford, _buffer, _keys, colours, _specials, _decay_factor, _delta, _camera, _accumulate| {
+                if colours.lch.light_intercept == 1.0 {
+                    colours.lch.set_dark_background();
                 } else {
-                    // Light background
-                    lch.light_intercept = 1.0;
-                    lch.light_slope = -1.0;
-                    lch.chroma_intercept = 0.7;
-                    lch.chroma_slope = 1.5;
+                    colours.lch.set_light_background();
                 }
-                println!("Inverted colours:\n{:#}", lch);
+                println!("Inverted colours:\n{:#}", colours.lch);
                 clifford.step(1);
             }),
             description: "Change between black and white backgrounds".to_string(),
             enabled: true,
         },
+        Command { // Cycle through the tone curves
+            keys: vec![Key::H],
+            action: Box::new(|clifford, _buffer, _keys, colours, _specials, _decay_factor, _delta, _camera, _accumulate| {
+                colours.tone.curve = colours.tone.curve.next();
+                println!("{}", colours.tone);
+                clifford.step(1);
+                sleep(Duration::from_millis(150));
+            }),
+            description: "Cycle through the tone curves (gamma, log, asinh, and histogram equalisation)".to_string(),
+            enabled: true,
+        },
+        Command { // Cycle through the palettes
+            keys: vec![Key::G],
+            action: Box::new(move |clifford, _buffer, keys, colours, _specials, _decay_factor, _delta, _camera, _accumulate| {
+                colours.cycle_palette(&palettes, !keys.contains(&Key::LeftShift));
+                match &colours.palette {
+                    Some(palette) => println!("Using palette {}", palette.name),
+                    None => println!("Using the LCh colours"),
+                }
+                clifford.step(1);
+                sleep(Duration::from_millis(150));
+            }),
+            description: "Cycle forwards (or backwards with shift) through the gradient palettes".to_string(),
+            enabled: true,
+        },
         Command { // Mark the location as 'special'
             keys: vec![Key::M],
-            action: Box::new(|clifford, _buffer, _keys, _lch, specials, _decay_factor, _delta| {
+            action: Box::new(|clifford, _buffer, _keys, _colours, specials, _decay_factor, _delta, _camera, _accumulate| {
                 let filename = "cache/clifford/special.txt";
                 if let Some(specials) = specials {
                     specials.push(vec![clifford.a, clifford.b, clifford.c, clifford.d]);
@@ -300,7 +402,7 @@ fn main() {
         },
         Command { // Change how quickly images blend together (helps with image flickering)
             keys: vec![Key::N],
-            action: Box::new(|_clifford, _buffer, keys, _lch, _specials, noodle_factor, _delta| {
+            action: Box::new(|_clifford, _buffer, keys, _colours, _specials, noodle_factor, _delta, _camera, _accumulate| {
                 let sign = if keys.contains(&Key::LeftShift) { -1.0 } else { 1.0 };
                 *noodle_factor = f64::min(1.0, f64::max(0.05, *noodle_factor + sign * 0.05));
                 println!("noodle_factor: {}", noodle_factor);
@@ -310,7 +412,7 @@ fn main() {
         },
         Command { // Change the amount by which to step the parameters
             keys: vec![Key::T],
-            action: Box::new(|_clifford, _buffer, keys, _lch, _specials, _noodle_factor, delta| {
+            action: Box::new(|_clifford, _buffer, keys, _colours, _specials, _noodle_factor, delta, _camera, _accumulate| {
                 let sign = if keys.contains(&Key::LeftShift) { -1 } else { 1 };
                 *delta = *delta * 10.0_f64.powi(sign); // Either multiply or divide by 10
                 println!("delta: {delta}");
@@ -319,6 +421,98 @@ fn main() {
             description: "Change how quickly the parameter values are changed".to_string(),
             enabled: true,
         },
+        Command { // Rotate the camera left or right
+            keys: vec![Key::Left],
+            action: Box::new(|_clifford, _buffer, _keys, _colours, _specials, _decay_factor, _delta, camera, _accumulate| {
+                camera.yaw -= CAMERA_STEP;
+            }),
+            description: "Rotate the camera to the left (yaw)".to_string(),
+            enabled: true,
+        },
+        Command {
+            keys: vec![Key::Right],
+            action: Box::new(|_clifford, _buffer, _keys, _colours, _specials, _decay_factor, _delta, camera, _accumulate| {
+                camera.yaw += CAMERA_STEP;
+            }),
+            description: "Rotate the camera to the right (yaw)".to_string(),
+            enabled: true,
+        },
+        Command { // Tilt the camera up or down
+            keys: vec![Key::Up],
+            action: Box::new(|_clifford, _buffer, _keys, _colours, _specials, _decay_factor, _delta, camera, _accumulate| {
+                camera.pitch -= CAMERA_STEP;
+            }),
+            description: "Tilt the camera upwards (pitch)".to_string(),
+            enabled: true,
+        },
+        Command {
+            keys: vec![Key::Down],
+            action: Box::new(|_clifford, _buffer, _keys, _colours, _specials, _decay_factor, _delta, camera, _accumulate| {
+                camera.pitch += CAMERA_STEP;
+            }),
+            description: "Tilt the camera downwards (pitch)".to_string(),
+            enabled: true,
+        },
+        Command { // Roll the camera
+            keys: vec![Key::Q],
+            action: Box::new(|_clifford, _buffer, keys, _colours, _specials, _decay_factor, _delta, camera, _accumulate| {
+                let sign = if keys.contains(&Key::LeftShift) { -1.0 } else { 1.0 };
+                camera.roll += sign * CAMERA_STEP;
+            }),
+            description: "Roll the camera clockwise (or anticlockwise with shift)".to_string(),
+            enabled: true,
+        },
+        Command { // Zoom the camera
+            keys: vec![Key::Z],
+            action: Box::new(|_clifford, _buffer, keys, _colours, _specials, _decay_factor, _delta, camera, _accumulate| {
+                let sign = if keys.contains(&Key::LeftShift) { -1.0 } else { 1.0 };
+                camera.zoom *= 1.0 + sign * CAMERA_STEP;
+            }),
+            description: "Zoom the camera in (or out with shift)".to_string(),
+            enabled: true,
+        },
+        Command { // Switch between orthographic and perspective projections
+            keys: vec![Key::V],
+            action: Box::new(|_clifford, _buffer, _keys, _colours, _specials, _decay_factor, _delta, camera, _accumulate| {
+                camera.projection = match camera.projection {
+                    Projection::Orthographic => Projection::Perspective { distance: 3.0 },
+                    Projection::Perspective { .. } => Projection::Orthographic,
+                };
+                println!("projection: {:?}", camera.projection);
+                sleep(Duration::from_millis(150));
+            }),
+            description: "Switch between orthographic and perspective projections".to_string(),
+            enabled: true,
+        },
+        Command { // Make the back of the attractor fainter than the front
+            keys: vec![Key::D],
+            action: Box::new(|_clifford, _buffer, keys, _colours, _specials, _decay_factor, _delta, camera, _accumulate| {
+                let sign = if keys.contains(&Key::LeftShift) { -1.0 } else { 1.0 };
+                camera.depth_shading = (camera.depth_shading + sign * DEPTH_SHADING_STEP).clamp(0.0, 1.0);
+                println!("depth shading: {:.1}", camera.depth_shading);
+                sleep(Duration::from_millis(150));
+            }),
+            description: "Shade the back of the attractor more (or less with shift) once the camera has been moved".to_string(),
+            enabled: true,
+        },
+        Command { // Switch between keeping the history and accumulating into a histogram
+            keys: vec![Key::A],
+            action: Box::new(|_clifford, _buffer, _keys, _colours, _specials, _decay_factor, _delta, _camera, accumulate| {
+                *accumulate = !*accumulate;
+                println!("accumulate: {}", accumulate);
+                sleep(Duration::from_millis(150));
+            }),
+            description: "Switch to accumulating positions into a histogram, which keeps refining the image without storing every position".to_string(),
+            enabled: true,
+        },
+        Command { // Reset the camera
+            keys: vec![Key::C],
+            action: Box::new(|_clifford, _buffer, _keys, _colours, _specials, _decay_factor, _delta, camera, _accumulate| {
+                *camera = Camera::default();
+            }),
+            description: "Reset the camera and viewport so the whole attractor is seen face-on".to_string(),
+            enabled: true,
+        },
         ];
     println!("=== List of Commands ===");
     for command in commands.iter() {
@@ -328,15 +522,7 @@ fn main() {
         );
     }
 
-    // These parameters have been manually tuned
-    let mut lch = LchParams {
-        light_intercept: 0.0, // no touchie
-        light_slope: 1.0,
-        chroma_intercept: 1.5,
-        chroma_slope: 0.2,
-        hue_intercept: 0.45,
-        hue_slope: 0.15, // values over 0.5 give a bit of a blowout effect
-    };
+    let mut colours = ColourScheme::default();
 
     let mut map_window = Window::new(
         "Map",
@@ -349,40 +535,78 @@ fn main() {
     .unwrap();
     map_window.set_position(0, 0);
 
-    // let mut diagnostics = Window::new(
-    //     "Diagnostics",
-    //     DIAG_WIDTH,
-    //     DIAG_HEIGHT,
-    //     WindowOptions {
-    //         ..WindowOptions::default()
-    //     },
-    // ).unwrap();
-    // diagnostics.set_position(0, 65 + MAP_HEIGHT as isize);
+    let mut diagnostics = Window::new(
+        "Diagnostics",
+        DIAG_WIDTH,
+        DIAG_HEIGHT,
+        WindowOptions {
+            ..WindowOptions::default()
+        },
+    )
+    .unwrap();
+    diagnostics.set_position(0, 65 + MAP_HEIGHT as isize);
     window.set_position(MAP_WIDTH as isize, 0);
 
+    // The camera is only used once it's been moved, until then the attractor is drawn as usual
+    let mut camera = Camera::default();
+    // When accumulating, positions are binned as they're calculated instead of being kept in the
+    // history, and the accumulator is replaced whenever the parameters or camera change
+    let mut accumulate = false;
+    let mut accumulator: Option<Accumulator> = None;
+    // Where the mouse was last frame while the left button was held down, for panning
+    let mut drag = None;
+
     clifford.step(MIN_NUM_STEPS);
     let mut densities;
     let mut prev_densities = vec![0f64; WIDTH * HEIGHT];
-    // let mut diag_buf = vec![0u32; DIAG_WIDTH * DIAG_HEIGHT];
+    let mut diag_buf = vec![0u32; DIAG_WIDTH * DIAG_HEIGHT];
+    let mut frame = 0;
+    let mut dimension = None;
+    let mut box_dimension = None;
     let mut map_buf = vec![0u32; MAP_WIDTH * MAP_HEIGHT];
     while window.is_open() && !window.is_key_down(Key::Escape) {
         // Then use those generated points to draw onto the buffer in
         // the appropriate spaces
-        if clifford.history.len() < 20_000_000 {
-            clifford.step(MIN_NUM_STEPS);
+        update_viewport(&window, &mut camera, &clifford, &mut drag);
+        if accumulate {
+            if !accumulator
+                .as_ref()
+                .is_some_and(|acc| acc.is_for(&clifford, &camera))
+            {
+                // The bounds and camera are fitted to the history, so make sure there is some
+                if clifford.history.len() < MIN_NUM_STEPS {
+                    clifford.step(MIN_NUM_STEPS);
+                }
+                accumulator = Some(Accumulator::new(
+                    &clifford,
+                    &camera,
+                    WIDTH,
+                    HEIGHT,
+                    0.05,
+                    &Sampling::default(),
+                ));
+            }
+            let acc = accumulator
+                .as_mut()
+                .expect("The accumulator was just created");
+            // Only the new positions get binned, so the image keeps sharpening at no extra cost
+            acc.step(&mut clifford, MIN_NUM_STEPS);
+            densities = acc.densities();
+            prev_densities.copy_from_slice(&densities);
+        } else {
+            accumulator = None;
+            if clifford.history.len() < 20_000_000 {
+                clifford.step(MIN_NUM_STEPS);
+            }
+            densities =
+                camera.get_densities(&mut clifford, WIDTH, HEIGHT, 0.05, &Sampling::default());
+            for (prev, density) in prev_densities.iter_mut().zip(&densities) {
+                *prev = noodle_factor * density + (1.0 - noodle_factor) * *prev;
+            }
         }
-        densities = clifford.get_densities_with_border(WIDTH, HEIGHT, 0.05);
         let avg_density = densities.iter().sum::<f64>() / densities.len() as f64;
-        for (i, item) in buffer.iter_mut().enumerate() {
-            prev_densities[i] =
-                noodle_factor * densities[i] + (1.0 - noodle_factor) * prev_densities[i];
-            *item = hsla_to_u32(
-                (prev_densities[i]) * lch.hue_slope + lch.hue_intercept,
-                (prev_densities[i]) * lch.chroma_slope + lch.chroma_intercept,
-                (prev_densities[i]).powf(0.3) * lch.light_slope + lch.light_intercept,
-                0.0,
-            );
-        }
+        // Use the same colours as the `S` export, so that the preview matches the saved image
+        buffer.copy_from_slice(&colours.densities_to_u32(&prev_densities));
         if map_window.is_open() {
             let mouse_pos = map_window.get_mouse_pos(MouseMode::Discard);
             let new_params = update_map(
@@ -398,7 +622,9 @@ fn main() {
                 .unwrap();
             // Only update the attractor if >0 of the parameters have changed
             if new_params.iter().any(|p| p.is_some()) {
-                clifford.set_params(new_params);
+                clifford
+                    .set_params(new_params)
+                    .expect("Clifford attractors take 4 parameters");
                 clifford.reset();
                 clifford.step(MIN_NUM_STEPS);
             }
@@ -411,19 +637,287 @@ fn main() {
                     &mut clifford,
                     &mut buffer,
                     &wind_keys,
-                    &mut lch,
+                    &mut colours,
                     &mut specials,
                     &mut noodle_factor,
                     &mut delta,
+                    &mut camera,
+                    &mut accumulate,
                 );
             }
         }
         window.update_with_buffer(&buffer, WIDTH, HEIGHT).unwrap();
 
-        // if diagnostics.is_open() {
-        //     update_diagnostics(&mut diag_buf, &clifford, avg_density);
-        //     diagnostics.update_with_buffer(&diag_buf, DIAG_WIDTH, DIAG_HEIGHT).unwrap();
-        // }
+        if diagnostics.is_open() {
+            let lyapunov = lyapunov_exponent(&clifford, 2_000);
+            update_diagnostics(&mut diag_buf, frame, lyapunov, avg_density);
+            // The dimensions are slower to calculate, so only update them now and then
+            if frame % 30 == 0 {
+                // While accumulating there's only one position in the history, so this runs a
+                // copy of the attractor on for a while instead
+                let samples = dimension_samples(&clifford, 100_000);
+                dimension = correlation_dimension(&samples, 1_000);
+                box_dimension = box_counting_dimension(&samples, 100_000);
+            }
+            let describe = |d: &Option<DimensionEstimate>| {
+                d.as_ref().map_or("unknown".to_string(), |d| d.to_string())
+            };
+            diagnostics.set_title(&format!(
+                "Lyapunov: {}, Dimension: {}, Box-counting: {}",
+                lyapunov.map_or("diverges".to_string(), |exp| format!("{:+.3}", exp)),
+                describe(&dimension),
+                describe(&box_dimension),
+            ));
+            diagnostics
+                .update_with_buffer(&diag_buf, DIAG_WIDTH, DIAG_HEIGHT)
+                .unwrap();
+        }
+        frame += 1;
+    }
+}
+
+/// The palettes which `g` cycles through: the built in palettes, then any in the palette file.
+fn live_palettes() -> Vec<Palette> {
+    let mut palettes = Palette::built_in();
+    if Path::new(PALETTE_FILE).exists() {
+        match palette::load_palettes(PALETTE_FILE) {
+            Ok(loaded) => palettes.extend(loaded),
+            Err(e) => println!("Couldn't load {}: {}", PALETTE_FILE, e),
+        }
+    }
+    palettes
+}
+
+/// Open a window showing the attractor described by `args` (render options like
+/// `--attractor lorenz --yaw 0.5`). Unlike the main window this works for every attractor,
+/// including 3D ones, but only the camera and colours can be changed, not the parameters.
+fn view_from_args(args: &[String]) -> Result<(), String> {
+    let mut opts = RenderOptions::default();
+    opts.update_from_flags(headless::parse_flags(args)?)?;
+    opts.with_attractor(View(&opts))
+}
+
+/// Show an attractor in a window of its own. See `view_from_args`.
+struct View<'a>(&'a RenderOptions);
+
+impl AttractorJob for View<'_> {
+    type Output = ();
+    fn run<A: Attractor + Sync>(self, attractor: A) -> Result<(), String> {
+        view(self.0, attractor)
+    }
+}
+
+/// Show `attractor` through a camera which can be moved around it, with the same keys as the main
+/// window, until the window is closed.
+fn view<A: Attractor + Sync>(opts: &RenderOptions, mut attractor: A) -> Result<(), String> {
+    let mut window = Window::new(
+        &format!("{} (hold esc to exit)", A::NAME),
+        WIDTH,
+        HEIGHT,
+        WindowOptions::default(),
+    )
+    .map_err(|e| e.to_string())?;
+    println!("=== List of Commands ===");
+    println!("`[Left]`/`[Right]`/`[Up]`/`[Down]` => Rotate the camera (yaw and pitch)");
+    println!("`[Q]`/`[Z]` => Roll/zoom the camera (the other way with shift)");
+    println!("`[V]` => Switch between orthographic and perspective projections");
+    println!("`[D]` => Shade the back of the attractor more (or less with shift)");
+    println!("`[C]` => Reset the camera");
+    println!("`[A]` => Switch to accumulating positions into a histogram (without depth shading)");
+    println!("`[H]`/`[G]`/`[B]` => Cycle the tone curves/palettes/backgrounds");
+    println!("`[S]` => Save the attractor in high resolution to disc as png");
+
+    let palettes = live_palettes();
+    let mut camera = opts.camera.clone();
+    let mut colours = opts.colours.clone();
+    let mut accumulate = false;
+    let mut accumulator: Option<Accumulator> = None;
+    let mut buffer = vec![0u32; WIDTH * HEIGHT];
+    let mut prev_densities = vec![0f64; WIDTH * HEIGHT];
+    let noodle_factor = 0.9;
+    attractor.step(MIN_NUM_STEPS);
+    while window.is_open() && !window.is_key_down(Key::Escape) {
+        let sign = if window.is_key_down(Key::LeftShift) {
+            -1.0
+        } else {
+            1.0
+        };
+        // The camera moves smoothly for as long as these keys are held down...
+        for key in window.get_keys() {
+            match key {
+                Key::Left => camera.yaw -= CAMERA_STEP,
+                Key::Right => camera.yaw += CAMERA_STEP,
+                Key::Up => camera.pitch -= CAMERA_STEP,
+                Key::Down => camera.pitch += CAMERA_STEP,
+                Key::Q => camera.roll += sign * CAMERA_STEP,
+                Key::Z => camera.zoom *= 1.0 + sign * CAMERA_STEP,
+                _ => {}
+            }
+        }
+        // ...but everything else only happens once per key press
+        for key in window.get_keys_pressed(KeyRepeat::No) {
+            match key {
+                Key::V => {
+                    camera.projection = match camera.projection {
+                        Projection::Orthographic => Projection::Perspective { distance: 3.0 },
+                        Projection::Perspective { .. } => Projection::Orthographic,
+                    };
+                    println!("projection: {:?}", camera.projection);
+                }
+                Key::D => {
+                    camera.depth_shading =
+                        (camera.depth_shading + sign * DEPTH_SHADING_STEP).clamp(0.0, 1.0);
+                    println!("depth shading: {:.1}", camera.depth_shading);
+                }
+                Key::C => camera = Camera::default(),
+                Key::A => {
+                    accumulate = !accumulate;
+                    println!("accumulate: {}", accumulate);
+                }
+                Key::H => {
+                    colours.tone.curve = colours.tone.curve.next();
+                    println!("{}", colours.tone);
+                }
+                Key::G => colours.cycle_palette(&palettes, sign > 0.0),
+                Key::B if colours.lch.light_intercept == 1.0 => colours.lch.set_dark_background(),
+                Key::B => colours.lch.set_light_background(),
+                Key::S => save_view(&attractor, opts, &colours, &camera),
+                _ => {}
+            }
+        }
+
+        let densities = if accumulate {
+            if !accumulator
+                .as_ref()
+                .is_some_and(|acc| acc.is_for(&attractor, &camera))
+            {
+                // The bounds and camera are fitted to the history, so make sure there is some
+                if attractor.history().len() < MIN_NUM_STEPS {
+                    attractor.step(MIN_NUM_STEPS);
+                }
+                accumulator = Some(Accumulator::new(
+                    &attractor,
+                    &camera,
+                    WIDTH,
+                    HEIGHT,
+                    0.05,
+                    &Sampling::default(),
+                ));
+            }
+            let acc = accumulator
+                .as_mut()
+                .expect("The accumulator was just created");
+            acc.step(&mut attractor, MIN_NUM_STEPS);
+            acc.densities()
+        } else {
+            accumulator = None;
+            if attractor.history().len() < 20_000_000 {
+                attractor.step(MIN_NUM_STEPS);
+            }
+            camera.get_densities(&mut attractor, WIDTH, HEIGHT, 0.05, &Sampling::default())
+        };
+        for (prev, density) in prev_densities.iter_mut().zip(&densities) {
+            *prev = noodle_factor * density + (1.0 - noodle_factor) * *prev;
+        }
+        buffer.copy_from_slice(&colours.densities_to_u32(&prev_densities));
+        window
+            .update_with_buffer(&buffer, WIDTH, HEIGHT)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Render `attractor` like the main window's `S` does, to `cache/<NAME>/` with a name made from
+/// its code (or its parameters, if it doesn't have a code).
+fn save_view<A: Attractor>(
+    attractor: &A,
+    opts: &RenderOptions,
+    colours: &ColourScheme,
+    camera: &Camera,
+) {
+    let layout = PrintLayout::default();
+    let (width, height) = layout.page_size();
+    let filename = batch::special_filename(
+        &attractor.param_names(),
+        &attractor.params(),
+        attractor.code().as_deref(),
+        width,
+        height,
+    );
+    let dir = Path::new("cache").join(A::NAME);
+    let mut opts = RenderOptions {
+        params: Some(attractor.params()),
+        code: None,
+        iterations: 40_000_000,
+        colours: colours.clone(),
+        camera: camera.clone(),
+        // Share each position between the nearest pixels, so thin lines print smoothly
+        sampling: Sampling {
+            splat: Splat::Bilinear,
+            ..Sampling::default()
+        },
+        print: Some(layout),
+        from_file: None,
+        save_trajectory: None,
+        output: dir.join(filename).to_string_lossy().to_string(),
+        ..opts.clone()
+    };
+    // Split the positions between orbits on every core, unless they need shading by depth
+    if camera.depth_shading == 0.0 {
+        opts.orbits.count = EXPORT_ORBITS;
+    }
+    let saved = std::fs::create_dir_all(&dir)
+        .map_err(|e| format!("Couldn't create {}: {}", dir.display(), e))
+        .and_then(|_| opts.render());
+    if let Err(e) = saved {
+        println!("failed: {}", e);
+    }
+}
+
+/// Zoom the camera's viewport in or out around the mouse when the mouse wheel is scrolled, and
+/// move it when the mouse is dragged with the left button held down. `drag` is where the mouse
+/// was last frame while dragging. The viewport is only used while the camera hasn't been rotated,
+/// so nothing happens otherwise.
+fn update_viewport(
+    window: &Window,
+    camera: &mut Camera,
+    clifford: &CliffordAttractor,
+    drag: &mut Option<(f32, f32)>,
+) {
+    let Some(mouse) = window.get_mouse_pos(MouseMode::Discard) else {
+        *drag = None;
+        return;
+    };
+    if !camera.is_default() {
+        return;
+    }
+    // The same mapping from positions to pixels as the main window uses
+    let screen = Histogram::without_counts(
+        WIDTH,
+        HEIGHT,
+        camera.bounds_of(clifford),
+        0.05,
+        Splat::Nearest,
+    );
+    if let Some((_dx, dy)) = window.get_scroll_wheel() {
+        if dy != 0.0 {
+            let centre = screen.position_of(mouse.0 as f64, mouse.1 as f64);
+            let factor = ZOOM_PER_SCROLL.powf(dy as f64);
+            camera.viewport = Some(screen.bounds.zoomed(centre, factor));
+        }
+    }
+    if window.get_mouse_down(MouseButton::Left) {
+        if let Some(last) = *drag {
+            if last != mouse {
+                // Move the viewport the opposite way, so that the attractor follows the mouse
+                let from = screen.position_of(last.0 as f64, last.1 as f64);
+                let to = screen.position_of(mouse.0 as f64, mouse.1 as f64);
+                camera.viewport = Some(screen.bounds.moved(from[0] - to[0], from[1] - to[1]));
+            }
+        }
+        *drag = Some(mouse);
+    } else {
+        *drag = None;
     }
 }
 
@@ -669,134 +1163,42 @@ fn from_range_to_domain(
         + lower_to;
 }
 
-// fn update_diagnostics(diag_buf: &mut Vec<u32>, clifford: &CliffordAttractor, avg_density: f64) {
-//     for param in clifford.param_history.iter() {
-//         let a_in_bounds = -5.0 <= param[0] && param[0] < 5.0;
-//         let b_in_bounds = -5.0 <= param[1] && param[1] < 5.0;
-//         let c_in_bounds = -5.0 <= param[2] && param[2] < 5.0;
-//         let d_in_bounds = -5.0 <= param[3] && param[3] < 5.0;
-//         if  a_in_bounds && b_in_bounds && c_in_bounds && d_in_bounds {
-//             let a = (param[0] + 5.0) / 10.0 * 100.0; // horizontal axis
-//             let b = (param[1] + 5.0) / 10.0 * 100.0; // vertical axis
-//             let c = ((param[2] + 5.0) / 10.0 * 200.0 + 50.0) as u8; // Red colour axis
-//             let d = ((param[3] + 5.0) / 10.0 * 200.0 + 50.0) as u8; // Green color axis
-//             let pos = b as usize * DIAG_WIDTH + a as usize;
-//             diag_buf[pos] = argb_to_u32(0, c, d, 255);
-//         }
-//     }
-//     let x = DIAG_WIDTH / 3 + (clifford.param_history.len() % ((DIAG_WIDTH * 2) / 3));
-//     for y in 0..DIAG_HEIGHT {
-//         let pos = y * DIAG_WIDTH + x as usize;
-//         diag_buf[pos] = argb_to_u32(0, 0, 0, 0);
-//     }
-//     // Raise avg_density to the power of 0.3 because there are _loads_ of small values
-//     // (1e-3 < value < 1e-1) which are still meaningful but get lost
-//     let y = (avg_density.powf(0.3) * DIAG_HEIGHT as f64 ) as usize;
-//     let pos = y * DIAG_WIDTH + x as usize;
-//     diag_buf[pos] = argb_to_u32(0, 255, 255, 255);
-// }
-
-fn u32_to_argb(packed: u32) -> (u8, u8, u8, u8) {
-    let a = ((0xFF_00_00_00 & packed) >> 24) as u8;
-    let r = ((0x00_FF_00_00 & packed) >> 16) as u8;
-    let g = ((0x00_00_FF_00 & packed) >> 8) as u8;
-    let b = (0x00_00_00_FF & packed) as u8;
-    return (a, r, g, b);
+/// Draw one column of the scrolling diagnostics plot. The Lyapunov exponent is drawn in green
+/// when it's positive (chaotic) and red when it's negative, with the grey line in the middle
+/// marking zero. The average density is drawn in white, from the bottom of the window.
+fn update_diagnostics(diag_buf: &mut [u32], frame: usize, lyapunov: Option<f64>, avg_density: f64) {
+    let x = frame % DIAG_WIDTH;
+    let mid = DIAG_HEIGHT / 2;
+    for y in 0..DIAG_HEIGHT {
+        diag_buf[y * DIAG_WIDTH + x] = argb_to_u32(0, 0, 0, 0);
+        // Draw a cursor just ahead of the newest column so it's clear where the plot is up to
+        diag_buf[y * DIAG_WIDTH + (x + 1) % DIAG_WIDTH] = argb_to_u32(0, 80, 80, 80);
+    }
+    diag_buf[mid * DIAG_WIDTH + x] = argb_to_u32(0, 60, 60, 60);
+    if let Some(exp) = lyapunov {
+        // Exponents are almost always within [-1, 1], so clamp anything bigger to the edges
+        let y = (mid as f64 - exp.clamp(-1.0, 1.0) * (mid - 1) as f64) as usize;
+        diag_buf[y * DIAG_WIDTH + x] = if exp > 0.0 {
+            argb_to_u32(0, 0, 255, 0)
+        } else {
+            argb_to_u32(0, 255, 0, 0)
+        };
+    }
+    // Raise avg_density to the power of 0.3 because there are _loads_ of small values
+    // (1e-3 < value < 1e-1) which are still meaningful but get lost
+    let y = DIAG_HEIGHT - 1 - (avg_density.powf(0.3) * (DIAG_HEIGHT - 1) as f64) as usize;
+    diag_buf[y * DIAG_WIDTH + x] = argb_to_u32(0, 255, 255, 255);
 }
 
 fn get_specials() -> Option<Vec<Vec<f64>>> {
     let filename = "cache/clifford/special.txt";
 
     if Path::new(filename).exists() {
-        let file_read = File::open(filename).expect("file not found!");
-        let reader = BufReader::new(file_read);
-        let specials = reader
-            .lines()
-            .map(|l| {
-                l.unwrap()
-                    .clone()
-                    .split(",")
-                    .map(|s| {
-                        *(&s[2..]
-                            .to_owned()
-                            .parse::<f64>()
-                            .expect("Couldn't parse specials.txt line"))
-                    })
-                    .collect()
-            })
-            .collect();
-        return Some(specials);
+        return Some(batch::read_specials(filename).expect("Couldn't parse specials.txt"));
     }
     return None;
 }
 
-fn argb_to_u32(a: u8, r: u8, g: u8, b: u8) -> u32 {
-    let (a, r, g, b) = (a as u32, r as u32, g as u32, b as u32);
-    (a << 24) | (r << 16) | (g << 8) | b
-}
-
-/// Convert a Hue Saturation Light Alpha colour to a bit-packed u32. Alpha is currently ignored.
-fn hsla_to_u32(h: f64, s: f64, l: f64, _a: f64) -> u32 {
-    // Converted to rust from JS taken from:
-    // https://stackoverflow.com/a/9493060
-    let r: u8;
-    let g: u8;
-    let b: u8;
-    let a: u8 = 1;
-
-    // If saturation is zero, then the color is just grey => all red,
-    // green, blue components are equal
-    if s == 0.0 {
-        r = (l * 255.0) as u8;
-        g = (l * 255.0) as u8;
-        b = (l * 255.0) as u8;
-    } else {
-        fn hue_to_rgb_floats(p: f64, q: f64, mut t: f64) -> f64 {
-            // ensure 0.0 <= t <= 1.0
-            if t < 0.0 {
-                t += 1.0
-            };
-            if t > 1.0 {
-                t -= 1.0
-            };
-            // I've got no clue how this works
-            if t < 1.0 / 6.0 {
-                return p + (q - p) * 6.0 * t;
-            };
-            if t < 1.0 / 2.0 {
-                return q;
-            };
-            if t < 2.0 / 3.0 {
-                return p + (q - p) * (2.0 / 3.0 - t) * 6.0;
-            };
-            return p;
-        }
-
-        // I've got no clue how this works
-        let q = if l < 0.5 {
-            l * (1.0 + s)
-        } else {
-            l + s - l * s
-        };
-        // I've got no clue how this works
-        let p = 2.0 * l - q;
-        r = (255.0 * hue_to_rgb_floats(p, q, h + 1.0 / 3.0)) as u8;
-        g = (255.0 * hue_to_rgb_floats(p, q, h)) as u8;
-        b = (255.0 * hue_to_rgb_floats(p, q, h - 1.0 / 3.0)) as u8;
-    }
-    return argb_to_u32(a, r, g, b);
-}
-
-// /// Convert CIE Light Chroma Hue to a bit-packed u32 value. https://css.land/lch/
-// /// l, c, and h are all within [0, 1]
-// /// Light -> 0.0 is black, 0.5 is full color, 1.0 is  white
-// /// Chroma -> 0.0 is grey, 1.0 is full colourfulness
-// /// Hue -> 0.0 to 1.0 is: pink, red, orange, yellow, green, light blue, dark blue, purple, pink
-// fn lch_to_u32(l: f64, c: f64, h: f64) -> u32 {
-//     let lch = Lch::new(l * 100.0, c * 132.0, h * 360.0);
-//     let rgb = Srgb::from_color(lch);
-//     return argb_to_u32(0, (rgb.red * 255.0) as u8, (rgb.green * 255.0) as u8, (rgb.blue * 255.0) as u8);
-// }
 // TODO plot the keyframes as lines tracing the a,b,c,d parameters along the
 // bottom of the screen
 
@@ -811,10 +1213,12 @@ struct Command {
             &mut CliffordAttractor,
             &mut Vec<u32>,
             &Vec<Key>,
-            &mut LchParams,
+            &mut ColourScheme,
             &mut Option<Vec<Vec<f64>>>,
             &mut f64,
             &mut f64,
+            &mut Camera,
+            &mut bool,
         ) -> (),
     >,
     /// A one-line description of what `action` does.
@@ -822,36 +1226,3 @@ struct Command {
     /// `action` is only called if `key` is pressed and `enabled` is true.
     enabled: bool,
 }
-
-/// Contains the constants that get multiplied by the value at each pixel in order to convert that
-/// scalar value to a color in LCH space. The conversion is done as:
-/// ```
-/// light_component  = val * light_slope  + light_intercept
-/// chroma_component = val * chroma_slope + chroma_intercept
-/// hue_component    = val * hue_slope    + hue_intercept
-/// ```
-/// For example
-struct LchParams {
-    light_intercept: f64,
-    /// Light -> 0.0 is black, 0.5 is full color, 1.0 is  white
-    light_slope: f64,
-    chroma_intercept: f64,
-    /// Chroma -> 0.0 is grey, 1.0 is full colourfulness
-    chroma_slope: f64,
-    hue_intercept: f64,
-    /// Hue -> 0.0 to 1.0 is: pink, red, orange, yellow, green, light blue, dark blue, purple, pink
-    hue_slope: f64,
-}
-
-impl Display for LchParams {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "LchParams:\n  light = val * {light_slope:+.4} + {light_intercept:+.4}\n  chroma = val * {chroma_slope:+.4} + {chroma_intercept:+.4}\n  hue = val * {hue_slope:+.4} + {hue_intercept:+.4}",
-               light_slope=self.light_slope,
-               light_intercept=self.light_intercept,
-               chroma_slope=self.chroma_slope,
-               chroma_intercept=self.chroma_intercept,
-               hue_slope=self.hue_slope,
-               hue_intercept=self.hue_intercept,
-        )
-    }
-}
@@ -0,0 +1,220 @@
+//! Numerical integrators for ordinary differential equations (ODEs).
+//!
+//! Maps like the Clifford attractor jump straight from one position to the next, but flows like
+//! the Lorenz attractor are defined by their derivative:
+//! ```
+//! d(pos)/dt = f(pos)
+//! ```
+//! and have to be integrated over some small time step `dt` to find the next position. Two
+//! integrators are available:
+//! - [RK4](https://en.wikipedia.org/wiki/Runge%E2%80%93Kutta_methods), the classic fixed-step
+//!   fourth order Runge-Kutta method, which is fast and works well when `dt` is small enough.
+//! - [RK45](https://en.wikipedia.org/wiki/Dormand%E2%80%93Prince_method), the adaptive
+//!   Dormand-Prince method, which takes as many sub-steps as needed to keep the error of each
+//!   step below a tolerance. This is slower, but copes with stiff flows and larger values of `dt`.
+
+/// Which method to use when integrating a flow over one time step.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Integrator {
+    /// Take one fixed fourth order Runge-Kutta step of size `dt`.
+    Rk4,
+    /// Take as many adaptive Dormand-Prince sub-steps as required to cover `dt`, keeping the
+    /// estimated error of each sub-step below `tolerance` (relative to the size of the position).
+    Rk45 { tolerance: f64 },
+}
+
+impl Integrator {
+    /// Integrate `f` from `pos` over a time of `dt`, returning the new position.
+    ///
+    /// `substep` is the size of the adaptive sub-step to try first. It gets updated with a good
+    /// size for the next call, so keep it around between calls. It is ignored by `Rk4`.
+    pub fn advance<F, const N: usize>(
+        &self,
+        f: F,
+        pos: &[f64; N],
+        dt: f64,
+        substep: &mut f64,
+    ) -> [f64; N]
+    where
+        F: Fn(&[f64; N]) -> [f64; N],
+    {
+        match self {
+            Integrator::Rk4 => rk4(&f, pos, dt),
+            Integrator::Rk45 { tolerance } => rk45(&f, pos, dt, *tolerance, substep),
+        }
+    }
+}
+
+/// Return `y + sum(h * weights[i] * ks[i])`.
+fn add_scaled<const N: usize>(y: &[f64; N], h: f64, weights: &[f64], ks: &[[f64; N]]) -> [f64; N] {
+    let mut out = *y;
+    for (w, k) in weights.iter().zip(ks.iter()) {
+        for i in 0..N {
+            out[i] += h * w * k[i];
+        }
+    }
+    out
+}
+
+/// Take one classic fourth order Runge-Kutta step of size `h`.
+pub fn rk4<F, const N: usize>(f: &F, y: &[f64; N], h: f64) -> [f64; N]
+where
+    F: Fn(&[f64; N]) -> [f64; N],
+{
+    let k1 = f(y);
+    let k2 = f(&add_scaled(y, h, &[0.5], &[k1]));
+    let k3 = f(&add_scaled(y, h, &[0.5], &[k2]));
+    let k4 = f(&add_scaled(y, h, &[1.0], &[k3]));
+    add_scaled(
+        y,
+        h,
+        &[1.0 / 6.0, 1.0 / 3.0, 1.0 / 3.0, 1.0 / 6.0],
+        &[k1, k2, k3, k4],
+    )
+}
+
+/// Take one Dormand-Prince step of size `h`, returning the fifth order estimate of the new
+/// position and the difference between it and the fourth order estimate (which is used as an
+/// estimate of the error).
+pub fn dormand_prince<F, const N: usize>(f: &F, y: &[f64; N], h: f64) -> ([f64; N], [f64; N])
+where
+    F: Fn(&[f64; N]) -> [f64; N],
+{
+    // The Butcher tableau, see https://en.wikipedia.org/wiki/Dormand%E2%80%93Prince_method
+    const A: [&[f64]; 6] = [
+        &[1.0 / 5.0],
+        &[3.0 / 40.0, 9.0 / 40.0],
+        &[44.0 / 45.0, -56.0 / 15.0, 32.0 / 9.0],
+        &[
+            19372.0 / 6561.0,
+            -25360.0 / 2187.0,
+            64448.0 / 6561.0,
+            -212.0 / 729.0,
+        ],
+        &[
+            9017.0 / 3168.0,
+            -355.0 / 33.0,
+            46732.0 / 5247.0,
+            49.0 / 176.0,
+            -5103.0 / 18656.0,
+        ],
+        &[
+            35.0 / 384.0,
+            0.0,
+            500.0 / 1113.0,
+            125.0 / 192.0,
+            -2187.0 / 6784.0,
+            11.0 / 84.0,
+        ],
+    ];
+    const B4: [f64; 7] = [
+        5179.0 / 57600.0,
+        0.0,
+        7571.0 / 16695.0,
+        393.0 / 640.0,
+        -92097.0 / 339200.0,
+        187.0 / 2100.0,
+        1.0 / 40.0,
+    ];
+
+    let mut ks: Vec<[f64; N]> = vec![f(y)];
+    for row in A.iter() {
+        ks.push(f(&add_scaled(y, h, row, &ks)));
+    }
+    // The last row of A are the fifth order weights, so the fifth order estimate has already been
+    // calculated as part of finding the last k
+    let fifth = add_scaled(y, h, A[5], &ks);
+    let fourth = add_scaled(y, h, &B4, &ks);
+    let mut error = [0.0; N];
+    for i in 0..N {
+        error[i] = fifth[i] - fourth[i];
+    }
+    (fifth, error)
+}
+
+/// Integrate `f` from `y` over a time of exactly `dt` with adaptive Dormand-Prince sub-steps,
+/// starting with a sub-step of size `substep` and updating it with a good size for next time.
+pub fn rk45<F, const N: usize>(
+    f: &F,
+    y: &[f64; N],
+    dt: f64,
+    tolerance: f64,
+    substep: &mut f64,
+) -> [f64; N]
+where
+    F: Fn(&[f64; N]) -> [f64; N],
+{
+    // Never let the sub-step get so small that the integration grinds to a halt
+    let min_substep = dt.abs() * 1e-9;
+    let mut y = *y;
+    let mut t = 0.0;
+    if !(*substep > 0.0 && substep.is_finite()) {
+        *substep = dt;
+    }
+    while t < dt {
+        let h = substep.min(dt - t);
+        let (next, error) = dormand_prince(f, &y, h);
+        // The error is measured relative to the size of the position, but never relative to
+        // anything smaller than 1
+        let err = error
+            .iter()
+            .zip(y.iter())
+            .map(|(e, y)| e.abs() / (tolerance * y.abs().max(1.0)))
+            .fold(0.0, f64::max);
+        if !err.is_finite() {
+            // The flow has escaped to infinity, so there's no point trying to be accurate
+            return next;
+        }
+        if err <= 1.0 || h <= min_substep {
+            t += h;
+            y = next;
+        }
+        // Grow or shrink the sub-step, but not by too much at a time
+        let factor = if err == 0.0 {
+            5.0
+        } else {
+            (0.9 * err.powf(-0.2)).clamp(0.2, 5.0)
+        };
+        // Only remember sub-steps which weren't cut short by the end of `dt`
+        if h == *substep || err > 1.0 {
+            *substep = (h * factor).max(min_substep);
+        }
+    }
+    y
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// dy/dt = y has the solution y = e^t
+    fn exponential(y: &[f64; 1]) -> [f64; 1] {
+        [y[0]]
+    }
+
+    #[test]
+    fn integrators_solve_exponential_growth() {
+        let mut substep = 0.0;
+        for integrator in [Integrator::Rk4, Integrator::Rk45 { tolerance: 1e-10 }] {
+            let mut y = [1.0];
+            for _ in 0..100 {
+                y = integrator.advance(exponential, &y, 0.01, &mut substep);
+            }
+            assert!(
+                (y[0] - 1f64.exp()).abs() < 1e-8,
+                "{:?} gave {}",
+                integrator,
+                y[0]
+            );
+        }
+    }
+
+    #[test]
+    fn rk45_takes_big_steps_accurately() {
+        let mut substep = 0.0;
+        let y = rk45(&exponential, &[1.0], 2.0, 1e-10, &mut substep);
+        assert!((y[0] - 2f64.exp()).abs() < 1e-7);
+        // A single RK4 step of the same size is nowhere near as accurate
+        assert!((rk4(&exponential, &[1.0], 2.0)[0] - 2f64.exp()).abs() > 1e-2);
+    }
+}
@@ -0,0 +1,305 @@
+//! Gradient palettes, for colouring attractors with something other than `LchParams`.
+//!
+//! A palette is a list of colour stops, each at a position in [0, 1]. Densities are mapped onto
+//! the palette by interpolating between the stops on either side, so a palette with a dark first
+//! stop is best on a dark background. A few perceptually uniform palettes from
+//! [matplotlib](https://bids.github.io/colormap/) are built in, and more can be loaded from a
+//! palette file written in a small subset of TOML:
+//! ```
+//! # Each palette has a [name] followed by it's colours, from the lowest density to the highest
+//! [sunset]
+//! colours = ["#000000", "#7b1fa2", "#ff7043", "#fff59d"]
+//! # The positions of each colour are optional, and are evenly spaced if left out
+//! positions = [0.0, 0.3, 0.7, 1.0]
+//! ```
+//! Every array has to be on a single line.
+use std::fmt::Display;
+use std::fs;
+
+use crate::attractors::AttractorError;
+use crate::colour::argb_to_u32;
+
+/// A named gradient made of colour stops.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    pub name: String,
+    /// The position in [0, 1] and the sRGB colour of each stop, sorted by position.
+    pub stops: Vec<(f64, [u8; 3])>,
+}
+
+/// The palettes which are always available, as (name, evenly spaced hex colours).
+const BUILT_IN: &[(&str, &[&str])] = &[
+    (
+        "viridis",
+        &[
+            "#440154", "#472d7b", "#3b528b", "#2c728e", "#21918c", "#28ae80", "#5ec962", "#addc30",
+            "#fde725",
+        ],
+    ),
+    (
+        "magma",
+        &[
+            "#000004", "#1c1044", "#4f127b", "#812581", "#b5367a", "#e55064", "#fb8861", "#fec287",
+            "#fcfdbf",
+        ],
+    ),
+    (
+        "inferno",
+        &[
+            "#000004", "#1f0c48", "#550f6d", "#88226a", "#ba3655", "#e35933", "#f98e09", "#f9cb35",
+            "#fcffa4",
+        ],
+    ),
+    (
+        "plasma",
+        &[
+            "#0d0887", "#4c02a1", "#7e03a8", "#a92395", "#cc4778", "#e56b5d", "#f89540", "#fdc527",
+            "#f0f921",
+        ],
+    ),
+    (
+        "fire",
+        &[
+            "#000000", "#7f0000", "#ff4500", "#ffa500", "#ffff66", "#ffffff",
+        ],
+    ),
+    ("greys", &["#000000", "#ffffff"]),
+];
+
+/// Parse a colour like `#ff8800` (the `#` is optional).
+pub fn parse_hex(hex: &str) -> Option<[u8; 3]> {
+    let hex = hex.trim().trim_start_matches('#');
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+impl Palette {
+    /// Create a palette from colours at `positions`. The colours are evenly spaced if
+    /// `positions` is `None`. Returns an error if there are fewer than two colours, or if the
+    /// positions aren't in [0, 1] or don't match up with the colours.
+    pub fn new(
+        name: &str,
+        colours: Vec<[u8; 3]>,
+        positions: Option<Vec<f64>>,
+    ) -> Result<Self, String> {
+        if colours.len() < 2 {
+            return Err(format!("The palette `{}` needs at least 2 colours", name));
+        }
+        let positions = match positions {
+            Some(positions) if positions.len() != colours.len() => {
+                return Err(format!(
+                    "The palette `{}` has {} colours but {} positions",
+                    name,
+                    colours.len(),
+                    positions.len()
+                ))
+            }
+            Some(positions) => positions,
+            None => (0..colours.len())
+                .map(|i| i as f64 / (colours.len() - 1) as f64)
+                .collect(),
+        };
+        if positions.iter().any(|p| !(0.0..=1.0).contains(p)) {
+            return Err(format!(
+                "The positions of the palette `{}` must be in [0, 1]",
+                name
+            ));
+        }
+        let mut stops: Vec<(f64, [u8; 3])> = positions.into_iter().zip(colours).collect();
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Ok(Palette {
+            name: name.to_string(),
+            stops,
+        })
+    }
+
+    /// Every built in palette.
+    pub fn built_in() -> Vec<Palette> {
+        BUILT_IN
+            .iter()
+            .map(|(name, hexes)| {
+                let colours = hexes
+                    .iter()
+                    .map(|h| parse_hex(h).expect("Built in palettes are valid"))
+                    .collect();
+                Palette::new(name, colours, None).expect("Built in palettes are valid")
+            })
+            .collect()
+    }
+
+    /// Find a built in palette, or a palette in `palettes`, by it's name.
+    pub fn find(name: &str, palettes: &[Palette]) -> Option<Palette> {
+        palettes
+            .iter()
+            .cloned()
+            .chain(Palette::built_in())
+            .find(|p| p.name == name)
+    }
+
+    /// The colour at `t` in [0, 1], interpolated between the stops on either side of `t`.
+    pub fn colour_at(&self, t: f64) -> [u8; 3] {
+        let t = t.clamp(0.0, 1.0);
+        let upper = self
+            .stops
+            .iter()
+            .position(|(pos, _)| *pos >= t)
+            .unwrap_or(self.stops.len() - 1);
+        if upper == 0 {
+            return self.stops[0].1;
+        }
+        let (p0, c0) = self.stops[upper - 1];
+        let (p1, c1) = self.stops[upper];
+        let frac = if p1 > p0 { (t - p0) / (p1 - p0) } else { 1.0 };
+        let mut colour = [0; 3];
+        for i in 0..3 {
+            colour[i] = (c0[i] as f64 + (c1[i] as f64 - c0[i] as f64) * frac).round() as u8;
+        }
+        colour
+    }
+
+    /// The colour at `t` in [0, 1] as a bit-packed u32.
+    pub fn to_u32(&self, t: f64) -> u32 {
+        let [r, g, b] = self.colour_at(t);
+        argb_to_u32(0, r, g, b)
+    }
+}
+
+impl Display for Palette {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let stops: Vec<String> = self
+            .stops
+            .iter()
+            .map(|(pos, [r, g, b])| format!("{:.2}:#{:02x}{:02x}{:02x}", pos, r, g, b))
+            .collect();
+        write!(f, "Palette {} ({})", self.name, stops.join(" "))
+    }
+}
+
+/// Parse a one-line TOML array like `["#000000", "#ffffff"]` or `[0.0, 1.0]` into it's items,
+/// with any quotes removed.
+fn parse_array(value: &str) -> Option<Vec<String>> {
+    let inner = value.trim().strip_prefix('[')?.strip_suffix(']')?;
+    Some(
+        inner
+            .split(',')
+            .map(|item| item.trim().trim_matches('"').to_string())
+            .filter(|item| !item.is_empty())
+            .collect(),
+    )
+}
+
+/// Remove a `#` comment from the end of `line`, ignoring any `#`s inside quotes (since colours
+/// start with a `#`).
+fn strip_comment(line: &str) -> &str {
+    let mut in_quotes = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '#' if !in_quotes => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Parse the palettes in the text of a palette file. See the module docs for the format.
+pub fn parse_palettes(text: &str) -> Result<Vec<Palette>, AttractorError> {
+    // The name, colours, and positions of each palette, and the line it started on
+    type Parsed = (String, Vec<[u8; 3]>, Option<Vec<f64>>, usize);
+    let mut parsed: Vec<Parsed> = vec![];
+    let invalid = |line: usize, reason: String| AttractorError::InvalidFile { line, reason };
+    for (i, line) in text.lines().enumerate() {
+        let line_num = i + 1;
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            parsed.push((name.trim().to_string(), vec![], None, line_num));
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| invalid(line_num, "expected `key = value`".to_string()))?;
+        let current = parsed
+            .last_mut()
+            .ok_or_else(|| invalid(line_num, "expected a [name] before any colours".to_string()))?;
+        let items = parse_array(value)
+            .ok_or_else(|| invalid(line_num, "expected an array like [...]".to_string()))?;
+        match key.trim() {
+            "colours" | "colors" => {
+                current.1 = items
+                    .iter()
+                    .map(|h| {
+                        parse_hex(h)
+                            .ok_or_else(|| invalid(line_num, format!("`{}` isn't a colour", h)))
+                    })
+                    .collect::<Result<_, _>>()?;
+            }
+            "positions" => {
+                current.2 = Some(
+                    items
+                        .iter()
+                        .map(|p| {
+                            p.parse::<f64>()
+                                .map_err(|_| invalid(line_num, format!("`{}` isn't a position", p)))
+                        })
+                        .collect::<Result<_, _>>()?,
+                );
+            }
+            other => return Err(invalid(line_num, format!("unknown key `{}`", other))),
+        }
+    }
+    parsed
+        .into_iter()
+        .map(|(name, colours, positions, line)| {
+            Palette::new(&name, colours, positions).map_err(|reason| invalid(line, reason))
+        })
+        .collect()
+}
+
+/// Load every palette from the palette file named `filename`.
+pub fn load_palettes(filename: &str) -> Result<Vec<Palette>, AttractorError> {
+    parse_palettes(&fs::read_to_string(filename)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn palettes_interpolate_between_stops() {
+        let palette = Palette::new(
+            "test",
+            vec![[0, 0, 0], [255, 0, 0], [255, 255, 255]],
+            Some(vec![0.0, 0.25, 1.0]),
+        )
+        .unwrap();
+        assert_eq!(palette.colour_at(0.0), [0, 0, 0]);
+        assert_eq!(palette.colour_at(0.125), [128, 0, 0]);
+        assert_eq!(palette.colour_at(0.25), [255, 0, 0]);
+        assert_eq!(palette.colour_at(1.0), [255, 255, 255]);
+        assert_eq!(palette.colour_at(2.0), [255, 255, 255]);
+        assert!(Palette::find("viridis", &[]).is_some());
+    }
+
+    #[test]
+    fn parse_palette_files() {
+        let palettes = parse_palettes(
+            "# A comment\n[sunset]\ncolours = [\"#000000\", \"#ff7043\"] # trailing comment\n\n\
+             [ice]\ncolours = [\"#000000\", \"88ccff\", \"#ffffff\"]\npositions = [0.0, 0.8, 1.0]\n",
+        )
+        .unwrap();
+        assert_eq!(palettes.len(), 2);
+        assert_eq!(palettes[0].name, "sunset");
+        assert_eq!(palettes[0].stops[1], (1.0, [0xff, 0x70, 0x43]));
+        assert_eq!(palettes[1].stops[1], (0.8, [0x88, 0xcc, 0xff]));
+
+        assert!(parse_palettes("colours = [\"#000000\"]").is_err());
+        assert!(parse_palettes("[bad]\ncolours = [\"#00000\", \"#ffffff\"]").is_err());
+        assert!(parse_palettes("[short]\ncolours = [\"#000000\"]").is_err());
+    }
+}
@@ -0,0 +1,243 @@
+//! Rendering with many independent orbits on every core at once.
+//!
+//! `Attractor::step` calculates one position after another, so a single orbit can only ever use
+//! one core. Instead, `Orbits` starts lots of orbits from slightly different (jittered) points
+//! near the attractor, throws away the first `transient` positions of each so that they've
+//! settled onto the attractor, and splits the iterations between them. Worker threads take the
+//! next orbit from a queue and bin its positions into their own grid of counts, and the grids
+//! are added together at the end.
+//!
+//! Each orbit's start point comes from a random number generator seeded by mixing `seed` and the
+//! orbit's index together (see `orbit_seed`), and the counts are integers (bilinear splats are
+//! stored in units of `1 / SPLAT_SCALE` of a position), so adding the grids together gives exactly
+//! the same result no matter how many workers there are or which worker ran which orbit. The same
+//! seed always renders the same image.
+//!
+//! Every worker keeps a full size grid of counts, so this uses `4 * workers` bytes per pixel
+//! (times `supersample` squared) instead of the memory needed for every position. Huge images
+//! get fewer workers, so that their grids fit in `GRID_BUDGET`.
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::attractors::{Attractor, Bounds, Histogram};
+use crate::camera::{include_in_box, Camera};
+use crate::sampling::{Sampling, SPLAT_SCALE};
+
+/// The number of positions calculated by the single orbit which finds the bounds, the fit of the
+/// camera, and the point the other orbits start near.
+const REFERENCE_STEPS: usize = 100_000;
+/// The most positions any orbit steps at once, which bounds the memory used by attractors that
+/// can't step one position at a time.
+const CHUNK_SIZE: usize = 1_000_000;
+/// The most memory the workers' grids of counts can use between them, in bytes. There's always
+/// at least one worker, however large its grid is.
+const GRID_BUDGET: usize = 1 << 30;
+
+/// How many orbits to run, and how to start them. See the module docs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Orbits {
+    /// The number of independent orbits. The iterations are split evenly between them.
+    pub count: usize,
+    /// The number of positions thrown away at the start of each orbit.
+    pub transient: usize,
+    /// How far each orbit's start point is moved from the reference orbit, as a fraction of the
+    /// size of the attractor in each direction.
+    pub jitter: f64,
+    pub seed: u64,
+    /// The number of threads running orbits at once.
+    pub workers: usize,
+}
+
+impl Default for Orbits {
+    fn default() -> Self {
+        let workers = thread::available_parallelism().map_or(1, |n| n.get());
+        Orbits {
+            count: 1,
+            transient: 1_000,
+            jitter: 0.01,
+            seed: 0,
+            workers,
+        }
+    }
+}
+
+impl Orbits {
+    /// The bounds which `densities` draws within when `camera` is the default camera.
+    pub fn bounds<A: Attractor>(&self, attractor: &A, camera: &Camera) -> Bounds {
+        camera.bounds_of(&self.reference(attractor))
+    }
+
+    /// A fresh copy of `attractor`, stepped far enough to find its bounds and a point on it.
+    fn reference<A: Attractor>(&self, attractor: &A) -> A {
+        let mut reference = attractor.empty_copy();
+        reference.step(self.transient + REFERENCE_STEPS);
+        reference
+    }
+
+    /// Step `iterations` positions (not counting the transients) split between `count` orbits of
+    /// `attractor`, and return the density of every pixel of a `width` by `height` image seen
+    /// through `camera`, normalised to [0.0, 1.0] like `Camera::get_densities`. The history of
+    /// `attractor` itself isn't used or changed.
+    #[allow(clippy::too_many_arguments)]
+    pub fn densities<A: Attractor + Sync>(
+        &self,
+        attractor: &A,
+        iterations: usize,
+        width: usize,
+        height: usize,
+        perc_border: f64,
+        camera: &Camera,
+        sampling: &Sampling,
+    ) -> Vec<f64> {
+        let factor = sampling.supersample.max(1);
+        let reference = self.reference(attractor);
+        let (bounds, projection) = if camera.is_default() {
+            (camera.bounds_of(&reference), None)
+        } else {
+            let mut fitted = camera.clone();
+            fitted.fit(reference.history());
+            (fitted.screen_bounds(width, height), Some(fitted))
+        };
+        let mapping = Histogram::without_counts(
+            width * factor,
+            height * factor,
+            bounds,
+            perc_border,
+            sampling.splat,
+        );
+        // Start every orbit near the end of the reference orbit, which is on the attractor
+        let mut min = [f64::INFINITY; 3];
+        let mut max = [f64::NEG_INFINITY; 3];
+        include_in_box(&mut min, &mut max, reference.history());
+        let size: Vec<f64> = (0..3).map(|i| (max[i] - min[i]).max(0.0)).collect();
+        let centre = reference.history().last().cloned().unwrap_or_default();
+
+        let count = self.count.max(1);
+        let grid_bytes = mapping.width * mapping.height * std::mem::size_of::<u32>();
+        let workers = self
+            .workers
+            .clamp(1, count)
+            .min((GRID_BUDGET / grid_bytes.max(1)).max(1));
+        let next = AtomicUsize::new(0);
+        let mut totals = vec![0.0; mapping.width * mapping.height];
+        thread::scope(|s| {
+            let handles: Vec<_> = (0..workers)
+                .map(|_| {
+                    s.spawn(|| {
+                        let mut counts = vec![0u32; mapping.width * mapping.height];
+                        loop {
+                            let orbit = next.fetch_add(1, Ordering::Relaxed);
+                            if orbit >= count {
+                                break;
+                            }
+                            // The first few orbits take the remainder, one position each
+                            let steps = iterations / count + (orbit < iterations % count) as usize;
+                            let mut rng = StdRng::seed_from_u64(orbit_seed(self.seed, orbit));
+                            let start = centre
+                                .iter()
+                                .zip(&size)
+                                .map(|(c, s)| c + s * self.jitter * rng.gen_range(-1.0..=1.0))
+                                .collect();
+                            let mut orbit = attractor.empty_copy();
+                            orbit.set_history(vec![start]);
+                            orbit.step_with(self.transient, |_| {});
+                            let mut visited = 0;
+                            while visited < steps {
+                                let chunk = (steps - visited).min(CHUNK_SIZE);
+                                visited += chunk;
+                                orbit.step_with(chunk, |pos| {
+                                    let weights = match &projection {
+                                        Some(camera) => mapping.splat_weights(&camera.project(pos)),
+                                        None => mapping.splat_weights(pos),
+                                    };
+                                    for (i, weight) in weights.into_iter().flatten() {
+                                        if weight > 0.0 {
+                                            let splat = (weight * SPLAT_SCALE).round() as u32;
+                                            counts[i] = counts[i].saturating_add(splat);
+                                        }
+                                    }
+                                });
+                            }
+                        }
+                        counts
+                    })
+                })
+                .collect();
+            // Add each grid to the totals as soon as it's done, so it can be freed
+            for handle in handles {
+                let grid = handle.join().expect("A worker panicked");
+                for (total, count) in totals.iter_mut().zip(grid) {
+                    *total += count as f64;
+                }
+            }
+        });
+
+        let mut histogram = mapping;
+        histogram.counts = totals;
+        histogram.max = histogram.counts.iter().copied().fold(0.0, f64::max);
+        histogram.downsampled(factor, sampling.filter).normalised()
+    }
+}
+
+/// The seed for orbit number `orbit`, scrambled with SplitMix64 so that nearby seeds don't share
+/// orbits (just adding the index would give seed 1's orbit 0 the same start as seed 0's orbit 1).
+fn orbit_seed(seed: u64, orbit: usize) -> u64 {
+    fn mix(mut z: u64) -> u64 {
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+    const GOLDEN_GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+    mix(mix(seed).wrapping_add((orbit as u64 + 1).wrapping_mul(GOLDEN_GAMMA)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attractors::CliffordAttractor;
+    use crate::sampling::Splat;
+
+    fn render(workers: usize, seed: u64) -> Vec<f64> {
+        let clifford = CliffordAttractor::new(vec![1.7, 1.7, 0.6, 1.2]).unwrap();
+        let orbits = Orbits {
+            count: 7,
+            workers,
+            seed,
+            ..Orbits::default()
+        };
+        let sampling = Sampling {
+            splat: Splat::Bilinear,
+            ..Sampling::default()
+        };
+        orbits.densities(
+            &clifford,
+            50_000,
+            40,
+            30,
+            0.05,
+            &Camera::default(),
+            &sampling,
+        )
+    }
+
+    #[test]
+    fn orbits_are_deterministic_for_any_number_of_workers() {
+        let one = render(1, 42);
+        assert!(one.iter().any(|d| *d > 0.0));
+        assert_eq!(one, render(3, 42));
+        assert_eq!(one, render(8, 42));
+        assert_ne!(one, render(3, 43));
+    }
+
+    #[test]
+    fn nearby_seeds_share_no_orbits() {
+        let seeds = |seed| (0..100).map(move |orbit| orbit_seed(seed, orbit));
+        let mut all: Vec<u64> = seeds(42).chain(seeds(43)).chain(seeds(0)).collect();
+        all.sort_unstable();
+        all.dedup();
+        assert_eq!(all.len(), 300);
+    }
+}
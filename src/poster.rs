@@ -0,0 +1,469 @@
+//! Splitting posters which are too big for the printer onto sheets which it can print.
+//!
+//! The poster is cut into a grid of pieces, each as big as a sheet less its margins, which
+//! overlap their neighbours by `overlap_mm`. Every piece is saved as a page of its own, with marks
+//! in the margins for putting the poster back together:
+//! - Crop marks continue the lines to cut along, which run through the middle of each overlap
+//!   (or along the edge of the poster). Once cut, the pieces butt up against each other, and the
+//!   overlap leaves room for cuts which aren't quite straight.
+//! - Registration marks sit halfway along each side of the piece, for lining sheets up.
+//! - A label says which row and column the sheet goes in.
+//!
+//! A manifest listing every sheet and which part of the poster it has is saved next to them.
+//!
+//! The poster is given a few rows at a time and only one row of sheets is kept in memory, so
+//! posters can be streamed from a `TiledRender` or a `PrintLayout` just like a single PNG.
+use std::fs;
+
+use image::{Rgb, RgbImage};
+
+use crate::font::{draw_text, text_size, GLYPH_HEIGHT};
+use crate::print::{save_png, IsoPaper, Orientation, Paper, PrintLayout, Rows};
+
+/// The colour of the paper around each piece, and of the marks.
+const PAPER: Rgb<u8> = Rgb([255, 255, 255]);
+const INK: Rgb<u8> = Rgb([0, 0, 0]);
+/// The thickness of the marks.
+const MARK_MM: f64 = 0.25;
+
+/// How to split a poster onto sheets. See the module docs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Poster {
+    /// The sheets the poster is printed on, which must have the same DPI as the poster. The
+    /// margin is left for the marks (and most printers can't print up to the edge anyway), and
+    /// whichever orientation needs the fewest sheets is used. The bleed and caption are ignored.
+    pub sheet: PrintLayout,
+    /// How far each piece overlaps its neighbours.
+    pub overlap_mm: f64,
+}
+
+impl Default for Poster {
+    fn default() -> Self {
+        Poster {
+            sheet: PrintLayout {
+                paper: Paper::Iso(IsoPaper::A3),
+                margin_mm: 10.0,
+                ..PrintLayout::default()
+            },
+            overlap_mm: 10.0,
+        }
+    }
+}
+
+/// How a poster is split into pieces, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Grid {
+    orientation: Orientation,
+    /// The width and height of each sheet.
+    sheet: (usize, usize),
+    /// The space between the edge of each sheet and its piece.
+    margin: usize,
+    overlap: usize,
+    /// The width and height of the largest piece which fits on a sheet.
+    piece: (usize, usize),
+    /// The distance between the starts of neighbouring pieces.
+    step: (usize, usize),
+    columns: usize,
+    rows: usize,
+}
+
+impl Grid {
+    /// The first column (or row) and the number of columns (or rows) of the poster on the piece
+    /// at `index` along an axis of the poster which is `length` pixels long.
+    fn span(&self, axis: usize, index: usize, length: usize) -> (usize, usize) {
+        let (piece, step) = [(self.piece.0, self.step.0), (self.piece.1, self.step.1)][axis];
+        let start = index * step;
+        (start, piece.min(length - start))
+    }
+
+    /// The first column (or row) and the number of columns (or rows) left after cutting the
+    /// piece at `index` along its crop marks.
+    fn trim(&self, axis: usize, index: usize, length: usize) -> (usize, usize) {
+        let (start, _len) = self.span(axis, index, length);
+        let last = [self.columns, self.rows][axis] - 1;
+        let step = [self.step.0, self.step.1][axis];
+        let from = if index == 0 {
+            0
+        } else {
+            start + self.overlap / 2
+        };
+        let to = if index == last {
+            length
+        } else {
+            start + step + self.overlap / 2
+        };
+        (from, to - from)
+    }
+}
+
+impl Poster {
+    /// How a `width` by `height` pixel poster is split, in whichever orientation needs the fewest
+    /// sheets (preferring portrait).
+    fn grid(&self, width: usize, height: usize) -> Result<Grid, String> {
+        let portrait = self.grid_for(Orientation::Portrait, width, height)?;
+        let landscape = self.grid_for(Orientation::Landscape, width, height)?;
+        if landscape.columns * landscape.rows < portrait.columns * portrait.rows {
+            Ok(landscape)
+        } else {
+            Ok(portrait)
+        }
+    }
+
+    /// How a `width` by `height` pixel poster is split onto sheets turned to `orientation`.
+    fn grid_for(
+        &self,
+        orientation: Orientation,
+        width: usize,
+        height: usize,
+    ) -> Result<Grid, String> {
+        let sheet = PrintLayout {
+            orientation,
+            bleed_mm: 0.0,
+            caption: None,
+            ..self.sheet.clone()
+        };
+        let size = sheet.page_size();
+        let margin = sheet.px(sheet.margin_mm);
+        let overlap = sheet.px(self.overlap_mm);
+        if margin == 0 {
+            return Err("Poster sheets need a margin to put the marks in".to_string());
+        }
+        let piece = (
+            size.0.saturating_sub(2 * margin),
+            size.1.saturating_sub(2 * margin),
+        );
+        if piece.0 <= overlap || piece.1 <= overlap {
+            return Err(format!(
+                "The overlap must be smaller than the {} by {} pixels inside the margins of a sheet",
+                piece.0, piece.1
+            ));
+        }
+        let step = (piece.0 - overlap, piece.1 - overlap);
+        // Enough pieces that the last one reaches the far edge of the poster
+        let count = |length: usize, piece: usize, step: usize| {
+            1 + length.saturating_sub(piece).div_ceil(step)
+        };
+        Ok(Grid {
+            orientation,
+            sheet: size,
+            margin,
+            overlap,
+            piece,
+            step,
+            columns: count(width, piece.0, step.0),
+            rows: count(height, piece.1, step.1),
+        })
+    }
+
+    /// Split a `width` by `height` pixel poster onto sheets, saving each sheet next to `output`
+    /// (`poster.png` is saved as `poster_r1_c1.png`, `poster_r1_c2.png`, and so on) along with
+    /// `poster_manifest.txt`. `rows` is called once, and should give every row of the poster to the
+    /// `Rows` it's called with.
+    pub fn save(
+        &self,
+        width: usize,
+        height: usize,
+        output: &str,
+        rows: impl FnOnce(&mut Rows) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let grid = self.grid(width, height)?;
+        let stem = output.strip_suffix(".png").unwrap_or(output);
+        let row_bytes = 3 * width;
+        let wrong_size = || format!("The poster must be {} by {} pixels", width, height);
+
+        // The rows of the poster from `band_start` onwards, which the current row of sheets needs
+        let mut band: Vec<u8> = vec![];
+        let mut band_start = 0;
+        let mut sheet_row = 0;
+        let mut files = vec![];
+        rows(&mut |bytes: &[u8]| {
+            for row in bytes.chunks(row_bytes) {
+                if row.len() != row_bytes || sheet_row == grid.rows {
+                    return Err(wrong_size());
+                }
+                band.extend_from_slice(row);
+                let (y, h) = grid.span(1, sheet_row, height);
+                if band_start + band.len() / row_bytes < y + h {
+                    continue;
+                }
+                for column in 0..grid.columns {
+                    let file = format!("{}_r{}_c{}.png", stem, sheet_row + 1, column + 1);
+                    let sheet = self.sheet_image(&grid, sheet_row, column, &band, width, height);
+                    let (sheet_width, sheet_height) = grid.sheet;
+                    save_png(
+                        &file,
+                        sheet_width,
+                        sheet_height,
+                        Some(self.sheet.dpi),
+                        |write| write(sheet.as_raw()).map(|_| vec![]),
+                    )?;
+                    files.push((file, sheet_row, column));
+                }
+                sheet_row += 1;
+                // The next row of sheets overlaps this one, so keep the rows they share
+                if sheet_row < grid.rows {
+                    let (next_y, _h) = grid.span(1, sheet_row, height);
+                    band.drain(..(next_y - band_start) * row_bytes);
+                    band_start = next_y;
+                }
+            }
+            Ok(())
+        })?;
+        if sheet_row != grid.rows {
+            return Err(wrong_size());
+        }
+        let manifest = format!("{}_manifest.txt", stem);
+        fs::write(&manifest, self.manifest(&grid, width, height, &files))
+            .map_err(|e| format!("Couldn't write {}: {}", manifest, e))
+    }
+
+    /// The sheet for the piece in `row` and `column`, where `band` has every row of the poster
+    /// from the top of the piece onwards.
+    fn sheet_image(
+        &self,
+        grid: &Grid,
+        row: usize,
+        column: usize,
+        band: &[u8],
+        width: usize,
+        height: usize,
+    ) -> RgbImage {
+        let (sheet_width, sheet_height) = grid.sheet;
+        let m = grid.margin;
+        let (x, w) = grid.span(0, column, width);
+        let (y, h) = grid.span(1, row, height);
+        let mut sheet = RgbImage::from_pixel(sheet_width as u32, sheet_height as u32, PAPER);
+        let pixels: &mut [u8] = &mut sheet;
+        for py in 0..h {
+            let src = 3 * (py * width + x);
+            let dst = 3 * ((m + py) * sheet_width + m);
+            pixels[dst..dst + 3 * w].copy_from_slice(&band[src..src + 3 * w]);
+        }
+
+        let thickness = self.sheet.px(MARK_MM).max(1);
+        // Keep the marks clear of the piece, so that none of them are left after cutting
+        let gap = m / 4;
+        let (trim_x, trim_w) = grid.trim(0, column, width);
+        let (trim_y, trim_h) = grid.trim(1, row, height);
+        let (left, right) = (m + trim_x - x, m + trim_x - x + trim_w);
+        let (top, bottom) = (m + trim_y - y, m + trim_y - y + trim_h);
+        for line_x in [left, right] {
+            let line_x = line_x.saturating_sub(thickness / 2);
+            fill(&mut sheet, line_x, 0, thickness, m - gap);
+            fill(&mut sheet, line_x, m + h + gap, thickness, m - gap);
+        }
+        for line_y in [top, bottom] {
+            let line_y = line_y.saturating_sub(thickness / 2);
+            fill(&mut sheet, 0, line_y, m - gap, thickness);
+            fill(&mut sheet, m + w + gap, line_y, m - gap, thickness);
+        }
+        let middle = (m + w / 2, m + h / 2);
+        for centre in [
+            (middle.0, m / 2),
+            (middle.0, m + h + m / 2),
+            (m / 2, middle.1),
+            (m + w + m / 2, middle.1),
+        ] {
+            registration_mark(&mut sheet, centre, m / 4, thickness);
+        }
+
+        let label = format!(
+            "row {} of {}, column {} of {}",
+            row + 1,
+            grid.rows,
+            column + 1,
+            grid.columns
+        );
+        // Fit the label between the crop mark and the registration mark
+        let label_x = left + m / 2;
+        let room = (middle.0 - m / 2).saturating_sub(label_x);
+        let (label_width, _height) = text_size(&label, 1);
+        let scale = (m / 3 / GLYPH_HEIGHT).min(room / label_width).max(1);
+        let (_width, label_height) = text_size(&label, scale);
+        let label_y = m.saturating_sub(label_height) / 2;
+        draw_text(&mut sheet, label_x, label_y, scale, &label, INK);
+        sheet
+    }
+
+    /// A description of how to put the poster together, and a line for every sheet in `files`
+    /// saying which part of the poster it has.
+    fn manifest(
+        &self,
+        grid: &Grid,
+        width: usize,
+        height: usize,
+        files: &[(String, usize, usize)],
+    ) -> String {
+        let mut manifest = format!(
+            "# A {} by {} pixel poster at {} DPI, on {} {} {} sheets ({} across and {} down)\n\
+             # Neighbouring sheets overlap by {} mm. Cut each sheet along its crop marks, which\n\
+             # run through the middle of each overlap, then butt the sheets up against each other.\n\
+             # Each line has the file, its row and column, the part of the poster on the sheet\n\
+             # and the part left after cutting, in pixels of the poster:\n\
+             # file,row,column,x,y,width,height,trim_x,trim_y,trim_width,trim_height\n",
+            width,
+            height,
+            self.sheet.dpi,
+            files.len(),
+            self.sheet.paper,
+            grid.orientation,
+            grid.columns,
+            grid.rows,
+            self.overlap_mm,
+        );
+        for (file, row, column) in files {
+            let (x, w) = grid.span(0, *column, width);
+            let (y, h) = grid.span(1, *row, height);
+            let (trim_x, trim_w) = grid.trim(0, *column, width);
+            let (trim_y, trim_h) = grid.trim(1, *row, height);
+            let name = file.rsplit('/').next().unwrap_or(file);
+            manifest.push_str(&format!(
+                "{},{},{},{},{},{},{},{},{},{},{}\n",
+                name,
+                row + 1,
+                column + 1,
+                x,
+                y,
+                w,
+                h,
+                trim_x,
+                trim_y,
+                trim_w,
+                trim_h
+            ));
+        }
+        manifest
+    }
+}
+
+/// Fill the `width` by `height` rectangle with its top left corner at (`x`, `y`) with ink, cutting
+/// off anything outside of `image`.
+fn fill(image: &mut RgbImage, x: usize, y: usize, width: usize, height: usize) {
+    let x_end = (x + width).min(image.width() as usize);
+    let y_end = (y + height).min(image.height() as usize);
+    for py in y..y_end {
+        for px in x..x_end {
+            image.put_pixel(px as u32, py as u32, INK);
+        }
+    }
+}
+
+/// Draw a circle of `radius` pixels around `centre`, with cross hairs through it.
+fn registration_mark(
+    image: &mut RgbImage,
+    centre: (usize, usize),
+    radius: usize,
+    thickness: usize,
+) {
+    let (cx, cy) = centre;
+    let reach = radius + radius / 2;
+    fill(
+        image,
+        cx.saturating_sub(reach),
+        cy.saturating_sub(thickness / 2),
+        2 * reach,
+        thickness,
+    );
+    fill(
+        image,
+        cx.saturating_sub(thickness / 2),
+        cy.saturating_sub(reach),
+        thickness,
+        2 * reach,
+    );
+    let (inner, outer) = (
+        radius as f64 - thickness as f64 / 2.0,
+        radius as f64 + thickness as f64 / 2.0,
+    );
+    for py in cy.saturating_sub(reach)..(cy + reach).min(image.height() as usize) {
+        for px in cx.saturating_sub(reach)..(cx + reach).min(image.width() as usize) {
+            let dist = (px as f64 - cx as f64).hypot(py as f64 - cy as f64);
+            if inner <= dist && dist < outer {
+                image.put_pixel(px as u32, py as u32, INK);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A poster which splits onto sheets with room for a 100 by 150 pixel piece and 10 pixels of
+    /// overlap, turned whichever way fits best.
+    fn poster() -> Poster {
+        Poster {
+            sheet: PrintLayout {
+                paper: Paper::Custom {
+                    width_mm: 120.0,
+                    height_mm: 170.0,
+                },
+                dpi: 25.4,
+                margin_mm: 10.0,
+                ..PrintLayout::default()
+            },
+            overlap_mm: 10.0,
+        }
+    }
+
+    #[test]
+    fn pieces_overlap_and_trims_meet() {
+        let grid = poster().grid(250, 180).unwrap();
+        // Landscape sheets hold 150 by 100 pixel pieces, which needs 2 by 2 sheets instead of 3
+        // by 2
+        assert_eq!(grid.orientation, Orientation::Landscape);
+        assert_eq!((grid.columns, grid.rows), (2, 2));
+        assert_eq!(grid.span(0, 1, 250), (140, 110));
+        // The trims cover the whole poster without gaps or overlaps
+        assert_eq!(grid.trim(0, 0, 250), (0, 145));
+        assert_eq!(grid.trim(0, 1, 250), (145, 105));
+        assert_eq!(grid.trim(1, 1, 180), (95, 85));
+        assert!(Poster {
+            overlap_mm: 100.0,
+            ..poster()
+        }
+        .grid(250, 180)
+        .is_err());
+    }
+
+    #[test]
+    fn sheets_have_every_part_of_the_poster() {
+        let (width, height) = (250, 180);
+        let pixel = |x: usize, y: usize| Rgb([x as u8, y as u8, 7]);
+        let image = RgbImage::from_fn(width as u32, height as u32, |x, y| {
+            pixel(x as usize, y as usize)
+        });
+        let dir = std::env::temp_dir().join("attractors_poster_test");
+        fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("poster.png");
+        let output = output.to_str().unwrap();
+        let poster = poster();
+        // Give the rows a few at a time, like a tiled render would
+        poster
+            .save(width, height, output, |write| {
+                image.as_raw().chunks(3 * width * 7).try_for_each(write)
+            })
+            .unwrap();
+
+        let manifest = fs::read_to_string(dir.join("poster_manifest.txt")).unwrap();
+        let sheets: Vec<&str> = manifest.lines().filter(|l| !l.starts_with('#')).collect();
+        assert_eq!(sheets.len(), 4);
+        for line in sheets {
+            let fields: Vec<&str> = line.split(',').collect();
+            let num = |i: usize| fields[i].parse::<usize>().unwrap();
+            let sheet = image::open(dir.join(fields[0])).unwrap().into_rgb8();
+            assert_eq!(sheet.dimensions(), (170, 120));
+            // The piece starts inside the 10 pixel margin
+            let (x, y, w, h) = (num(3), num(4), num(5), num(6));
+            assert_eq!(*sheet.get_pixel(10, 10), pixel(x, y));
+            assert_eq!(
+                *sheet.get_pixel(10 + w as u32 - 1, 10 + h as u32 - 1),
+                pixel(x + w - 1, y + h - 1)
+            );
+        }
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(poster
+            .save(width, height, output, |write| write(&[0; 3]))
+            .is_err());
+    }
+}
@@ -0,0 +1,596 @@
+//! Laying attractors out on paper, so that they print at the right size.
+//!
+//! A `PrintLayout` turns a paper size, an orientation, and a resolution into the size of the page
+//! in pixels, and decides where on the page the attractor goes:
+//! - The page is the paper after trimming, plus `bleed_mm` on every side for the printer to cut
+//!   off, so that nothing unprinted shows if the cut isn't perfectly straight.
+//! - The attractor is kept `margin_mm` inside the trimmed edge, or with no margin it covers the
+//!   whole page (bleed and all).
+//! - An optional caption goes in a band below the attractor, drawn with the built in font in the
+//!   brightest colour of the colour scheme.
+//!
+//! Pages are saved as PNGs with their resolution in a `pHYs` chunk, so that other programs know
+//! how large to print them. The attractor is given as rows of pixels, so pages can be streamed
+//! from a `TiledRender` without ever keeping the whole page in memory.
+use std::fmt::{self, Display};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::str::FromStr;
+
+use image::{Rgb, RgbImage};
+use png::text_metadata::{ITXtChunk, TEXtChunk};
+
+use crate::attractors::Attractor;
+use crate::colour::{u32_to_argb, ColourScheme};
+use crate::font::{draw_text, text_size, GLYPH_HEIGHT};
+
+/// Somewhere to give the RGB bytes of an image to, a few whole rows at a time from the top of the
+/// image to the bottom. Returns an error if the rows couldn't be used.
+pub type Rows<'a> = dyn FnMut(&[u8]) -> Result<(), String> + 'a;
+
+/// Text to save in a PNG, as pairs of a keyword (like `Attractor`) and its text.
+pub type Text = Vec<(&'static str, String)>;
+
+pub const MM_PER_INCH: f64 = 25.4;
+/// The height of a capital letter in the caption.
+const CAPTION_HEIGHT_MM: f64 = 3.0;
+/// The space between the caption and anything else on the page.
+const CAPTION_GAP_MM: f64 = 5.0;
+
+/// The [ISO 216](https://en.wikipedia.org/wiki/ISO_216) paper sizes. Each size is the one before
+/// it cut in half, so A1 is half of A0 and A4 is a sixteenth of A0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IsoPaper {
+    A0,
+    A1,
+    A2,
+    A3,
+    A4,
+    A5,
+    A6,
+    B0,
+    B1,
+    B2,
+    B3,
+    B4,
+    B5,
+    B6,
+}
+
+impl IsoPaper {
+    const ALL: [IsoPaper; 14] = [
+        IsoPaper::A0,
+        IsoPaper::A1,
+        IsoPaper::A2,
+        IsoPaper::A3,
+        IsoPaper::A4,
+        IsoPaper::A5,
+        IsoPaper::A6,
+        IsoPaper::B0,
+        IsoPaper::B1,
+        IsoPaper::B2,
+        IsoPaper::B3,
+        IsoPaper::B4,
+        IsoPaper::B5,
+        IsoPaper::B6,
+    ];
+
+    /// The name of the size, like `A4`.
+    pub fn name(&self) -> String {
+        format!("{:?}", self)
+    }
+
+    /// The width and height in millimetres, in portrait.
+    pub fn size_mm(&self) -> (f64, f64) {
+        let (mut size, halvings) = match *self as usize {
+            i if i <= IsoPaper::A6 as usize => ((841, 1189), i),
+            i => ((1000, 1414), i - IsoPaper::B0 as usize),
+        };
+        // The standard rounds every halving down to a whole millimetre
+        for _ in 0..halvings {
+            size = (size.1 / 2, size.0);
+        }
+        (size.0 as f64, size.1 as f64)
+    }
+}
+
+/// The paper sizes used in the US and Canada.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsPaper {
+    Letter,
+    Legal,
+    Tabloid,
+    ArchA,
+    ArchB,
+    ArchC,
+    ArchD,
+    ArchE,
+}
+
+impl UsPaper {
+    const ALL: [UsPaper; 8] = [
+        UsPaper::Letter,
+        UsPaper::Legal,
+        UsPaper::Tabloid,
+        UsPaper::ArchA,
+        UsPaper::ArchB,
+        UsPaper::ArchC,
+        UsPaper::ArchD,
+        UsPaper::ArchE,
+    ];
+
+    /// The name of the size, like `letter` or `arch-d`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            UsPaper::Letter => "letter",
+            UsPaper::Legal => "legal",
+            UsPaper::Tabloid => "tabloid",
+            UsPaper::ArchA => "arch-a",
+            UsPaper::ArchB => "arch-b",
+            UsPaper::ArchC => "arch-c",
+            UsPaper::ArchD => "arch-d",
+            UsPaper::ArchE => "arch-e",
+        }
+    }
+
+    /// The width and height in inches, in portrait.
+    pub fn size_in(&self) -> (f64, f64) {
+        match self {
+            UsPaper::Letter => (8.5, 11.0),
+            UsPaper::Legal => (8.5, 14.0),
+            UsPaper::Tabloid => (11.0, 17.0),
+            UsPaper::ArchA => (9.0, 12.0),
+            UsPaper::ArchB => (12.0, 18.0),
+            UsPaper::ArchC => (18.0, 24.0),
+            UsPaper::ArchD => (24.0, 36.0),
+            UsPaper::ArchE => (36.0, 48.0),
+        }
+    }
+}
+
+/// Any size of paper.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Paper {
+    Iso(IsoPaper),
+    Us(UsPaper),
+    Custom { width_mm: f64, height_mm: f64 },
+}
+
+impl Paper {
+    /// The width and height in millimetres, before the orientation is applied.
+    pub fn size_mm(&self) -> (f64, f64) {
+        match self {
+            Paper::Iso(paper) => paper.size_mm(),
+            Paper::Us(paper) => {
+                let (width, height) = paper.size_in();
+                (width * MM_PER_INCH, height * MM_PER_INCH)
+            }
+            Paper::Custom {
+                width_mm,
+                height_mm,
+            } => (*width_mm, *height_mm),
+        }
+    }
+}
+
+impl Display for Paper {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Paper::Iso(paper) => write!(f, "{}", paper.name()),
+            Paper::Us(paper) => write!(f, "{}", paper.name()),
+            Paper::Custom {
+                width_mm,
+                height_mm,
+            } => write!(f, "{}x{}mm", width_mm, height_mm),
+        }
+    }
+}
+
+impl FromStr for Paper {
+    type Err = String;
+
+    /// Parse a named size like `a4`, `b2`, `letter`, or `arch-d`, or a custom size like
+    /// `300x200mm` or `24x36in`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.trim().to_lowercase();
+        if let Some(paper) = IsoPaper::ALL
+            .iter()
+            .find(|p| p.name().to_lowercase() == lower)
+        {
+            return Ok(Paper::Iso(*paper));
+        }
+        if let Some(paper) = UsPaper::ALL.iter().find(|p| p.name() == lower) {
+            return Ok(Paper::Us(*paper));
+        }
+        let (size, mm_per_unit) = if let Some(size) = lower.strip_suffix("mm") {
+            (size, 1.0)
+        } else if let Some(size) = lower.strip_suffix("in") {
+            (size, MM_PER_INCH)
+        } else {
+            return Err(format!("Unknown paper size `{}`", s));
+        };
+        let custom = size.split_once('x').and_then(|(width, height)| {
+            let width: f64 = width.trim().parse().ok()?;
+            let height: f64 = height.trim().parse().ok()?;
+            (width > 0.0 && height > 0.0).then_some(Paper::Custom {
+                width_mm: width * mm_per_unit,
+                height_mm: height * mm_per_unit,
+            })
+        });
+        custom.ok_or_else(|| format!("Custom paper sizes look like 300x200mm, not `{}`", s))
+    }
+}
+
+/// Which way up the paper is. Portrait pages are taller than they're wide, and landscape pages
+/// are wider than they're tall.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    Portrait,
+    Landscape,
+}
+
+impl Display for Orientation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Orientation::Portrait => write!(f, "portrait"),
+            Orientation::Landscape => write!(f, "landscape"),
+        }
+    }
+}
+
+impl FromStr for Orientation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "portrait" => Ok(Orientation::Portrait),
+            "landscape" => Ok(Orientation::Landscape),
+            _ => Err(format!("Unknown orientation `{}`", s)),
+        }
+    }
+}
+
+/// A rectangle of pixels on the page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Area {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+/// Where everything goes on a printed page. See the module docs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrintLayout {
+    pub paper: Paper,
+    pub orientation: Orientation,
+    /// The resolution of the page in pixels per inch.
+    pub dpi: f64,
+    /// The space between the trimmed edge of the paper and the attractor, or 0 to print the
+    /// attractor right up to (and past) the edge.
+    pub margin_mm: f64,
+    /// The extra paper around every side, which is cut off after printing.
+    pub bleed_mm: f64,
+    /// Text printed below the attractor. Each line of the caption is drawn below the last.
+    pub caption: Option<String>,
+}
+
+impl Default for PrintLayout {
+    fn default() -> Self {
+        PrintLayout {
+            paper: Paper::Iso(IsoPaper::A3),
+            orientation: Orientation::Portrait,
+            dpi: 600.0,
+            margin_mm: 0.0,
+            bleed_mm: 0.0,
+            caption: None,
+        }
+    }
+}
+
+impl PrintLayout {
+    /// The number of pixels covering `mm` millimetres.
+    pub fn px(&self, mm: f64) -> usize {
+        (mm / MM_PER_INCH * self.dpi).round() as usize
+    }
+
+    /// The width and height of the paper in pixels after trimming, turned to the orientation.
+    fn trimmed_size(&self) -> (usize, usize) {
+        let (width, height) = self.paper.size_mm();
+        let (short, long) = (width.min(height), width.max(height));
+        match self.orientation {
+            Orientation::Portrait => (self.px(short), self.px(long)),
+            Orientation::Landscape => (self.px(long), self.px(short)),
+        }
+    }
+
+    /// The width and height of the whole page in pixels, including the bleed.
+    pub fn page_size(&self) -> (usize, usize) {
+        let (width, height) = self.trimmed_size();
+        let bleed = self.px(self.bleed_mm);
+        (width + 2 * bleed, height + 2 * bleed)
+    }
+
+    /// The distance from the left of the page to the caption.
+    fn caption_left(&self) -> usize {
+        match self.inset() {
+            // Keep the caption clear of the edge when the attractor covers the whole page
+            0 => self.px(self.bleed_mm) + self.px(CAPTION_GAP_MM),
+            inset => inset,
+        }
+    }
+
+    /// The number of image pixels per font pixel, so that capitals are `CAPTION_HEIGHT_MM` high,
+    /// or smaller if that's needed for `caption` to fit across the page.
+    fn caption_scale(&self, caption: &str) -> usize {
+        let scale = (self.px(CAPTION_HEIGHT_MM) as f64 / GLYPH_HEIGHT as f64).round() as usize;
+        let room = self.page_size().0.saturating_sub(2 * self.caption_left());
+        let (width, _height) = text_size(caption, 1);
+        scale.min(room / width.max(1)).max(1)
+    }
+
+    /// The height of the band below the attractor which the caption is drawn in, which is 0
+    /// without a caption.
+    fn caption_band(&self) -> usize {
+        let Some(caption) = &self.caption else {
+            return 0;
+        };
+        let gap = self.px(CAPTION_GAP_MM);
+        let (_width, text_height) = text_size(caption, self.caption_scale(caption));
+        match self.inset() {
+            0 => gap + text_height + gap + self.px(self.bleed_mm),
+            _ => gap + text_height,
+        }
+    }
+
+    /// The distance from the edge of the page to the attractor.
+    fn inset(&self) -> usize {
+        if self.margin_mm > 0.0 {
+            self.px(self.bleed_mm) + self.px(self.margin_mm)
+        } else {
+            0
+        }
+    }
+
+    /// Where the attractor goes on the page. Returns an error if the margins and caption leave
+    /// no room for it.
+    pub fn art_area(&self) -> Result<Area, String> {
+        let (width, height) = self.page_size();
+        let inset = self.inset();
+        let band = self.caption_band();
+        let area = Area {
+            x: inset,
+            y: inset,
+            width: width.saturating_sub(2 * inset),
+            height: height.saturating_sub(2 * inset + band),
+        };
+        if area.width == 0 || area.height == 0 {
+            return Err(format!(
+                "The margins and caption leave no room on a {} by {} pixel page",
+                width, height
+            ));
+        }
+        Ok(area)
+    }
+
+    /// Give `write` every row of the page. `art` is called once, and should give every row of
+    /// the art (which must be the size of `art_area`) to the `Rows` it's called with. Whatever
+    /// `art` returns is returned once the page is finished.
+    pub fn write_rows<T>(
+        &self,
+        colours: &ColourScheme,
+        art: impl FnOnce(&mut Rows) -> Result<T, String>,
+        write: &mut Rows,
+    ) -> Result<T, String> {
+        let (width, height) = self.page_size();
+        let area = self.art_area()?;
+        let (_a, r, g, b) = u32_to_argb(colours.to_u32(0.0));
+        let blank_row = [r, g, b].repeat(width);
+
+        for _ in 0..area.y {
+            write(&blank_row)?;
+        }
+        let row_bytes = 3 * area.width;
+        let wrong_size = || {
+            format!(
+                "The attractor must be {} by {} pixels to fit on the page",
+                area.width, area.height
+            )
+        };
+        let mut art_rows = 0;
+        let mut row = Vec::with_capacity(3 * width);
+        let drawn = art(&mut |rows: &[u8]| {
+            for art_row in rows.chunks(row_bytes) {
+                if art_row.len() != row_bytes || art_rows == area.height {
+                    return Err(wrong_size());
+                }
+                row.clear();
+                row.extend_from_slice(&blank_row[..3 * area.x]);
+                row.extend_from_slice(art_row);
+                row.extend_from_slice(&blank_row[3 * (area.x + area.width)..]);
+                write(&row)?;
+                art_rows += 1;
+            }
+            Ok(())
+        })?;
+        if art_rows != area.height {
+            return Err(wrong_size());
+        }
+
+        // Everything below the attractor is small enough to draw in memory
+        let below = height - area.y - area.height;
+        let mut footer = RgbImage::from_pixel(width as u32, below as u32, Rgb([r, g, b]));
+        if let Some(caption) = &self.caption {
+            let (_a, r, g, b) = u32_to_argb(colours.to_u32(1.0));
+            draw_text(
+                &mut footer,
+                self.caption_left(),
+                self.px(CAPTION_GAP_MM),
+                self.caption_scale(caption),
+                caption,
+                Rgb([r, g, b]),
+            );
+        }
+        write(footer.as_raw())?;
+        Ok(drawn)
+    }
+}
+
+/// The caption for `attractor`: its name, then the formula with its parameters filled in.
+pub fn caption<A: Attractor>(attractor: &A) -> String {
+    let mut lines = vec![A::NAME.to_string()];
+    lines.extend(attractor.equations());
+    lines.join("\n")
+}
+
+/// Save a `width` by `height` RGB PNG to `output`, which should be printed at `dpi` pixels per
+/// inch (if it's given). `rows` is called once, and should give every row of the image to the
+/// `Rows` it's called with, then return any text to save in the PNG.
+///
+/// The text goes in `tEXt` chunks (or `iTXt` chunks, if it isn't Latin-1) after the image, since
+/// some of it (like the bounds of the attractor) is only known once the image has been drawn.
+pub fn save_png(
+    output: &str,
+    width: usize,
+    height: usize,
+    dpi: Option<f64>,
+    rows: impl FnOnce(&mut Rows) -> Result<Text, String>,
+) -> Result<(), String> {
+    let file = File::create(output).map_err(|e| format!("Couldn't create {}: {}", output, e))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let png_err = |e: png::EncodingError| format!("Couldn't write {}: {}", output, e);
+    let mut writer = encoder.write_header().map_err(png_err)?;
+    if let Some(dpi) = dpi {
+        // PNGs store their resolution in pixels per metre, for both axes, followed by the unit
+        let ppm = (dpi / MM_PER_INCH * 1000.0).round() as u32;
+        let mut phys = Vec::with_capacity(9);
+        phys.extend_from_slice(&ppm.to_be_bytes());
+        phys.extend_from_slice(&ppm.to_be_bytes());
+        phys.push(1);
+        writer
+            .write_chunk(png::chunk::pHYs, &phys)
+            .map_err(png_err)?;
+    }
+    let mut stream = writer.stream_writer().map_err(png_err)?;
+    let text = rows(&mut |bytes: &[u8]| {
+        stream
+            .write_all(bytes)
+            .map_err(|e| format!("Couldn't write {}: {}", output, e))
+    })?;
+    stream.finish().map_err(png_err)?;
+    for (keyword, text) in text {
+        let result = if text.chars().all(|c| (c as u32) < 256) {
+            writer.write_text_chunk(&TEXtChunk::new(keyword, text))
+        } else {
+            writer.write_text_chunk(&ITXtChunk::new(keyword, text))
+        };
+        result.map_err(png_err)?;
+    }
+    writer.finish().map_err(png_err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paper_sizes_match_the_standards() {
+        assert_eq!(IsoPaper::A4.size_mm(), (210.0, 297.0));
+        assert_eq!(IsoPaper::A6.size_mm(), (105.0, 148.0));
+        assert_eq!(IsoPaper::B5.size_mm(), (176.0, 250.0));
+        assert_eq!("A0".parse::<Paper>(), Ok(Paper::Iso(IsoPaper::A0)));
+        assert_eq!("arch-d".parse::<Paper>(), Ok(Paper::Us(UsPaper::ArchD)));
+        assert_eq!(
+            "24x36in".parse::<Paper>().unwrap().size_mm(),
+            (24.0 * 25.4, 36.0 * 25.4)
+        );
+        assert!("a9".parse::<Paper>().is_err());
+        assert!("0x10mm".parse::<Paper>().is_err());
+        // The same sizes as were used before there was a print layout
+        let layout = PrintLayout {
+            paper: Paper::Iso(IsoPaper::A0),
+            ..PrintLayout::default()
+        };
+        assert_eq!(layout.page_size(), (19866, 28087));
+    }
+
+    #[test]
+    fn art_fits_inside_the_margins_and_above_the_caption() {
+        let mut layout = PrintLayout {
+            paper: Paper::Custom {
+                width_mm: 100.0,
+                height_mm: 50.0,
+            },
+            orientation: Orientation::Portrait,
+            dpi: 254.0,
+            ..PrintLayout::default()
+        };
+        // 10 pixels per millimetre, turned so that the long side is the height
+        assert_eq!(layout.page_size(), (500, 1000));
+        assert_eq!(layout.art_area().unwrap().width, 500);
+        layout.bleed_mm = 3.0;
+        layout.margin_mm = 10.0;
+        assert_eq!(layout.page_size(), (560, 1060));
+        assert_eq!(
+            layout.art_area().unwrap(),
+            Area {
+                x: 130,
+                y: 130,
+                width: 300,
+                height: 800
+            }
+        );
+        layout.caption = Some("two\nlines".to_string());
+        let art = layout.art_area().unwrap();
+        let band = layout.caption_band();
+        assert!(band > 0);
+        assert_eq!(art.height + band, 800);
+        layout.margin_mm = 30.0;
+        assert!(layout.art_area().is_err());
+    }
+
+    #[test]
+    fn pages_are_saved_with_their_resolution() {
+        let layout = PrintLayout {
+            paper: Paper::Custom {
+                width_mm: 20.0,
+                height_mm: 30.0,
+            },
+            dpi: 127.0,
+            margin_mm: 2.0,
+            caption: Some("clifford".to_string()),
+            ..PrintLayout::default()
+        };
+        let area = layout.art_area().unwrap();
+        let art = RgbImage::from_pixel(area.width as u32, area.height as u32, Rgb([1, 2, 3]));
+        let output = std::env::temp_dir().join("attractors_print_test.png");
+        let output = output.to_str().unwrap();
+        let (width, height) = layout.page_size();
+        let save = |art: &RgbImage| {
+            save_png(output, width, height, Some(layout.dpi), |write| {
+                let colours = ColourScheme::default();
+                layout.write_rows(&colours, |art_rows| art_rows(art.as_raw()), write)?;
+                Ok(vec![("Title", "clifford".to_string())])
+            })
+        };
+        save(&art).unwrap();
+        assert!(save(&RgbImage::new(1, 1)).is_err());
+        save(&art).unwrap();
+
+        let decoder = png::Decoder::new(File::open(output).unwrap());
+        let reader = decoder.read_info().unwrap();
+        let dims = reader.info().pixel_dims.unwrap();
+        assert_eq!(dims.unit, png::Unit::Meter);
+        assert_eq!(dims.xppu, 5000);
+        let page = image::open(output).unwrap().into_rgb8();
+        std::fs::remove_file(output).unwrap();
+        assert_eq!(page.dimensions(), (100, 150));
+        assert_eq!(
+            *page.get_pixel(area.x as u32, area.y as u32),
+            Rgb([1, 2, 3])
+        );
+        assert_ne!(*page.get_pixel(0, 0), Rgb([1, 2, 3]));
+    }
+}
@@ -0,0 +1,493 @@
+//! Remembering how an image was rendered, so that it can be rendered again from the image alone.
+//!
+//! Filenames like `a=..._iters=....png` only remember some of what went into an image, and none
+//! of its colours. Instead, every PNG saved by `render`, `batch`, or the `S` key gets text chunks
+//! (see `print::save_png`) holding the attractor, all of its parameters, the number of
+//! iterations, the bounds which were drawn, the border, the `LchParams`, the tone curve, and any
+//! other options which change how it looks. Like:
+//! ```
+//! Software: attractors
+//! Attractor: clifford
+//! Parameters: -1.4,1.6,1,0.7
+//! Iterations: 10000000
+//! Bounds: -1.9838,1.9838,-1.7001,1.7001
+//! Border: 0.05
+//! LchParams: light_slope=1,light_intercept=0,chroma_slope=0.2,...
+//! Tone curve: equalise
+//! ```
+//! Apart from `Software` and `LchParams`, each chunk holds the value of a command line option
+//! written just like it would be on the command line, so reading an image back is a matter of
+//! turning its chunks back into options. The bounds are the ones which were actually drawn
+//! (after `--fit-bounds`, or after a Sprott attractor has grown), and are read back as a
+//! `--viewport`, so the attractor lands in exactly the same place again.
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+
+use crate::attractors::Bounds;
+use crate::camera::Projection;
+use crate::colour::LchParams;
+use crate::headless::{Flags, RenderOptions};
+use crate::ode::Integrator;
+use crate::palette::{parse_hex, Palette};
+use crate::print::Text;
+
+/// The `Software` chunk of every image rendered by this program.
+const SOFTWARE: &str = "attractors";
+
+/// The keyword of each chunk which holds the value of a command line option, and that option.
+const OPTIONS: &[(&str, &str)] = &[
+    ("Attractor", "attractor"),
+    ("Parameters", "params"),
+    ("Iterations", "iterations"),
+    ("Bounds", "viewport"),
+    ("Border", "border"),
+    ("Tone curve", "tone"),
+    ("Clip", "clip"),
+    ("Palette", "palette"),
+    ("Width", "width"),
+    ("Height", "height"),
+    ("Paper", "paper"),
+    ("Orientation", "orientation"),
+    ("DPI", "dpi"),
+    ("Margin", "margin"),
+    ("Bleed", "bleed"),
+    ("Caption", "caption"),
+    ("Splat", "splat"),
+    ("Supersample", "supersample"),
+    ("Filter", "filter"),
+    ("Tile rows", "tile-rows"),
+    ("Orbits", "orbits"),
+    ("Orbit transient", "orbit-transient"),
+    ("Jitter", "jitter"),
+    ("Seed", "seed"),
+    ("Yaw", "yaw"),
+    ("Pitch", "pitch"),
+    ("Roll", "roll"),
+    ("Zoom", "zoom"),
+    ("Perspective", "perspective"),
+    ("Depth shading", "depth-shading"),
+    ("Trajectory file", "from-file"),
+    ("Time step", "dt"),
+    ("Transient", "transient"),
+    ("RK45 tolerance", "rk45"),
+];
+
+/// The options which lay an image out on paper.
+const PRINT_OPTIONS: &[&str] = &["paper", "orientation", "dpi", "margin", "bleed", "caption"];
+
+/// The options remembered from an image which are left out when an option is changed, since
+/// they'd conflict with it.
+const REPLACED_BY: &[(&str, &[&str])] = &[
+    ("fit-bounds", &["viewport"]),
+    ("light-power", &["tone"]),
+    ("paper", &["width", "height"]),
+    ("width", PRINT_OPTIONS),
+    ("height", PRINT_OPTIONS),
+];
+
+/// The text chunks describing how `opts` rendered an attractor with `params` (and `code`, if the
+/// attractor has one). `bounds` are the bounds which were drawn, if they're known.
+pub fn text(
+    opts: &RenderOptions,
+    params: &[f64],
+    code: Option<String>,
+    bounds: Option<Bounds>,
+) -> Text {
+    let join = |values: &[f64]| {
+        let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+        values.join(",")
+    };
+    let lch = &opts.colours.lch;
+    let mut text: Text = vec![
+        ("Software", SOFTWARE.to_string()),
+        ("Attractor", opts.attractor.clone()),
+        ("Parameters", join(params)),
+    ];
+    if let Some(code) = code {
+        text.push(("Code", code));
+    }
+    text.push(("Iterations", opts.iterations.to_string()));
+    if let Some(b) = bounds {
+        text.push(("Bounds", join(&[b.xmin, b.xmax, b.ymin, b.ymax])));
+    }
+    text.push(("Border", opts.border.to_string()));
+    text.push((
+        "LchParams",
+        format!(
+            "light_slope={},light_intercept={},chroma_slope={},chroma_intercept={},\
+             hue_slope={},hue_intercept={}",
+            lch.light_slope,
+            lch.light_intercept,
+            lch.chroma_slope,
+            lch.chroma_intercept,
+            lch.hue_slope,
+            lch.hue_intercept
+        ),
+    ));
+    text.push(("Tone curve", opts.colours.tone.curve.to_string()));
+    text.push((
+        "Clip",
+        join(&[opts.colours.tone.clip.0, opts.colours.tone.clip.1]),
+    ));
+    if let Some(palette) = &opts.colours.palette {
+        text.push(("Palette", palette.name.clone()));
+        // Palettes from a palette file can't be found by name again, so keep every stop
+        let stops: Vec<String> = palette
+            .stops
+            .iter()
+            .map(|(pos, [r, g, b])| format!("{}:#{:02x}{:02x}{:02x}", pos, r, g, b))
+            .collect();
+        text.push(("Palette stops", stops.join(",")));
+    }
+    match &opts.print {
+        Some(layout) => {
+            text.push(("Paper", layout.paper.to_string()));
+            text.push(("Orientation", layout.orientation.to_string()));
+            text.push(("DPI", layout.dpi.to_string()));
+            text.push(("Margin", layout.margin_mm.to_string()));
+            text.push(("Bleed", layout.bleed_mm.to_string()));
+            if opts.auto_caption {
+                text.push(("Caption", "auto".to_string()));
+            } else if let Some(caption) = &layout.caption {
+                text.push(("Caption", caption.clone()));
+            }
+        }
+        None => {
+            text.push(("Width", opts.width.to_string()));
+            text.push(("Height", opts.height.to_string()));
+        }
+    }
+    text.push(("Splat", opts.sampling.splat.to_string()));
+    text.push(("Supersample", opts.sampling.supersample.to_string()));
+    text.push(("Filter", opts.sampling.filter.to_string()));
+    if opts.tiled {
+        let rows = opts.tile_rows.map_or("auto".to_string(), |r| r.to_string());
+        text.push(("Tile rows", rows));
+    }
+    if opts.orbits.count > 1 {
+        text.push(("Orbits", opts.orbits.count.to_string()));
+        text.push(("Orbit transient", opts.orbits.transient.to_string()));
+        text.push(("Jitter", opts.orbits.jitter.to_string()));
+        text.push(("Seed", opts.orbits.seed.to_string()));
+    }
+    let camera = &opts.camera;
+    if !camera.is_default() {
+        text.push(("Yaw", camera.yaw.to_string()));
+        text.push(("Pitch", camera.pitch.to_string()));
+        text.push(("Roll", camera.roll.to_string()));
+        text.push(("Zoom", camera.zoom.to_string()));
+        if let Projection::Perspective { distance } = camera.projection {
+            text.push(("Perspective", distance.to_string()));
+        }
+        if camera.depth_shading != 0.0 {
+            text.push(("Depth shading", camera.depth_shading.to_string()));
+        }
+    }
+    if let Some(filename) = &opts.from_file {
+        text.push(("Trajectory file", filename.clone()));
+    }
+    if let Some(dt) = opts.dt {
+        text.push(("Time step", dt.to_string()));
+    }
+    if let Some(transient) = opts.transient {
+        text.push(("Transient", transient.to_string()));
+    }
+    if let Integrator::Rk45 { tolerance } = opts.integrator {
+        text.push(("RK45 tolerance", tolerance.to_string()));
+    }
+    text
+}
+
+/// Render options which draw the image at `path` again, with `changes` (parsed command line
+/// flags, which must include `output`) replacing what the image remembers.
+pub fn render_options(path: &str, changes: Flags) -> Result<RenderOptions, String> {
+    let text = read_text(path)?;
+    if text.get("Software").map(|s| s.as_str()) != Some(SOFTWARE) {
+        return Err(format!(
+            "{} doesn't remember how it was rendered, so it can't be rendered again",
+            path
+        ));
+    }
+    if !changes.contains_key("output") {
+        return Err("An output file must be given with `--output`".to_string());
+    }
+    let mut opts = RenderOptions::default();
+    if let Some(lch) = text.get("LchParams") {
+        opts.colours.lch = parse_lch(lch).map_err(|e| format!("{}: {}", path, e))?;
+    }
+    let mut flags: Flags = OPTIONS
+        .iter()
+        .filter_map(|(keyword, flag)| Some((flag.to_string(), text.get(*keyword)?.clone())))
+        .collect();
+    if let (Some(name), Some(stops)) = (text.get("Palette"), text.get("Palette stops")) {
+        // The palette is rebuilt from its stops, since it might have come from a palette file
+        flags.remove("palette");
+        opts.colours.palette =
+            Some(parse_stops(name, stops).map_err(|e| format!("{}: {}", path, e))?);
+    }
+    for (changed, replaced) in REPLACED_BY {
+        if changes.contains_key(*changed) {
+            for flag in *replaced {
+                flags.remove(*flag);
+            }
+        }
+    }
+    flags.extend(changes);
+    opts.update_from_flags(flags)?;
+    Ok(opts)
+}
+
+/// True if the image at `path` remembers being rendered by `opts` with `params`, so rendering it
+/// again would give the same image. The bounds which were drawn are only known after rendering,
+/// so they're only compared if `opts` has a viewport. Images which don't remember how they were
+/// rendered never match. Codes come from the parameters, so they're never compared.
+pub fn rendered_by(path: &str, opts: &RenderOptions, params: &[f64]) -> bool {
+    let saved = match read_text(path) {
+        Ok(saved) => saved,
+        Err(_) => return false,
+    };
+    let compared = |keyword: &str| {
+        keyword != "Code" && (keyword != "Bounds" || opts.camera.viewport.is_some())
+    };
+    let expected: Text = text(opts, params, None, opts.camera.viewport)
+        .into_iter()
+        .filter(|(keyword, _)| compared(keyword))
+        .collect();
+    saved.keys().filter(|keyword| compared(keyword)).count() == expected.len()
+        && expected
+            .iter()
+            .all(|(keyword, value)| saved.get(*keyword) == Some(value))
+}
+
+/// Parse the `Palette stops` of the palette `name`, written like `0:#000000,1:#ffffff` by
+/// `text`.
+fn parse_stops(name: &str, text: &str) -> Result<Palette, String> {
+    let invalid = || format!("Couldn't understand the palette stops `{}`", text);
+    let mut positions = vec![];
+    let mut colours = vec![];
+    for stop in text.split(',') {
+        let (pos, hex) = stop.split_once(':').ok_or_else(invalid)?;
+        positions.push(pos.trim().parse().map_err(|_| invalid())?);
+        colours.push(parse_hex(hex).ok_or_else(invalid)?);
+    }
+    Palette::new(name, colours, Some(positions))
+}
+
+/// Parse `LchParams` written like `light_slope=1,light_intercept=0,...` by `text`.
+fn parse_lch(text: &str) -> Result<LchParams, String> {
+    let mut lch = LchParams::default();
+    let mut found = 0;
+    for pair in text.split(',') {
+        let (name, value) = pair
+            .split_once('=')
+            .ok_or_else(|| format!("Couldn't understand the LchParams `{}`", text))?;
+        let field = match name.trim() {
+            "light_slope" => &mut lch.light_slope,
+            "light_intercept" => &mut lch.light_intercept,
+            "chroma_slope" => &mut lch.chroma_slope,
+            "chroma_intercept" => &mut lch.chroma_intercept,
+            "hue_slope" => &mut lch.hue_slope,
+            "hue_intercept" => &mut lch.hue_intercept,
+            _ => return Err(format!("Unknown LchParams value `{}`", name)),
+        };
+        *field = value
+            .trim()
+            .parse()
+            .map_err(|_| format!("Couldn't understand the LchParams `{}`", text))?;
+        found += 1;
+    }
+    if found != 6 {
+        return Err(format!("The LchParams `{}` are missing a value", text));
+    }
+    Ok(lch)
+}
+
+/// Read every uncompressed `tEXt` and `iTXt` chunk of the PNG at `path`, as a map from keyword
+/// to text. The image itself is skipped over without being decoded, so this is quick even for
+/// posters.
+pub fn read_text(path: &str) -> Result<HashMap<String, String>, String> {
+    let err = |e: std::io::Error| format!("Couldn't read {}: {}", path, e);
+    let mut file = BufReader::new(File::open(path).map_err(err)?);
+    let mut signature = [0; 8];
+    file.read_exact(&mut signature).map_err(err)?;
+    if signature != [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'] {
+        return Err(format!("{} isn't a PNG", path));
+    }
+    let mut text = HashMap::new();
+    loop {
+        // Each chunk is its length, its type, its data, then a CRC of the type and data
+        let mut header = [0; 8];
+        file.read_exact(&mut header).map_err(err)?;
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+        match &header[4..] {
+            b"tEXt" | b"iTXt" => {
+                let mut data = vec![0; length as usize];
+                file.read_exact(&mut data).map_err(err)?;
+                file.seek(SeekFrom::Current(4)).map_err(err)?;
+                let chunk = match &header[4..] {
+                    b"tEXt" => latin1_text(&data),
+                    _ => utf8_text(&data),
+                };
+                if let Some((keyword, value)) = chunk {
+                    text.insert(keyword, value);
+                }
+            }
+            b"IEND" => return Ok(text),
+            _ => {
+                file.seek(SeekFrom::Current(length as i64 + 4))
+                    .map_err(err)?;
+            }
+        }
+    }
+}
+
+/// The keyword and text of a `tEXt` chunk: the keyword, a zero byte, then the text, both in
+/// Latin-1.
+fn latin1_text(data: &[u8]) -> Option<(String, String)> {
+    let split = data.iter().position(|b| *b == 0)?;
+    let latin1 = |bytes: &[u8]| bytes.iter().map(|b| *b as char).collect();
+    Some((latin1(&data[..split]), latin1(&data[split + 1..])))
+}
+
+/// The keyword and text of an `iTXt` chunk: the keyword, a zero byte, whether the text is
+/// compressed and how, the language and translated keyword (each followed by a zero byte), and
+/// then the text in UTF-8. Compressed text is left out, since it's never written here.
+fn utf8_text(data: &[u8]) -> Option<(String, String)> {
+    let split = data.iter().position(|b| *b == 0)?;
+    let keyword = String::from_utf8(data[..split].to_vec()).ok()?;
+    let rest = data.get(split + 1..)?;
+    if rest.first() != Some(&0) {
+        return None;
+    }
+    let mut rest = rest.get(2..)?;
+    for _ in 0..2 {
+        let end = rest.iter().position(|b| *b == 0)?;
+        rest = &rest[end + 1..];
+    }
+    Some((keyword, String::from_utf8(rest.to_vec()).ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attractors::{Attractor, SprottAttractor};
+    use crate::print::save_png;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn images_can_be_rendered_again() {
+        let dir = std::env::temp_dir().join("attractors_provenance_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("first.png").to_string_lossy().to_string();
+        let opts = RenderOptions::from_args(&args(&format!(
+            "--attractor clifford --params -1.4,1.6,1.0,0.7 --width 40 --height 30 \
+             --iterations 20000 --hue 0.3 --tone log:100 --clip 1,99 --fit-bounds 0.5 \
+             --output {}",
+            output
+        )))
+        .unwrap();
+        opts.render().unwrap();
+
+        let mut changes = Flags::new();
+        changes.insert("output".to_string(), "second.png".to_string());
+        let again = render_options(&output, changes.clone()).unwrap();
+        assert_eq!(again.attractor, "clifford");
+        assert_eq!(again.params, Some(vec![-1.4, 1.6, 1.0, 0.7]));
+        assert_eq!((again.width, again.height), (40, 30));
+        assert_eq!(again.iterations, 20000);
+        assert_eq!(again.colours.lch, opts.colours.lch);
+        assert_eq!(again.colours.tone, opts.colours.tone);
+        // The fitted bounds are remembered, so they don't need fitting again
+        assert!(!again.fit_bounds && again.camera.viewport.is_some());
+        assert_eq!(
+            again.render_image().unwrap().0,
+            opts.render_image().unwrap().0
+        );
+
+        // Changed options replace the ones which were remembered
+        changes.insert("width".to_string(), "80".to_string());
+        changes.insert("background".to_string(), "light".to_string());
+        let larger = render_options(&output, changes.clone()).unwrap();
+        assert_eq!((larger.width, larger.height), (80, 30));
+        assert_eq!(larger.colours.lch.light_intercept, 1.0);
+        assert_eq!(larger.colours.lch.hue_intercept, 0.3);
+        changes.insert("fit-bounds".to_string(), "0.1".to_string());
+        assert!(render_options(&output, changes).unwrap().fit_bounds);
+        assert!(render_options(&output, Flags::new()).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn palettes_from_files_are_remembered() {
+        let dir = std::env::temp_dir().join("attractors_provenance_palette_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let palettes = dir.join("palettes.toml").to_string_lossy().to_string();
+        std::fs::write(
+            &palettes,
+            "[sunset]\ncolours = [\"#000000\", \"#7b1fa2\", \"#fff59d\"]\n\
+             positions = [0.0, 0.123456789, 1.0]\n",
+        )
+        .unwrap();
+        let output = dir.join("first.png").to_string_lossy().to_string();
+        let opts = RenderOptions::from_args(&args(&format!(
+            "--params -1.4,1.6,1.0,0.7 --width 20 --height 10 --iterations 5000 \
+             --palette sunset --palette-file {} --output {}",
+            palettes, output
+        )))
+        .unwrap();
+        opts.render().unwrap();
+
+        // The palette file isn't needed any more
+        std::fs::remove_file(&palettes).unwrap();
+        let mut changes = Flags::new();
+        changes.insert("output".to_string(), "second.png".to_string());
+        let again = render_options(&output, changes).unwrap();
+        assert_eq!(again.colours.palette, opts.colours.palette);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(parse_stops("bad", "0:#000000,x:#ffffff").is_err());
+    }
+
+    #[test]
+    fn sprott_codes_are_remembered() {
+        let output = std::env::temp_dir().join("attractors_provenance_code_test.png");
+        let output = output.to_str().unwrap();
+        let sprott = SprottAttractor::from_code("FIRCDERRPVLD").unwrap();
+        let params: Vec<String> = sprott.params().iter().map(|p| p.to_string()).collect();
+        let opts = RenderOptions::from_args(&args(&format!(
+            "--attractor sprott --params {} --width 20 --height 10 --iterations 5000 --output {}",
+            params.join(","),
+            output
+        )))
+        .unwrap();
+        opts.render().unwrap();
+        assert_eq!(read_text(output).unwrap()["Code"], "FIRCDERRPVLD");
+        // The code doesn't stop the image from being found again
+        assert!(rendered_by(output, &opts, &sprott.params()));
+        std::fs::remove_file(output).unwrap();
+    }
+
+    #[test]
+    fn text_is_read_back_exactly() {
+        let output = std::env::temp_dir().join("attractors_provenance_text_test.png");
+        let output = output.to_str().unwrap();
+        save_png(output, 2, 1, None, |write| {
+            write(&[0; 6])?;
+            Ok(vec![
+                ("Caption", "Lorenz\nx' = σ (y - x)".to_string()),
+                ("Title", "café".to_string()),
+            ])
+        })
+        .unwrap();
+        let text = read_text(output).unwrap();
+        assert_eq!(text["Caption"], "Lorenz\nx' = σ (y - x)");
+        assert_eq!(text["Title"], "café");
+        // Images without a Software chunk weren't rendered here
+        assert!(render_options(output, Flags::new()).is_err());
+        std::fs::remove_file(output).unwrap();
+        assert!(parse_lch("light_slope=1").is_err());
+    }
+}
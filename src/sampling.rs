@@ -0,0 +1,216 @@
+//! Anti-aliasing, for smooth renders of thin filaments.
+//!
+//! By default every position is binned into the single pixel it lands in, so a filament which is
+//! thinner than a pixel gets drawn as a jagged staircase. There are two ways to smooth this out,
+//! which can be used together:
+//! - `Splat::Bilinear` spreads each position over the four pixels nearest to it, weighted by how
+//!   close it is to the centre of each one, so that sub-pixel positions aren't lost.
+//! - Supersampling bins the positions into a histogram `supersample` times larger in each
+//!   direction, then shrinks it back down to the requested size with a `Filter`.
+//!
+//! Neither needs any more iterations, although supersampling uses `supersample` squared times
+//! more memory.
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// Renderers which count positions with integers store bilinear splats in units of
+/// `1 / SPLAT_SCALE` of a position.
+pub const SPLAT_SCALE: f64 = 64.0;
+
+/// How a position gets added to the pixels of a `Histogram`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Splat {
+    /// All of the position goes to the pixel it lands in.
+    #[default]
+    Nearest,
+    /// The position is shared between the four pixels with the nearest centres.
+    Bilinear,
+}
+
+/// The filter used to shrink a supersampled histogram back down to size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Filter {
+    /// Every output pixel is the average of the `supersample` by `supersample` block under it.
+    #[default]
+    Box,
+    /// A triangle filter twice as wide as the box, so blocks blend a little into each other.
+    Tent,
+    /// A Gaussian with a standard deviation of half an output pixel, which is the softest.
+    Gaussian,
+}
+
+impl Filter {
+    /// How far (in output pixels) the filter reaches from the centre of an output pixel.
+    fn radius(&self) -> f64 {
+        match self {
+            Filter::Box => 0.5,
+            Filter::Tent => 1.0,
+            Filter::Gaussian => 1.5,
+        }
+    }
+
+    /// The unnormalised weight of an input pixel `dist` output pixels from the centre.
+    fn weight(&self, dist: f64) -> f64 {
+        let dist = dist.abs();
+        match self {
+            Filter::Box => (dist < 0.5) as u8 as f64,
+            Filter::Tent => (1.0 - dist).max(0.0),
+            Filter::Gaussian => (-2.0 * dist * dist).exp(),
+        }
+    }
+}
+
+/// How positions are turned into densities. The default is the same as not anti-aliasing at all.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sampling {
+    pub splat: Splat,
+    /// The histogram is this many times larger in each direction before being downsampled.
+    pub supersample: usize,
+    pub filter: Filter,
+}
+
+impl Default for Sampling {
+    fn default() -> Self {
+        Sampling {
+            splat: Splat::Nearest,
+            supersample: 1,
+            filter: Filter::Box,
+        }
+    }
+}
+
+impl Display for Splat {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Splat::Nearest => write!(f, "nearest"),
+            Splat::Bilinear => write!(f, "bilinear"),
+        }
+    }
+}
+
+impl FromStr for Splat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nearest" => Ok(Splat::Nearest),
+            "bilinear" => Ok(Splat::Bilinear),
+            _ => Err(format!(
+                "Unknown splat `{}`, expected nearest or bilinear",
+                s
+            )),
+        }
+    }
+}
+
+impl Display for Filter {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Filter::Box => write!(f, "box"),
+            Filter::Tent => write!(f, "tent"),
+            Filter::Gaussian => write!(f, "gaussian"),
+        }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "box" => Ok(Filter::Box),
+            "tent" => Ok(Filter::Tent),
+            "gaussian" => Ok(Filter::Gaussian),
+            _ => Err(format!(
+                "Unknown filter `{}`, expected box, tent, or gaussian",
+                s
+            )),
+        }
+    }
+}
+
+/// The input pixels (and their weights, which sum to 1) which make up each of the
+/// `len / factor` output pixels along one axis.
+fn filter_weights(len: usize, factor: usize, filter: Filter) -> Vec<Vec<(usize, f64)>> {
+    let out_len = len / factor;
+    let reach = (filter.radius() * factor as f64).ceil() as usize;
+    (0..out_len)
+        .map(|j| {
+            // The centre of output pixel j, measured in input pixels
+            let centre = (j as f64 + 0.5) * factor as f64;
+            let first = (j * factor).saturating_sub(reach);
+            let last = ((j + 1) * factor + reach).min(len);
+            let mut weights: Vec<(usize, f64)> = (first..last)
+                .map(|i| (i, filter.weight((i as f64 + 0.5 - centre) / factor as f64)))
+                .filter(|(_, w)| *w > 0.0)
+                .collect();
+            // Pixels near the edge have less of the filter to work with, so normalise them
+            let total: f64 = weights.iter().map(|(_, w)| w).sum();
+            for (_, w) in weights.iter_mut() {
+                *w /= total;
+            }
+            weights
+        })
+        .collect()
+}
+
+/// Shrink a `width` by `height` grid (indexed as `x + y * width`) by `factor` in each
+/// direction, using `filter` to combine the pixels. `width` and `height` must be multiples of
+/// `factor`.
+pub fn downsample(
+    values: &[f64],
+    width: usize,
+    height: usize,
+    factor: usize,
+    filter: Filter,
+) -> Vec<f64> {
+    if factor == 1 {
+        return values.to_vec();
+    }
+    let (out_width, out_height) = (width / factor, height / factor);
+    // The filters are separable, so shrink the rows first and then the columns
+    let x_weights = filter_weights(width, factor, filter);
+    let mut rows = vec![0.0; out_width * height];
+    for y in 0..height {
+        let row = &values[y * width..(y + 1) * width];
+        for (x, weights) in x_weights.iter().enumerate() {
+            rows[x + y * out_width] = weights.iter().map(|(i, w)| row[*i] * w).sum();
+        }
+    }
+    let y_weights = filter_weights(height, factor, filter);
+    let mut out = vec![0.0; out_width * out_height];
+    for (y, weights) in y_weights.iter().enumerate() {
+        for (i, w) in weights {
+            let row = &rows[i * out_width..(i + 1) * out_width];
+            for (o, r) in out[y * out_width..(y + 1) * out_width].iter_mut().zip(row) {
+                *o += r * w;
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn box_filters_average_each_block() {
+        #[rustfmt::skip]
+        let values = vec![
+            1.0, 3.0, 0.0, 0.0,
+            1.0, 3.0, 0.0, 4.0,
+        ];
+        assert_eq!(downsample(&values, 4, 2, 2, Filter::Box), vec![2.0, 1.0]);
+    }
+
+    #[test]
+    fn filters_keep_constant_grids_constant() {
+        let values = vec![0.5; 12 * 9];
+        for filter in [Filter::Box, Filter::Tent, Filter::Gaussian] {
+            let out = downsample(&values, 12, 9, 3, filter);
+            assert_eq!(out.len(), 4 * 3);
+            assert!(out.iter().all(|v| (v - 0.5).abs() < 1e-12), "{}", filter);
+        }
+    }
+}
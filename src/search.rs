@@ -0,0 +1,405 @@
+//! Searching parameter space for attractors which are nice to look at.
+//!
+//! Most parameters give attractors which escape to infinity or collapse onto a dot or a loop,
+//! and the specials file was built up by pressing `m` on the rare good ones by hand. The search
+//! samples random parameters instead, throws away anything which isn't chaotic, and gives the
+//! rest a `Score` built from their Lyapunov exponent, fractal dimension, how much of the image
+//! they cover, and how evenly their density is spread. The best few are appended to the specials
+//! file along with their scores. It runs without a window, so it can be left going overnight:
+//! ```
+//! cargo run --release -- search --attractor clifford --samples 100000 --top 50
+//! ```
+use std::collections::HashSet;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use rand::Rng;
+
+use crate::attractors::*;
+use crate::batch::read_specials;
+use crate::diagnostics::{classify, correlation_dimension, lyapunov_exponent};
+use crate::flows::*;
+use crate::headless::{parse, parse_flags};
+
+/// How good an attractor looks, according to a few numbers which can be calculated from it.
+/// Every part of the score is in [0.0, 1.0], where larger is better.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Score {
+    /// The largest Lyapunov exponent per iteration.
+    pub lyapunov: f64,
+    /// The correlation dimension of the history.
+    pub dimension: f64,
+    /// The fraction of pixels which were landed on at least once.
+    pub coverage: f64,
+    /// The Shannon entropy of the density of each pixel, divided by the largest possible entropy
+    /// so that it's 1.0 if every pixel has the same density.
+    pub entropy: f64,
+    /// The weighted average of the other scores, see `Score::new`.
+    pub total: f64,
+}
+
+impl Score {
+    /// Combine the measurements into a total score, which is the weighted average of:
+    /// ```
+    /// min(lyapunov / 0.5, 1)   (more chaotic is better, up to a point)
+    /// clamp(dimension - 1, 0, 1)   (thin loops are dull, filled in areas are interesting)
+    /// coverage
+    /// entropy
+    /// ```
+    /// with `weights` in the same order.
+    pub fn new(
+        lyapunov: f64,
+        dimension: f64,
+        coverage: f64,
+        entropy: f64,
+        weights: &[f64; 4],
+    ) -> Self {
+        let parts = [
+            (lyapunov / 0.5).clamp(0.0, 1.0),
+            (dimension - 1.0).clamp(0.0, 1.0),
+            coverage,
+            entropy,
+        ];
+        let total = parts
+            .iter()
+            .zip(weights.iter())
+            .map(|(p, w)| p * w)
+            .sum::<f64>()
+            / weights.iter().sum::<f64>().max(f64::MIN_POSITIVE);
+        Score {
+            lyapunov,
+            dimension,
+            coverage,
+            entropy,
+            total,
+        }
+    }
+}
+
+/// The size of the density grid used to measure the coverage and entropy.
+const SCORE_GRID_SIZE: usize = 200;
+
+/// Score `attractor` by stepping it `iterations` times. Returns `None` if the attractor isn't
+/// chaotic, since there's no point scoring a dot or a loop.
+pub fn score<A: Attractor>(
+    attractor: &mut A,
+    iterations: usize,
+    weights: &[f64; 4],
+) -> Option<Score> {
+    if !classify(attractor, 1_000, 2_000).is_interesting() {
+        return None;
+    }
+    attractor.step(iterations);
+    let lyapunov = lyapunov_exponent(attractor, 2_000)?;
+    let dimension = correlation_dimension(attractor.history(), 1_000)?.dimension;
+
+    let mut histogram = Histogram::new(SCORE_GRID_SIZE, SCORE_GRID_SIZE, attractor.bounds(), 0.0);
+    histogram.add_all(attractor.history());
+    let total: f64 = histogram.counts.iter().sum();
+    if total == 0.0 {
+        return None;
+    }
+    let landed_on = histogram.counts.iter().filter(|c| **c > 0.0).count();
+    let coverage = landed_on as f64 / histogram.counts.len() as f64;
+    let entropy = -histogram
+        .counts
+        .iter()
+        .filter(|c| **c > 0.0)
+        .map(|c| (c / total) * (c / total).ln())
+        .sum::<f64>()
+        / (histogram.counts.len() as f64).ln();
+    Some(Score::new(lyapunov, dimension, coverage, entropy, weights))
+}
+
+/// Format a specials line for `params` named by `names`, with the attractor's code (if it has
+/// one) and the score after a `#` so that they're ignored when the specials are read back in.
+/// Parameters are written with `decimals` decimal places if it's given, otherwise in full.
+pub fn special_line(
+    names: &[String],
+    params: &[f64],
+    decimals: Option<usize>,
+    code: Option<&str>,
+    score: &Score,
+) -> String {
+    let params: Vec<String> = names
+        .iter()
+        .zip(params)
+        .map(|(name, p)| match decimals {
+            Some(decimals) => format!("{}={:.*}", name, decimals, p),
+            None => format!("{}={}", name, p),
+        })
+        .collect();
+    let code = code.map_or(String::new(), |code| format!("code={} ", code));
+    format!(
+        "{} # {}score={:.4} lyapunov={:.4} dimension={:.4} coverage={:.4} entropy={:.4}",
+        params.join(","),
+        code,
+        score.total,
+        score.lyapunov,
+        score.dimension,
+        score.coverage,
+        score.entropy
+    )
+}
+
+/// Everything needed to search for good parameters.
+#[derive(Debug, Clone)]
+pub struct SearchOptions {
+    /// The `Attractor::NAME` of the attractor to search.
+    pub attractor: String,
+    /// The number of random parameter sets to try.
+    pub samples: usize,
+    /// The number of the best parameter sets to save.
+    pub top: usize,
+    /// The number of iterations used to score each candidate.
+    pub iterations: usize,
+    /// Parameters are sampled from [-range, range]. Defaults to 4 for Clifford and De Jong
+    /// attractors and 1.2 for Sprott attractors. Flows ignore this, and instead sample within
+    /// 50% of their default parameters.
+    pub range: Option<f64>,
+    /// The weights of the Lyapunov exponent, dimension, coverage, and entropy in the total score.
+    pub weights: [f64; 4],
+    pub workers: usize,
+    /// The specials file to append the results to. Defaults to `cache/<NAME>/special.txt`.
+    pub output: Option<String>,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        SearchOptions {
+            attractor: CliffordAttractor::NAME.to_string(),
+            samples: 1_000,
+            top: 10,
+            iterations: 200_000,
+            range: None,
+            weights: [1.0; 4],
+            workers: thread::available_parallelism().map_or(1, |n| n.get()),
+            output: None,
+        }
+    }
+}
+
+impl SearchOptions {
+    /// Build the search options from command line arguments like `--samples 1000 --top 10`.
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut opts = SearchOptions::default();
+        for (name, value) in parse_flags(args)? {
+            let n = name.as_str();
+            match n {
+                "attractor" => opts.attractor = value,
+                "samples" => opts.samples = parse(n, &value)?,
+                "top" => opts.top = parse(n, &value)?,
+                "iterations" => opts.iterations = parse(n, &value)?,
+                "range" => opts.range = Some(parse(n, &value)?),
+                "weights" => {
+                    let weights: Vec<f64> = value
+                        .split(',')
+                        .map(|w| parse(n, w.trim()))
+                        .collect::<Result<_, _>>()?;
+                    opts.weights = weights
+                        .try_into()
+                        .map_err(|_| "`--weights` needs exactly 4 values".to_string())?;
+                }
+                "workers" => opts.workers = parse(n, &value)?,
+                "output" => opts.output = Some(value),
+                _ => return Err(format!("Unknown option `--{}`", name)),
+            }
+        }
+        if opts.workers == 0 {
+            return Err("The number of workers must be larger than 0".to_string());
+        }
+        Ok(opts)
+    }
+
+    /// Search the attractor named by `attractor`.
+    pub fn run(&self) -> Result<(), String> {
+        match self.attractor.as_str() {
+            "clifford" => self.search::<CliffordAttractor>(self.range.unwrap_or(4.0), 0.0, None),
+            "dejong" => self.search::<DeJongAttractor>(self.range.unwrap_or(4.0), 0.0, None),
+            // Sprott attractors are sampled on a grid of 0.1 so that they have a letter code
+            "sprott" => self.search::<SprottAttractor>(self.range.unwrap_or(1.2), 0.1, None),
+            "sprottthreed" => {
+                self.search::<Sprott3dAttractor>(self.range.unwrap_or(1.2), 0.1, None)
+            }
+            "lorenz" => self.search::<LorenzAttractor>(0.0, 0.0, Some(Lorenz::DEFAULT_PARAMS)),
+            "rossler" => self.search::<RosslerAttractor>(0.0, 0.0, Some(Rossler::DEFAULT_PARAMS)),
+            "arneodo" => self.search::<ArneodoAttractor>(0.0, 0.0, Some(Arneodo::DEFAULT_PARAMS)),
+            "burkeshaw" => {
+                self.search::<BurkeShawAttractor>(0.0, 0.0, Some(BurkeShaw::DEFAULT_PARAMS))
+            }
+            "yuwang" => self.search::<YuWangAttractor>(0.0, 0.0, Some(YuWang::DEFAULT_PARAMS)),
+            "thomas" => self.search::<ThomasAttractor>(0.0, 0.0, Some(Thomas::DEFAULT_PARAMS)),
+            "aizawa" => self.search::<AizawaAttractor>(0.0, 0.0, Some(Aizawa::DEFAULT_PARAMS)),
+            _ => Err(format!("Unknown attractor `{}`", self.attractor)),
+        }
+    }
+
+    /// Sample `samples` random parameter sets, score them in parallel, and append the best `top`
+    /// to the specials file.
+    ///
+    /// Parameters are sampled uniformly from [-range, range] (rounded to the nearest `grid` if
+    /// `grid` isn't zero), or within 50% of `centre` if it's given.
+    fn search<A: Attractor>(
+        &self,
+        range: f64,
+        grid: f64,
+        centre: Option<&[f64]>,
+    ) -> Result<(), String> {
+        let output = self
+            .output
+            .clone()
+            .unwrap_or_else(|| format!("cache/{}/special.txt", A::NAME));
+        // Don't add parameters which are already special
+        let existing = if Path::new(&output).exists() {
+            read_specials(&output).map_err(|e| e.to_string())?
+        } else {
+            vec![]
+        };
+        println!(
+            "Searching {} random {} attractors with {} workers",
+            self.samples,
+            A::NAME,
+            self.workers
+        );
+
+        let next = AtomicUsize::new(0);
+        let found: Mutex<Vec<(Vec<f64>, Score)>> = Mutex::new(vec![]);
+        thread::scope(|s| {
+            for _ in 0..self.workers {
+                s.spawn(|| {
+                    let mut rng = rand::thread_rng();
+                    loop {
+                        let sample = next.fetch_add(1, Ordering::Relaxed);
+                        if sample >= self.samples {
+                            break;
+                        }
+                        if sample > 0 && sample.is_multiple_of(1_000) {
+                            let found = found.lock().expect("A worker panicked").len();
+                            println!("Tried {} samples, {} were chaotic", sample, found);
+                        }
+                        let params: Vec<f64> = (0..A::NUM_PARAMETERS as usize)
+                            .map(|i| match centre {
+                                Some(centre) => centre[i] * rng.gen_range(0.5..1.5),
+                                // Dividing by the number of steps per unit gives the closest float
+                                // to each grid value, like 0.3 instead of 0.30000000000000004
+                                None if grid > 0.0 => {
+                                    (rng.gen_range(-range..=range) / grid).round() / (1.0 / grid)
+                                }
+                                None => rng.gen_range(-range..range),
+                            })
+                            .collect();
+                        if existing.contains(&params) {
+                            continue;
+                        }
+                        let Ok(mut attractor) = A::new(params.clone()) else {
+                            continue;
+                        };
+                        if let Some(score) = score(&mut attractor, self.iterations, &self.weights) {
+                            found
+                                .lock()
+                                .expect("A worker panicked")
+                                .push((params, score));
+                        }
+                    }
+                });
+            }
+        });
+
+        let mut found = found.into_inner().expect("A worker panicked");
+        found.sort_by(|a, b| b.1.total.total_cmp(&a.1.total));
+        // Random parameters on a grid can come up more than once
+        let mut seen = HashSet::new();
+        found.retain(|(params, _)| seen.insert(format!("{:?}", params)));
+        found.truncate(self.top);
+
+        // Parameters on a grid only need as many decimal places as the grid has
+        let decimals = (grid > 0.0).then(|| (-grid.log10()).ceil().max(0.0) as usize);
+        if let Some(parent) = Path::new(&output).parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Couldn't create {}: {}", parent.display(), e))?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&output)
+            .map_err(|e| format!("Couldn't open {}: {}", output, e))?;
+        for (params, score) in found.iter() {
+            let attractor = A::new(params.clone()).map_err(|e| e.to_string())?;
+            let code = attractor.code();
+            let line = special_line(
+                &attractor.param_names(),
+                params,
+                decimals,
+                code.as_deref(),
+                score,
+            );
+            println!("{}", line);
+            writeln!(file, "{}", line)
+                .map_err(|e| format!("Couldn't write to {}: {}", output, e))?;
+        }
+        println!("Saved the best {} attractors to {}", found.len(), output);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chaotic_attractors_score_better_than_dots() {
+        let weights = [1.0; 4];
+        let mut chaotic = CliffordAttractor::new(vec![1.7, 1.7, 0.6, 1.2]).unwrap();
+        let score = score(&mut chaotic, 100_000, &weights).unwrap();
+        assert!(0.2 < score.total && score.total <= 1.0, "{:?}", score);
+        assert!(score.lyapunov > 0.0 && score.dimension > 1.0, "{:?}", score);
+
+        let mut dot = CliffordAttractor::new(vec![0.1, 0.1, 0.1, 0.1]).unwrap();
+        assert_eq!(super::score(&mut dot, 50_000, &weights), None);
+    }
+
+    #[test]
+    fn special_lines_can_be_read_back() {
+        let dir = std::env::temp_dir().join("attractors_search_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let filename = dir.join("special.txt");
+        let score = Score::new(0.4, 1.6, 0.3, 0.8, &[1.0; 4]);
+        let clifford = CliffordAttractor::new(vec![1.5, -2.0, 0.25, 3.0]).unwrap();
+        let line = special_line(
+            &clifford.param_names(),
+            &clifford.params(),
+            None,
+            None,
+            &score,
+        );
+        assert!(line.starts_with("a=1.5,b=-2,c=0.25,d=3 #"));
+        std::fs::write(&filename, line).unwrap();
+        let specials = read_specials(filename.to_str().unwrap()).unwrap();
+        assert_eq!(specials, vec![vec![1.5, -2.0, 0.25, 3.0]]);
+
+        // Sprott attractors are written with their own names, as precisely as their grid, and
+        // with their codes (which are ignored when read back)
+        let mut params = SprottAttractor::from_code("FIRCDERRPVLD").unwrap().params();
+        params[2] = 0.1 + 0.2;
+        let sprott = SprottAttractor::new(params.clone()).unwrap();
+        let code = sprott.code();
+        let line = special_line(
+            &sprott.param_names(),
+            &params,
+            Some(1),
+            code.as_deref(),
+            &score,
+        );
+        assert!(line.starts_with("a1=-0.7,a2=-0.4,a3=0.3,"));
+        assert!(line.contains(",a12=-0.9 # code=FIPCDERRPVLD score="));
+        std::fs::write(&filename, line).unwrap();
+        let specials = read_specials(filename.to_str().unwrap()).unwrap();
+        let gridded = SprottAttractor::from_code("FIPCDERRPVLD").unwrap();
+        assert_eq!(specials, vec![gridded.params()]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
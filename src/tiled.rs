@@ -0,0 +1,298 @@
+//! Rendering images which are too large to fit in memory, like A0 prints at 600 DPI.
+//!
+//! An A0 print at 600 DPI is 19866 by 28087 pixels, so a `Vec<f64>` of densities takes about
+//! 4.5 GB before the image or the attractor's history are even counted. Instead of keeping every
+//! position in memory, `TiledRender` steps the attractor a chunk at a time and forgets each chunk
+//! once it has been counted. The image is split into tiles of full width rows, and only one
+//! tile's counts are kept at once (as integers, which are half the size of an `f64`):
+//! 1. The attractor is stepped once to find its bounds (unless the camera has a viewport).
+//! 2. Each tile is counted, to find the largest count and how often each count appears, which
+//!    the tone map needs before it can colour any pixel.
+//! 3. Each tile is counted again, coloured, and its rows are streamed out (usually into a PNG
+//!    encoder, see `print::save_png`).
+//!
+//! Attractors are deterministic, so every pass sees exactly the same positions. This trades
+//! time for memory: the attractor is stepped `1 + 2 * num_tiles` times (less one, since the
+//! first tile's counts are kept from step 2).
+use std::collections::BTreeMap;
+
+use crate::attractors::{include_in_bounds, Attractor, Bounds, Histogram};
+use crate::camera::{include_in_box, Camera};
+use crate::colour::{u32_to_argb, ColourScheme};
+use crate::sampling::{Splat, SPLAT_SCALE};
+use crate::tone::MAX_SAMPLES;
+
+/// The number of positions kept in memory at once while stepping the attractor.
+const CHUNK_SIZE: usize = 1_000_000;
+/// How many pixels are in each tile when the number of rows isn't given. Each pixel of a tile
+/// takes about 23 bytes while it's being coloured, so this uses a little under 1 GB.
+const TILE_PIXELS: usize = 40_000_000;
+
+/// Everything needed to render an attractor a few rows at a time.
+#[derive(Debug, Clone)]
+pub struct TiledRender<'a> {
+    pub width: usize,
+    pub height: usize,
+    /// The number of positions to calculate.
+    pub iterations: usize,
+    /// The fraction of the image left empty on each side.
+    pub border: f64,
+    pub colours: &'a ColourScheme,
+    /// Only used if it isn't the default camera, just like `Camera::get_densities`.
+    pub camera: &'a Camera,
+    pub splat: Splat,
+    /// The number of rows in each tile, or `None` to pick a number that fits in about 1 GB.
+    pub tile_rows: Option<usize>,
+}
+
+impl TiledRender<'_> {
+    /// The number of rows in each tile.
+    fn rows_per_tile(&self) -> usize {
+        self.tile_rows
+            .unwrap_or(TILE_PIXELS / self.width)
+            .clamp(1, self.height)
+    }
+
+    /// Render a fresh copy of `attractor`, giving `write` the RGB bytes of a few whole rows at a
+    /// time from the top of the image to the bottom. Stops at the first error from `write`.
+    /// Returns the bounds which were drawn, which are the camera's screen bounds if it isn't the
+    /// default camera.
+    ///
+    /// Every pass over the attractor takes a while, so `progress` is called as each one starts.
+    pub fn write_rows<A: Attractor>(
+        &self,
+        attractor: &A,
+        mut progress: impl FnMut(),
+        mut write: impl FnMut(&[u8]) -> Result<(), String>,
+    ) -> Result<Bounds, String> {
+        let rows = self.rows_per_tile();
+        let num_tiles = self.height.div_ceil(rows);
+        let (mapping, camera) = self.fit(attractor, &mut progress);
+        let tile = |i: usize| {
+            let start = i * rows;
+            self.count_tile(
+                attractor,
+                &mapping,
+                &camera,
+                start,
+                rows.min(self.height - start),
+            )
+        };
+
+        // Count every tile once to find the largest count and how often each count appears.
+        // This goes backwards so that the first tile is the one left over for writing.
+        let mut frequencies: BTreeMap<u32, u64> = BTreeMap::new();
+        let mut first_tile = vec![];
+        for i in (0..num_tiles).rev() {
+            progress();
+            let counts = tile(i);
+            for count in counts.iter().filter(|c| **c > 0) {
+                *frequencies.entry(*count).or_insert(0) += 1;
+            }
+            if i == 0 {
+                first_tile = counts;
+            }
+        }
+        let max = frequencies.keys().next_back().copied().unwrap_or(1) as f64;
+        let sample = sorted_sample(&frequencies, max);
+
+        let colour_map = self.colours.colour_map();
+        for i in 0..num_tiles {
+            let counts = if i == 0 {
+                std::mem::take(&mut first_tile)
+            } else {
+                progress();
+                tile(i)
+            };
+            let densities: Vec<f64> = counts.iter().map(|c| *c as f64 / max).collect();
+            drop(counts);
+            let mut bytes = Vec::with_capacity(densities.len() * 3);
+            for tone in self.colours.tone.apply_sampled(&sample, &densities) {
+                let (_a, r, g, b) = u32_to_argb(colour_map.get(tone));
+                bytes.extend_from_slice(&[r, g, b]);
+            }
+            write(&bytes)?;
+        }
+        Ok(mapping.bounds)
+    }
+
+    /// Step through every position once to find where the attractor is (unless there's a
+    /// viewport), returning a histogram which maps positions onto the pixels of the whole image,
+    /// and the camera fitted to the attractor (if the camera is used at all). `progress` is called
+    /// if the attractor needs stepping.
+    fn fit<A: Attractor>(
+        &self,
+        attractor: &A,
+        progress: &mut impl FnMut(),
+    ) -> (Histogram, Option<Camera>) {
+        if let (true, Some(viewport)) = (self.camera.is_default(), self.camera.viewport) {
+            // There's nothing to find, since the viewport is exactly what gets drawn
+            return (self.mapping(viewport), None);
+        }
+        progress();
+        if self.camera.is_default() {
+            let mut bounds: Option<Bounds> = None;
+            for_each_chunk(attractor, self.iterations, |attractor, _chunk| {
+                let chunk_bounds = attractor.bounds();
+                match &mut bounds {
+                    Some(bounds) => {
+                        include_in_bounds(bounds, &[chunk_bounds.xmin, chunk_bounds.ymin]);
+                        include_in_bounds(bounds, &[chunk_bounds.xmax, chunk_bounds.ymax]);
+                    }
+                    None => bounds = Some(chunk_bounds),
+                }
+            });
+            let bounds = bounds.unwrap_or_else(|| attractor.bounds());
+            let mapping = self.mapping(bounds);
+            (mapping, None)
+        } else {
+            let mut min = [f64::INFINITY; 3];
+            let mut max = [f64::NEG_INFINITY; 3];
+            for_each_chunk(attractor, self.iterations, |_attractor, chunk| {
+                include_in_box(&mut min, &mut max, chunk)
+            });
+            let mut camera = self.camera.clone();
+            camera.fit_box(min, max);
+            let mapping = self.mapping(camera.screen_bounds(self.width, self.height));
+            (mapping, Some(camera))
+        }
+    }
+
+    /// A histogram which maps positions inside `bounds` onto the pixels of the whole image.
+    fn mapping(&self, bounds: Bounds) -> Histogram {
+        Histogram::without_counts(self.width, self.height, bounds, self.border, self.splat)
+    }
+
+    /// Step through every position, counting those which land in the `rows` rows starting from
+    /// the row `start`.
+    fn count_tile<A: Attractor>(
+        &self,
+        attractor: &A,
+        mapping: &Histogram,
+        camera: &Option<Camera>,
+        start: usize,
+        rows: usize,
+    ) -> Vec<u32> {
+        let mut counts = vec![0u32; self.width * rows];
+        let first = start * self.width;
+        let last = first + counts.len();
+        for_each_chunk(attractor, self.iterations, |_attractor, chunk| {
+            for pos in chunk {
+                let weights = match camera {
+                    Some(camera) => mapping.splat_weights(&camera.project(pos)),
+                    None => mapping.splat_weights(pos),
+                };
+                for (i, weight) in weights.into_iter().flatten() {
+                    if weight > 0.0 && (first..last).contains(&i) {
+                        let count = &mut counts[i - first];
+                        *count = count.saturating_add((weight * SPLAT_SCALE).round() as u32);
+                    }
+                }
+            }
+        });
+        counts
+    }
+}
+
+/// Step a fresh copy of `attractor` until it has visited `iterations` positions, calling `f`
+/// with the attractor and each chunk of new positions. Only one chunk is kept in memory at once.
+fn for_each_chunk<A: Attractor>(
+    attractor: &A,
+    iterations: usize,
+    mut f: impl FnMut(&A, &[Vec<f64>]),
+) {
+    let mut attractor = attractor.empty_copy();
+    // A fresh attractor's history already has its starting position, just like in memory
+    let start = attractor.history().len().min(iterations);
+    if start > 0 {
+        f(&attractor, &attractor.history()[..start]);
+    }
+    let mut visited = start;
+    while visited < iterations {
+        let before = attractor.history().len();
+        let new = (iterations - visited).min(CHUNK_SIZE);
+        attractor.step(new);
+        f(&attractor, &attractor.history()[before..]);
+        visited += new;
+        let history = attractor.history();
+        // Keep the last position so the attractor carries on from where it left off
+        let last = history[history.len() - 1].clone();
+        attractor.set_history(vec![last]);
+    }
+}
+
+/// Up to `MAX_SAMPLES` non-empty densities, evenly spread through every count in `frequencies`
+/// (which maps each count to the number of pixels with that count) and sorted from smallest to
+/// largest. This is the same as `ToneMap` would sample if every density was in memory at once.
+fn sorted_sample(frequencies: &BTreeMap<u32, u64>, max: f64) -> Vec<f64> {
+    let total: u64 = frequencies.values().sum();
+    let num_samples = total.min(MAX_SAMPLES as u64);
+    let mut sample = Vec::with_capacity(num_samples as usize);
+    let mut counts = frequencies.iter();
+    let mut seen = 0;
+    let mut current = None;
+    for k in 0..num_samples {
+        // The rank of this sample among every non-empty pixel
+        let rank = k * total / num_samples;
+        while seen <= rank {
+            let (count, freq) = counts.next().expect("The rank is less than the total");
+            current = Some(*count);
+            seen += freq;
+        }
+        sample.push(current.expect("At least one count has been seen") as f64 / max);
+    }
+    sample
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attractors::CliffordAttractor;
+    use crate::colour::densities_to_image;
+    use crate::print::save_png;
+
+    #[test]
+    fn tiles_match_rendering_all_at_once() {
+        let params = vec![1.7, 1.7, 0.6, 1.2];
+        let colours = ColourScheme::default();
+        let (width, height, iterations) = (30, 20, 20_000);
+        let output = std::env::temp_dir().join("attractors_tiled_test.png");
+        let output = output.to_str().unwrap();
+        let tiled = TiledRender {
+            width,
+            height,
+            iterations,
+            border: 0.05,
+            colours: &colours,
+            camera: &Camera::default(),
+            splat: Splat::Nearest,
+            tile_rows: Some(7),
+        };
+        let clifford = CliffordAttractor::new(params.clone()).unwrap();
+        save_png(output, width, height, None, |write| {
+            tiled.write_rows(&clifford, || {}, write)?;
+            Ok(vec![])
+        })
+        .unwrap();
+        let tiled = image::open(output).unwrap().into_rgb8();
+        std::fs::remove_file(output).unwrap();
+
+        let mut clifford = CliffordAttractor::new(params).unwrap();
+        while clifford.history().len() < iterations {
+            clifford.step(iterations - clifford.history().len());
+        }
+        let densities = clifford.get_densities_with_border(width, height, 0.05);
+        let expected = densities_to_image(&densities, width, height, &colours);
+        assert_eq!(tiled, expected);
+    }
+
+    #[test]
+    fn samples_follow_the_frequencies() {
+        // Three pixels with a count of 1, and one with a count of 4
+        let frequencies = BTreeMap::from([(1, 3), (4, 1)]);
+        assert_eq!(
+            sorted_sample(&frequencies, 4.0),
+            vec![0.25, 0.25, 0.25, 1.0]
+        );
+    }
+}
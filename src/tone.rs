@@ -0,0 +1,276 @@
+//! Tone curves, which decide how bright each density is drawn.
+//!
+//! Densities are the number of positions which landed on a pixel divided by the largest number
+//! which landed on any pixel, so almost every density is tiny and would be far too dark to see if
+//! it was used as the lightness directly. A `ToneMap` first rescales the densities so that some
+//! percentiles of the non-empty pixels map to 0.0 and 1.0 (clipping anything outside them), then
+//! brightens them with a `ToneCurve`. The result is called the tone of each pixel, and is in
+//! [0.0, 1.0].
+//!
+//! The same `ToneMap` is used by the live window and by every export, so that the preview looks
+//! like the final image. Images with many more pixels spread the same positions more thinly,
+//! which makes them darker with the `Gamma`, `Log`, and `Asinh` curves (clipping the top
+//! percentile or so helps with this), while `Equalise` only depends on which pixels are denser
+//! than which, so it looks about the same at any size. That's why it's the default: the small
+//! preview in the live window then looks like a huge print of the same attractor.
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// The number of non-empty densities sampled to find the percentiles and the histogram used by
+/// `ToneCurve::Equalise`, since sorting every density of a large image would be slow.
+pub const MAX_SAMPLES: usize = 100_000;
+
+/// A curve which brightens densities in [0.0, 1.0] into tones in [0.0, 1.0].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ToneCurve {
+    /// `density ^ exponent`. Smaller exponents make sparse areas brighter.
+    Gamma(f64),
+    /// `ln(1 + scale * density) / ln(1 + scale)`.
+    Log(f64),
+    /// `asinh(scale * density) / asinh(scale)`, which is linear for small densities and
+    /// logarithmic for large ones.
+    Asinh(f64),
+    /// Histogram equalisation, so that every tone is used by the same number of pixels.
+    Equalise,
+}
+
+impl ToneCurve {
+    /// One of each kind of curve with sensible defaults, in the order they're cycled through in
+    /// the live window.
+    pub const ALL: [ToneCurve; 4] = [
+        ToneCurve::Gamma(0.3),
+        ToneCurve::Log(1000.0),
+        ToneCurve::Asinh(100.0),
+        ToneCurve::Equalise,
+    ];
+
+    /// The next kind of curve after this one in `ToneCurve::ALL`.
+    pub fn next(&self) -> ToneCurve {
+        let idx = ToneCurve::ALL
+            .iter()
+            .position(|c| std::mem::discriminant(c) == std::mem::discriminant(self))
+            .unwrap_or(0);
+        ToneCurve::ALL[(idx + 1) % ToneCurve::ALL.len()]
+    }
+}
+
+impl Display for ToneCurve {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ToneCurve::Gamma(exponent) => write!(f, "gamma:{}", exponent),
+            ToneCurve::Log(scale) => write!(f, "log:{}", scale),
+            ToneCurve::Asinh(scale) => write!(f, "asinh:{}", scale),
+            ToneCurve::Equalise => write!(f, "equalise"),
+        }
+    }
+}
+
+impl FromStr for ToneCurve {
+    type Err = String;
+
+    /// Parse a curve like `gamma:0.3`, `log:1000`, `asinh:100`, or `equalise`. The number after
+    /// the `:` can be left out to use the default.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = match s.split_once(':') {
+            Some((name, value)) => (name, Some(value)),
+            None => (s, None),
+        };
+        let value = |default: f64| match value {
+            Some(v) => v
+                .parse::<f64>()
+                .ok()
+                .filter(|v| *v > 0.0)
+                .ok_or_else(|| format!("`{}` must be a number larger than 0", v)),
+            None => Ok(default),
+        };
+        match name {
+            "gamma" => Ok(ToneCurve::Gamma(value(0.3)?)),
+            "log" => Ok(ToneCurve::Log(value(1000.0)?)),
+            "asinh" => Ok(ToneCurve::Asinh(value(100.0)?)),
+            "equalise" | "equalize" => Ok(ToneCurve::Equalise),
+            _ => Err(format!(
+                "Unknown tone curve `{}`, expected gamma, log, asinh, or equalise",
+                name
+            )),
+        }
+    }
+}
+
+/// Converts a grid of densities into a grid of tones. See the module docs for details.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ToneMap {
+    pub curve: ToneCurve,
+    /// The percentiles (in [0, 100]) of the non-empty densities which become the darkest and
+    /// brightest tones. Anything outside them is clipped. A lower percentile of 0 clips nothing,
+    /// and maps a density of 0.0 to the darkest tone.
+    pub clip: (f64, f64),
+}
+
+impl Default for ToneMap {
+    fn default() -> Self {
+        ToneMap {
+            curve: ToneCurve::Equalise,
+            clip: (0.0, 99.9),
+        }
+    }
+}
+
+impl Display for ToneMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "ToneMap {} (clipped to percentiles {}-{})",
+            self.curve, self.clip.0, self.clip.1
+        )
+    }
+}
+
+impl ToneMap {
+    /// Convert every density into a tone in [0.0, 1.0]. Empty pixels always have a tone of 0.0.
+    pub fn apply(&self, densities: &[f64]) -> Vec<f64> {
+        self.apply_sampled(&sorted_sample(densities), densities)
+    }
+
+    /// The same as `apply`, but with the percentiles (and the histogram for
+    /// `ToneCurve::Equalise`) taken from `sorted`, a sorted sample of the non-empty densities.
+    /// This lets an image be tone mapped a few rows at a time, as long as `sorted` is sampled
+    /// from the whole image.
+    pub fn apply_sampled(&self, sorted: &[f64], densities: &[f64]) -> Vec<f64> {
+        if sorted.is_empty() {
+            return vec![0.0; densities.len()];
+        }
+        // Without any clipping at the bottom, the smallest densities should stay just above black
+        // rather than becoming black themselves
+        let low = if self.clip.0 > 0.0 {
+            percentile(sorted, self.clip.0)
+        } else {
+            0.0
+        };
+        let high = percentile(sorted, self.clip.1);
+        let range = (high - low).max(f64::MIN_POSITIVE);
+        // The sampled densities between the clip points, used for histogram equalisation
+        let (lo, hi) = (
+            sorted.partition_point(|d| *d < low),
+            sorted.partition_point(|d| *d <= high),
+        );
+        densities
+            .iter()
+            .map(|&density| {
+                if density <= 0.0 {
+                    return 0.0;
+                }
+                match self.curve {
+                    ToneCurve::Gamma(exponent) => {
+                        ((density - low) / range).clamp(0.0, 1.0).powf(exponent)
+                    }
+                    ToneCurve::Log(scale) => {
+                        let val = ((density - low) / range).clamp(0.0, 1.0);
+                        (scale * val).ln_1p() / scale.ln_1p()
+                    }
+                    ToneCurve::Asinh(scale) => {
+                        let val = ((density - low) / range).clamp(0.0, 1.0);
+                        (scale * val).asinh() / scale.asinh()
+                    }
+                    ToneCurve::Equalise => {
+                        // The fraction of the sampled densities which are smaller than this one
+                        let rank = sorted.partition_point(|d| *d <= density);
+                        (rank.saturating_sub(lo) as f64 / (hi - lo).max(1) as f64).clamp(0.0, 1.0)
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+/// Up to `MAX_SAMPLES` of the non-empty densities, evenly spread out through `densities`, sorted
+/// from smallest to largest.
+fn sorted_sample(densities: &[f64]) -> Vec<f64> {
+    let non_empty = densities.iter().filter(|d| **d > 0.0).count();
+    let stride = non_empty.div_ceil(MAX_SAMPLES).max(1);
+    let mut sample: Vec<f64> = densities
+        .iter()
+        .filter(|d| **d > 0.0)
+        .step_by(stride)
+        .copied()
+        .collect();
+    sample.sort_by(|a, b| a.total_cmp(b));
+    sample
+}
+
+/// The `p`th percentile (with `p` in [0, 100]) of the already sorted `sorted`.
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let idx = (p / 100.0 * (sorted.len() - 1) as f64).round() as usize;
+    sorted[idx.min(sorted.len() - 1)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tone_curves_are_monotonic_and_in_range() {
+        let densities: Vec<f64> = (0..=1000).map(|i| (i as f64 / 1000.0).powi(4)).collect();
+        for curve in ToneCurve::ALL {
+            let tones = ToneMap {
+                curve,
+                clip: (0.0, 100.0),
+            }
+            .apply(&densities);
+            assert_eq!(tones[0], 0.0, "{}", curve);
+            assert!((tones[1000] - 1.0).abs() < 1e-9, "{}", curve);
+            assert!(tones.windows(2).all(|w| w[0] <= w[1]), "{}", curve);
+        }
+    }
+
+    #[test]
+    fn clipping_saturates_the_brightest_pixels() {
+        let mut densities = vec![0.01; 99];
+        densities.push(1.0);
+        let tones = ToneMap {
+            curve: ToneCurve::Gamma(1.0),
+            clip: (0.0, 90.0),
+        }
+        .apply(&densities);
+        // Without the single bright pixel, every other pixel is the brightest
+        assert!(tones.iter().all(|t| *t == 1.0));
+    }
+
+    #[test]
+    fn the_default_looks_the_same_at_any_resolution() {
+        use crate::attractors::{Attractor, CliffordAttractor};
+        use crate::camera::Camera;
+        use crate::sampling::Sampling;
+        // The 10th, 50th, and 90th percentiles of the tones of the non-empty pixels
+        let percentiles = |size: usize| {
+            let mut clifford = CliffordAttractor::new(vec![-1.4, 1.6, 1.0, 0.7]).unwrap();
+            clifford.step(1_000_000);
+            let densities = Camera::default().get_densities(
+                &mut clifford,
+                size,
+                size,
+                0.05,
+                &Sampling::default(),
+            );
+            let mut tones: Vec<f64> = ToneMap::default()
+                .apply(&densities)
+                .into_iter()
+                .filter(|t| *t > 0.0)
+                .collect();
+            tones.sort_by(|a, b| a.total_cmp(b));
+            [10.0, 50.0, 90.0].map(|p| percentile(&tones, p))
+        };
+        let (small, large) = (percentiles(100), percentiles(400));
+        for (s, l) in small.iter().zip(&large) {
+            assert!((s - l).abs() < 0.02, "{:?} vs {:?}", small, large);
+        }
+    }
+
+    #[test]
+    fn parse_tone_curves() {
+        assert_eq!("gamma:0.1".parse(), Ok(ToneCurve::Gamma(0.1)));
+        assert_eq!("log".parse(), Ok(ToneCurve::Log(1000.0)));
+        assert_eq!("equalise".parse(), Ok(ToneCurve::Equalise));
+        assert!("gamma:-1".parse::<ToneCurve>().is_err());
+        assert!("linear".parse::<ToneCurve>().is_err());
+    }
+}
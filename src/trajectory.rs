@@ -0,0 +1,572 @@
+/// A compact binary format for saving the trajectory of an attractor.
+///
+/// The text format written by `Attractor::to_file` is easy to read, but takes ~40 bytes per point,
+/// which adds up to gigabytes for the 40 million point histories used when printing. This format
+/// stores each coordinate as a little-endian `f32` or `f64`, optionally deflate-compressed, and can
+/// be written and read one point at a time so that the whole history never has to be in memory.
+///
+/// The layout of the file is:
+/// ```
+/// b"ATTR"                                     4 bytes of magic
+/// <VERSION>                                   u8
+/// <PRECISION>                                 u8, either 4 (f32) or 8 (f64)
+/// <COMPRESSED>                                u8, either 0 or 1
+/// <NAME_LENGTH> <NAME>                        u8, then that many bytes of ASCII
+/// <NUM_PARAMETERS> <NUM_DIMENSIONS>           u8, u8
+/// <PARAMETER_1> ... <PARAMETER_n>             f64 each
+/// <CODE_LENGTH> <CODE>                        u8, then that many bytes of ASCII (0 if no code)
+/// <NUM_POINTS> <NUM_BYTES> <BYTES>            u32, u32, then a chunk of packed coordinates
+/// <NUM_POINTS> <NUM_BYTES> <BYTES>
+/// ...
+/// ```
+/// Where every number is little-endian, and the code is missing from version 1 files. The chunks
+/// continue until the end of the file, and each chunk holds the coordinates
+/// `x0, y0, (z0,) x1, y1, (z1,) ...` for `NUM_POINTS` points.
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+};
+
+use crate::attractors::{saved_attractor_name, Attractor, AttractorError};
+
+/// The first four bytes of every binary trajectory file.
+const MAGIC: &[u8; 4] = b"ATTR";
+/// The version of the format written by `TrajectoryWriter`. Files from version 1 onwards can be
+/// read.
+const VERSION: u8 = 2;
+/// The number of points buffered up before they get written out as one chunk.
+const CHUNK_POINTS: usize = 65_536;
+
+/// How many bytes are used to store each coordinate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Precision {
+    /// 4 bytes per coordinate, which is plenty for rendering an image.
+    F32,
+    /// 8 bytes per coordinate, which keeps the coordinates exactly as they were calculated.
+    F64,
+}
+
+impl Precision {
+    fn num_bytes(&self) -> u8 {
+        match self {
+            Precision::F32 => 4,
+            Precision::F64 => 8,
+        }
+    }
+
+    /// `coord` as it will be read back after being saved with this precision.
+    pub fn round(&self, coord: f64) -> f64 {
+        match self {
+            Precision::F32 => coord as f32 as f64,
+            Precision::F64 => coord,
+        }
+    }
+}
+
+/// Everything stored at the start of a binary trajectory file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+    /// The `Attractor::NAME` of the attractor which made the trajectory.
+    pub name: String,
+    /// The parameters of the attractor which made the trajectory.
+    pub params: Vec<f64>,
+    /// The `Attractor::code` of the attractor which made the trajectory, if it has one.
+    pub code: Option<String>,
+    /// The number of coordinates in each point.
+    pub dimensionality: u8,
+    pub precision: Precision,
+    /// If true, each chunk of points is deflate-compressed.
+    pub compressed: bool,
+}
+
+/// Writes points to a binary trajectory file, one chunk at a time.
+///
+/// `finish` must be called once all the points have been written, otherwise the last (partial)
+/// chunk will be lost.
+pub struct TrajectoryWriter<W: Write> {
+    writer: W,
+    header: Header,
+    /// Points which haven't been written out yet, already packed into bytes.
+    buffer: Vec<u8>,
+    num_buffered: usize,
+}
+
+impl<W: Write> TrajectoryWriter<W> {
+    /// Write the header to `writer` and return a `TrajectoryWriter` ready to accept points.
+    pub fn new(mut writer: W, header: Header) -> Result<Self, AttractorError> {
+        let code = header.code.as_deref().unwrap_or_default();
+        if [header.name.len(), header.params.len(), code.len()]
+            .iter()
+            .any(|len| *len > u8::MAX as usize)
+        {
+            return Err(AttractorError::InvalidBinaryFile(
+                "the name, parameters, and code must each have fewer than 256 items".to_string(),
+            ));
+        }
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[
+            VERSION,
+            header.precision.num_bytes(),
+            header.compressed as u8,
+            header.name.len() as u8,
+        ])?;
+        writer.write_all(header.name.as_bytes())?;
+        writer.write_all(&[header.params.len() as u8, header.dimensionality])?;
+        for param in header.params.iter() {
+            writer.write_all(&param.to_le_bytes())?;
+        }
+        writer.write_all(&[code.len() as u8])?;
+        writer.write_all(code.as_bytes())?;
+        Ok(TrajectoryWriter {
+            writer,
+            header,
+            buffer: vec![],
+            num_buffered: 0,
+        })
+    }
+
+    /// Add one point to the trajectory. Points are buffered and written out in chunks.
+    pub fn write_point(&mut self, pos: &[f64]) -> Result<(), AttractorError> {
+        if pos.len() != self.header.dimensionality as usize {
+            return Err(AttractorError::InvalidBinaryFile(format!(
+                "expected {} coordinates but got {}",
+                self.header.dimensionality,
+                pos.len()
+            )));
+        }
+        for coord in pos {
+            match self.header.precision {
+                Precision::F32 => self.buffer.extend((*coord as f32).to_le_bytes()),
+                Precision::F64 => self.buffer.extend(coord.to_le_bytes()),
+            }
+        }
+        self.num_buffered += 1;
+        if self.num_buffered >= CHUNK_POINTS {
+            self.flush_chunk()?;
+        }
+        Ok(())
+    }
+
+    /// Write every buffered point out as one chunk.
+    fn flush_chunk(&mut self) -> Result<(), AttractorError> {
+        if self.num_buffered == 0 {
+            return Ok(());
+        }
+        let bytes = if self.header.compressed {
+            miniz_oxide::deflate::compress_to_vec(&self.buffer, 6)
+        } else {
+            std::mem::take(&mut self.buffer)
+        };
+        self.writer
+            .write_all(&(self.num_buffered as u32).to_le_bytes())?;
+        self.writer.write_all(&(bytes.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&bytes)?;
+        self.buffer.clear();
+        self.num_buffered = 0;
+        Ok(())
+    }
+
+    /// Write out any buffered points and return the underlying writer.
+    pub fn finish(mut self) -> Result<W, AttractorError> {
+        self.flush_chunk()?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Reads points from a binary trajectory file, one chunk at a time.
+///
+/// The points are available by iterating over the reader.
+pub struct TrajectoryReader<R: Read> {
+    reader: R,
+    pub header: Header,
+    /// The points of the current chunk which haven't been returned yet, in reverse order.
+    chunk: Vec<Vec<f64>>,
+}
+
+impl<R: Read> TrajectoryReader<R> {
+    /// Read the header from `reader` and return a `TrajectoryReader` ready to return points.
+    pub fn new(mut reader: R) -> Result<Self, AttractorError> {
+        let invalid = |reason: &str| AttractorError::InvalidBinaryFile(reason.to_string());
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("this isn't a binary trajectory file"));
+        }
+        let mut fields = [0u8; 4];
+        reader.read_exact(&mut fields)?;
+        let [version, precision, compressed, name_len] = fields;
+        if !(1..=VERSION).contains(&version) {
+            return Err(AttractorError::InvalidBinaryFile(format!(
+                "version {} isn't supported, only versions 1 to {} are",
+                version, VERSION
+            )));
+        }
+        let precision = match precision {
+            4 => Precision::F32,
+            8 => Precision::F64,
+            _ => return Err(invalid("the precision must be 4 or 8 bytes")),
+        };
+        let mut name = vec![0u8; name_len as usize];
+        reader.read_exact(&mut name)?;
+        let name = String::from_utf8(name).map_err(|_| invalid("the name isn't valid UTF-8"))?;
+        let mut counts = [0u8; 2];
+        reader.read_exact(&mut counts)?;
+        let [num_params, dimensionality] = counts;
+        let mut params = vec![];
+        for _ in 0..num_params {
+            let mut bytes = [0u8; 8];
+            reader.read_exact(&mut bytes)?;
+            params.push(f64::from_le_bytes(bytes));
+        }
+        let mut code = None;
+        if version >= 2 {
+            let mut code_len = [0u8; 1];
+            reader.read_exact(&mut code_len)?;
+            let mut bytes = vec![0u8; code_len[0] as usize];
+            reader.read_exact(&mut bytes)?;
+            let text =
+                String::from_utf8(bytes).map_err(|_| invalid("the code isn't valid UTF-8"))?;
+            code = Some(text).filter(|text| !text.is_empty());
+        }
+        Ok(TrajectoryReader {
+            reader,
+            header: Header {
+                name,
+                params,
+                code,
+                dimensionality,
+                precision,
+                compressed: compressed != 0,
+            },
+            chunk: vec![],
+        })
+    }
+
+    /// Read the next chunk of points, returning false if there are no more chunks.
+    fn read_chunk(&mut self) -> Result<bool, AttractorError> {
+        let mut lengths = [0u8; 8];
+        // A clean end of file is only allowed between chunks
+        match self.reader.read(&mut lengths[..1])? {
+            0 => return Ok(false),
+            _ => self.reader.read_exact(&mut lengths[1..])?,
+        }
+        let num_points = u32::from_le_bytes(lengths[..4].try_into().unwrap()) as usize;
+        let num_bytes = u32::from_le_bytes(lengths[4..].try_into().unwrap()) as usize;
+        let coord_size = self.header.precision.num_bytes() as usize;
+        let point_size = coord_size * self.header.dimensionality as usize;
+        if point_size == 0 || num_points > CHUNK_POINTS {
+            return Err(AttractorError::InvalidBinaryFile(format!(
+                "chunks can have at most {} points of at least one coordinate, not {} points of {}",
+                CHUNK_POINTS, num_points, self.header.dimensionality
+            )));
+        }
+        // Check the lengths before allocating anything, so a corrupt file can't ask for gigabytes
+        let expected = num_points * point_size;
+        let max_bytes = match self.header.compressed {
+            // Deflate never grows incompressible data by more than a few bytes per block
+            true => expected + expected / 100 + 1024,
+            false => expected,
+        };
+        if num_bytes > max_bytes || (!self.header.compressed && num_bytes != expected) {
+            return Err(AttractorError::InvalidBinaryFile(format!(
+                "a chunk of {} points can't take up {} bytes",
+                num_points, num_bytes
+            )));
+        }
+        let mut bytes = vec![0u8; num_bytes];
+        self.reader.read_exact(&mut bytes)?;
+        if self.header.compressed {
+            bytes = miniz_oxide::inflate::decompress_to_vec_with_limit(&bytes, expected).map_err(
+                |e| {
+                    AttractorError::InvalidBinaryFile(format!("couldn't decompress chunk: {:?}", e))
+                },
+            )?;
+        }
+
+        if bytes.len() != expected {
+            return Err(AttractorError::InvalidBinaryFile(format!(
+                "chunk should have {} points but has {} bytes",
+                num_points,
+                bytes.len()
+            )));
+        }
+        self.chunk = bytes
+            .chunks_exact(point_size)
+            .rev()
+            .map(|point| {
+                point
+                    .chunks_exact(coord_size)
+                    .map(|c| match self.header.precision {
+                        Precision::F32 => f32::from_le_bytes(c.try_into().unwrap()) as f64,
+                        Precision::F64 => f64::from_le_bytes(c.try_into().unwrap()),
+                    })
+                    .collect()
+            })
+            .collect();
+        Ok(true)
+    }
+}
+
+impl<R: Read> Iterator for TrajectoryReader<R> {
+    type Item = Result<Vec<f64>, AttractorError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Keep reading chunks until one with points is found (chunks can't be empty, but it
+        // doesn't hurt to be careful)
+        while self.chunk.is_empty() {
+            match self.read_chunk() {
+                Ok(true) => continue,
+                Ok(false) => return None,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        self.chunk.pop().map(Ok)
+    }
+}
+
+/// Step `attractor` forward `num_steps` times, writing every new position to `writer` instead of
+/// keeping it in the attractor's history.
+///
+/// Only the most recent position is kept in `history` (so that stepping can carry on from it),
+/// which lets arbitrarily long trajectories be written with a constant amount of memory. Any
+/// positions already in the history are written out first, and every position is written exactly
+/// once, including the most recent one. `visit` is called with each position as it will be read
+/// back from the file (rounded to the writer's precision), so that it can be drawn at the same
+/// time.
+pub fn write_steps<A: Attractor, W: Write>(
+    attractor: &mut A,
+    num_steps: usize,
+    writer: &mut TrajectoryWriter<W>,
+    mut visit: impl FnMut(&[f64]),
+) -> Result<(), AttractorError> {
+    let precision = writer.header.precision;
+    let mut saved = Vec::with_capacity(A::DIMENSIONALITY as usize);
+    let mut write = |pos: &[f64]| -> Result<(), AttractorError> {
+        writer.write_point(pos)?;
+        saved.clear();
+        saved.extend(pos.iter().map(|coord| precision.round(*coord)));
+        visit(&saved);
+        Ok(())
+    };
+    for pos in attractor.history() {
+        write(pos)?;
+    }
+    let mut result = Ok(());
+    attractor.step_with(num_steps, |pos| {
+        if result.is_ok() {
+            result = write(pos);
+        }
+    });
+    if let Some(last) = attractor.history().last().cloned() {
+        attractor.set_history(vec![last]);
+    }
+    result
+}
+
+/// Step `attractor` forward `num_steps` times, streaming its positions to a new binary trajectory
+/// file with `write_steps` (which calls `visit` with each position as it was saved).
+pub fn save_steps<A: Attractor>(
+    attractor: &mut A,
+    num_steps: usize,
+    filename: &str,
+    precision: Precision,
+    compressed: bool,
+    visit: impl FnMut(&[f64]),
+) -> Result<(), AttractorError> {
+    let header = Header {
+        name: A::NAME.to_string(),
+        params: attractor.params(),
+        code: attractor.code(),
+        dimensionality: A::DIMENSIONALITY,
+        precision,
+        compressed,
+    };
+    let mut writer = TrajectoryWriter::new(BufWriter::new(File::create(filename)?), header)?;
+    write_steps(attractor, num_steps, &mut writer, visit)?;
+    writer.finish()?;
+    Ok(())
+}
+
+/// Load an attractor (both it's parameters and it's history) from a binary trajectory file.
+///
+/// Returns an error if the header doesn't match the attractor's `NAME`, `NUM_PARAMETERS`, and
+/// `DIMENSIONALITY`.
+pub fn load<A: Attractor>(filename: &str) -> Result<A, AttractorError> {
+    let (header, history) = read(filename)?;
+    if header.name != A::NAME || header.dimensionality != A::DIMENSIONALITY {
+        return Err(AttractorError::InvalidBinaryFile(format!(
+            "expected a {}-dimensional {} attractor but found a {}-dimensional {} attractor",
+            A::DIMENSIONALITY,
+            A::NAME,
+            header.dimensionality,
+            header.name
+        )));
+    }
+    let mut attractor = A::new(header.params)?;
+    attractor.set_history(history);
+    Ok(attractor)
+}
+
+/// Read the header and every position from a binary trajectory file.
+pub fn read(filename: &str) -> Result<(Header, Vec<Vec<f64>>), AttractorError> {
+    let reader = TrajectoryReader::new(BufReader::new(File::open(filename)?))?;
+    let header = reader.header.clone();
+    Ok((header, reader.collect::<Result<Vec<_>, _>>()?))
+}
+
+/// True if `filename` starts like a binary trajectory file, rather than a text one written by
+/// `Attractor::to_file`.
+pub fn is_binary(filename: &str) -> Result<bool, AttractorError> {
+    let mut magic = vec![];
+    File::open(filename)?
+        .take(MAGIC.len() as u64)
+        .read_to_end(&mut magic)?;
+    Ok(magic == MAGIC)
+}
+
+/// The `NAME` of the attractor whose trajectory was saved to `filename`, in either format.
+pub fn saved_name(filename: &str) -> Result<String, AttractorError> {
+    if !is_binary(filename)? {
+        return saved_attractor_name(filename);
+    }
+    let reader = TrajectoryReader::new(BufReader::new(File::open(filename)?))?;
+    Ok(reader.header.name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attractors::{CliffordAttractor, SprottAttractor};
+    use std::io::Cursor;
+
+    #[test]
+    fn round_trip_every_precision_and_compression() {
+        let mut clifford = CliffordAttractor::new(vec![-1.4, 1.6, 1.0, 0.7]).unwrap();
+        clifford.step(100_000);
+        for (precision, compressed) in [
+            (Precision::F64, false),
+            (Precision::F64, true),
+            (Precision::F32, false),
+            (Precision::F32, true),
+        ] {
+            let header = Header {
+                name: CliffordAttractor::NAME.to_string(),
+                params: clifford.params(),
+                code: None,
+                dimensionality: 2,
+                precision,
+                compressed,
+            };
+            let mut writer = TrajectoryWriter::new(vec![], header.clone()).unwrap();
+            for pos in clifford.history.iter() {
+                writer.write_point(pos).unwrap();
+            }
+            let bytes = writer.finish().unwrap();
+
+            let reader = TrajectoryReader::new(Cursor::new(bytes)).unwrap();
+            assert_eq!(reader.header, header);
+            let points: Vec<Vec<f64>> = reader.map(|p| p.unwrap()).collect();
+            assert_eq!(points.len(), clifford.history.len());
+            for (read, orig) in points.iter().zip(clifford.history.iter()) {
+                let tolerance = if precision == Precision::F32 {
+                    1e-6
+                } else {
+                    0.0
+                };
+                assert!((read[0] - orig[0]).abs() <= tolerance);
+                assert!((read[1] - orig[1]).abs() <= tolerance);
+            }
+        }
+    }
+
+    #[test]
+    fn write_steps_streams_without_keeping_history() {
+        let mut streamed = CliffordAttractor::new(vec![-1.4, 1.6, 1.0, 0.7]).unwrap();
+        let header = Header {
+            name: CliffordAttractor::NAME.to_string(),
+            params: streamed.params(),
+            code: None,
+            dimensionality: 2,
+            precision: Precision::F64,
+            compressed: true,
+        };
+        let mut writer = TrajectoryWriter::new(vec![], header).unwrap();
+        let mut visited = vec![];
+        write_steps(&mut streamed, 200_000, &mut writer, |pos| {
+            visited.push(pos.to_vec())
+        })
+        .unwrap();
+        assert_eq!(streamed.history.len(), 1);
+        let bytes = writer.finish().unwrap();
+
+        let points: Vec<Vec<f64>> = TrajectoryReader::new(Cursor::new(bytes))
+            .unwrap()
+            .map(|p| p.unwrap())
+            .collect();
+        // Every point was written once, including the most recent one, and they match a normal run
+        let mut clifford = CliffordAttractor::new(vec![-1.4, 1.6, 1.0, 0.7]).unwrap();
+        clifford.step(200_000);
+        assert_eq!(points, clifford.history);
+        assert_eq!(points.last(), streamed.history.last());
+        assert_eq!(visited, points);
+    }
+
+    #[test]
+    fn codes_are_saved_in_the_header() {
+        let filename = std::env::temp_dir().join("attractors_trajectory_code_test.traj");
+        let filename = filename.to_str().unwrap();
+        let mut sprott = SprottAttractor::from_code("FIRCDERRPVLD").unwrap();
+        save_steps(&mut sprott, 100, filename, Precision::F32, true, |_| {}).unwrap();
+        let (header, points) = read(filename).unwrap();
+        assert_eq!(header.code, Some("FIRCDERRPVLD".to_string()));
+        assert_eq!(points.len(), 101);
+        std::fs::remove_file(filename).unwrap();
+
+        // Files from before codes were saved can still be read
+        let header = Header {
+            name: CliffordAttractor::NAME.to_string(),
+            params: vec![-1.4, 1.6, 1.0, 0.7],
+            code: None,
+            dimensionality: 2,
+            precision: Precision::F64,
+            compressed: false,
+        };
+        let mut writer = TrajectoryWriter::new(vec![], header.clone()).unwrap();
+        writer.write_point(&[0.5, 0.25]).unwrap();
+        let mut bytes = writer.finish().unwrap();
+        bytes[4] = 1;
+        bytes.remove(8 + header.name.len() + 2 + 8 * header.params.len());
+        let reader = TrajectoryReader::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(reader.header, header);
+        let points: Vec<Vec<f64>> = reader.map(|p| p.unwrap()).collect();
+        assert_eq!(points, vec![vec![0.5, 0.25]]);
+    }
+
+    #[test]
+    fn corrupt_chunk_lengths_are_errors() {
+        let header = Header {
+            name: CliffordAttractor::NAME.to_string(),
+            params: vec![-1.4, 1.6, 1.0, 0.7],
+            code: None,
+            dimensionality: 2,
+            precision: Precision::F32,
+            compressed: false,
+        };
+        let start = TrajectoryWriter::new(vec![], header)
+            .unwrap()
+            .finish()
+            .unwrap();
+        for (num_points, num_bytes) in [(1u32, 9u32), (1, u32::MAX), (u32::MAX, 8)] {
+            let mut bytes = start.clone();
+            bytes.extend(num_points.to_le_bytes());
+            bytes.extend(num_bytes.to_le_bytes());
+            bytes.extend([0u8; 8]);
+            let mut reader = TrajectoryReader::new(Cursor::new(bytes)).unwrap();
+            assert!(matches!(
+                reader.next(),
+                Some(Err(AttractorError::InvalidBinaryFile(_)))
+            ));
+        }
+    }
+}